@@ -0,0 +1,341 @@
+use crate::backprop::tape::{Node, TapeEntry};
+use crate::forward::value::Value;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/*
+ * Anomaly detection.
+ *
+ * When enabled, every value produced by a forward-mode operation, every
+ * entry recorded on the tape and every adjoint handed out by a
+ * `TapeEntry::propagate` is checked for NaN and Inf. The first offending
+ * operation stops the computation with an `Anomaly` describing it, instead
+ * of letting non-finite numbers spread silently.
+ *
+ * Detection is off by default and, like the tape, is a per-thread setting.
+ */
+
+thread_local! {
+    static DETECT_ANOMALY: Cell<bool> = const { Cell::new(false) };
+    static LAST_ANOMALY: RefCell<Option<Anomaly>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pass {
+    Forward,
+    Backward,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operand {
+    // Name of the variable, if the operand has one.
+    pub label: Option<String>,
+    // A tensor is summarized by its first non-finite element, if any, and
+    // by its first element otherwise.
+    pub value: f32,
+    // Tangent in forward mode, adjoint in the backward pass.
+    pub der: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub op: String,
+    pub pass: Pass,
+    pub inputs: Vec<Operand>,
+    pub outputs: Vec<Operand>,
+}
+
+pub fn set_detect_anomaly(enabled: bool) {
+    DETECT_ANOMALY.set(enabled);
+}
+
+pub fn is_detecting_anomaly() -> bool {
+    DETECT_ANOMALY.get()
+}
+
+// Runs `f` with anomaly detection enabled and returns the first anomaly
+// it runs into, if any.
+pub fn detect_anomaly<R>(f: impl FnOnce() -> R) -> Result<R, Anomaly> {
+    let enabled = DETECT_ANOMALY.replace(true);
+    // An anomaly reported earlier and never taken must not be blamed for
+    // an unrelated panic in `f`.
+    LAST_ANOMALY.take();
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    DETECT_ANOMALY.set(enabled);
+
+    match result {
+        Ok(result) => Ok(result),
+        Err(payload) => match LAST_ANOMALY.take() {
+            Some(anomaly) => Err(anomaly),
+            None => panic::resume_unwind(payload),
+        },
+    }
+}
+
+fn report(anomaly: Anomaly) -> ! {
+    let message = anomaly.to_string();
+    LAST_ANOMALY.set(Some(anomaly));
+    panic!("{}", message);
+}
+
+fn summary(values: &[f32]) -> f32 {
+    values
+        .iter()
+        .copied()
+        .find(|v| !v.is_finite())
+        .or(values.first().copied())
+        .unwrap_or(0.0)
+}
+
+fn all_finite<N: Node>(nodes: &[N]) -> bool {
+    nodes
+        .iter()
+        .all(|x| x.values().iter().all(|v| v.is_finite()))
+}
+
+fn node_operand<N: Node>(node: &N, der: Option<&N>) -> Operand {
+    Operand {
+        label: Some(node.name().to_string()),
+        value: summary(node.values()),
+        der: der.map(|d| summary(d.values())),
+    }
+}
+
+fn value_operand(value: &Value) -> Operand {
+    Operand {
+        label: None,
+        value: value.value,
+        der: Some(value.der),
+    }
+}
+
+pub(crate) fn check_value(op: &str, operands: &[Value], result: Value) {
+    if !is_detecting_anomaly() || (result.value.is_finite() && result.der.is_finite()) {
+        return;
+    }
+
+    report(Anomaly {
+        op: op.to_string(),
+        pass: Pass::Forward,
+        inputs: operands.iter().map(value_operand).collect(),
+        outputs: vec![value_operand(&result)],
+    });
+}
+
+pub(crate) fn check_entry<N: Node>(entry: &TapeEntry<N>) {
+    if !is_detecting_anomaly() || all_finite(&entry.outputs) {
+        return;
+    }
+
+    report(Anomaly {
+        op: entry.op.to_string(),
+        pass: Pass::Forward,
+        inputs: entry.inputs.iter().map(|x| node_operand(x, None)).collect(),
+        outputs: entry
+            .outputs
+            .iter()
+            .map(|x| node_operand(x, None))
+            .collect(),
+    });
+}
+
+pub(crate) fn check_adjoints<N: Node>(
+    entry: &TapeEntry<N>,
+    dloss_doutputs: &[Option<N>],
+    dloss_dinputs: &[N],
+) {
+    if !is_detecting_anomaly() || all_finite(dloss_dinputs) {
+        return;
+    }
+
+    report(Anomaly {
+        op: entry.op.to_string(),
+        pass: Pass::Backward,
+        inputs: entry
+            .inputs
+            .iter()
+            .zip(dloss_dinputs)
+            .map(|(x, dloss_dx)| node_operand(x, Some(dloss_dx)))
+            .collect(),
+        outputs: entry
+            .outputs
+            .iter()
+            .zip(dloss_doutputs)
+            .map(|(x, dloss_dx)| node_operand(x, dloss_dx.as_ref()))
+            .collect(),
+    });
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{} = {}", label, self.value)?,
+            None => write!(f, "{}", self.value)?,
+        }
+        match self.der {
+            Some(der) => write!(f, " (d = {})", der),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn join(operands: &[Operand]) -> String {
+            operands
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+
+        let pass = match self.pass {
+            Pass::Forward => "forward",
+            Pass::Backward => "backward",
+        };
+        write!(
+            f,
+            "non-finite value in the {} pass of `{}`: inputs [{}], outputs [{}]",
+            pass,
+            self.op,
+            join(&self.inputs),
+            join(&self.outputs)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backprop::grad::grad;
+    use crate::backprop::variable::Variable;
+
+    #[test]
+    fn test_disabled_by_default() {
+        let x = Value::new(-1.0, 1.0).sqrt();
+        assert!(x.value.is_nan());
+    }
+
+    #[test]
+    fn test_value_div_by_zero() {
+        let anomaly = detect_anomaly(|| Value::new(1.0, 1.0) / Value::passive(0.0)).unwrap_err();
+
+        assert_eq!(anomaly.op, "div");
+        assert_eq!(anomaly.pass, Pass::Forward);
+        assert_eq!(anomaly.inputs[1].value, 0.0);
+        assert!(!is_detecting_anomaly());
+    }
+
+    #[test]
+    fn test_value_sqrt_of_negative() {
+        let anomaly = detect_anomaly(|| {
+            let x = Value::new(2.0, 1.0);
+            let y = x * Value::passive(-1.0);
+            y.sqrt()
+        })
+        .unwrap_err();
+
+        assert_eq!(anomaly.op, "sqrt");
+        assert_eq!(anomaly.inputs[0].value, -2.0);
+    }
+
+    #[test]
+    fn test_value_pow_at_zero() {
+        let anomaly = detect_anomaly(|| Value::new(0.0, 1.0).pow(0.5)).unwrap_err();
+
+        assert_eq!(anomaly.op, "pow");
+        assert_eq!(anomaly.outputs[0].value, 0.0);
+        assert_eq!(anomaly.outputs[0].der, Some(f32::INFINITY));
+    }
+
+    #[test]
+    fn test_finite_computation_passes() {
+        let y = detect_anomaly(|| Value::new(4.0, 1.0).sqrt()).unwrap();
+        assert_eq!(y.value, 2.0);
+    }
+
+    #[test]
+    fn test_variable_div_by_zero() {
+        let anomaly = detect_anomaly(|| {
+            let a = Variable::new(1.0, Some("a".to_string()));
+            let b = Variable::new(0.0, Some("b".to_string()));
+            a / b
+        })
+        .unwrap_err();
+
+        assert_eq!(anomaly.op, "div");
+        assert_eq!(anomaly.pass, Pass::Forward);
+        assert_eq!(anomaly.inputs[0].label, Some("a".to_string()));
+        assert_eq!(anomaly.inputs[1].label, Some("b".to_string()));
+        assert_eq!(anomaly.outputs[0].value, f32::INFINITY);
+    }
+
+    #[test]
+    fn test_variable_backward_overflow() {
+        // a / b is finite, but its adjoint -a / b^2 is not.
+        let a = Variable::new(1e-25, Some("a".to_string()));
+        let b = Variable::new(1e-25, Some("b".to_string()));
+        let loss = a.clone() / b.clone();
+
+        let anomaly = detect_anomaly(|| grad(&loss, &[a, b])).unwrap_err();
+
+        assert_eq!(anomaly.op, "div");
+        assert_eq!(anomaly.pass, Pass::Backward);
+        assert_eq!(anomaly.inputs[1].label, Some("b".to_string()));
+        assert_eq!(anomaly.inputs[1].der, Some(f32::NEG_INFINITY));
+    }
+
+    #[test]
+    fn test_first_anomaly_is_reported() {
+        let anomaly = detect_anomaly(|| {
+            let a = Variable::new(0.0, Some("a".to_string()));
+            let nan = a.clone() / a.clone();
+            nan.clone() * nan
+        })
+        .unwrap_err();
+
+        assert_eq!(anomaly.op, "div");
+    }
+
+    #[test]
+    fn test_unrelated_panic_after_stale_anomaly() {
+        set_detect_anomaly(true);
+        let stale = panic::catch_unwind(|| Value::new(1.0, 1.0) / Value::passive(0.0));
+        set_detect_anomaly(false);
+        assert!(stale.is_err());
+
+        let payload = panic::catch_unwind(|| detect_anomaly(|| panic!("unrelated"))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"unrelated"));
+    }
+
+    #[test]
+    fn test_anomaly_message() {
+        let anomaly = Anomaly {
+            op: "div".to_string(),
+            pass: Pass::Forward,
+            inputs: vec![
+                Operand {
+                    label: Some("a".to_string()),
+                    value: 1.0,
+                    der: None,
+                },
+                Operand {
+                    label: None,
+                    value: 0.0,
+                    der: Some(1.0),
+                },
+            ],
+            outputs: vec![Operand {
+                label: Some("v0".to_string()),
+                value: f32::INFINITY,
+                der: None,
+            }],
+        };
+
+        assert_eq!(
+            anomaly.to_string(),
+            "non-finite value in the forward pass of `div`: inputs [a = 1, 0 (d = 1)], outputs [v0 = inf]"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::backprop::grad::grad;
+use crate::backprop::tape::scoped;
+use crate::backprop::tensor::Tensor;
+use crate::backprop::variable::Variable;
+use crate::forward::value::Value;
+use crate::scalar::{Function, VectorFunction};
+use ndarray::{Array, Array1, Array2, ArrayBase, ArrayD, Data, Dimension, IxDyn, ScalarOperand};
+
+/*
+ * Interop with `ndarray`, behind the `ndarray` feature.
+ *
+ * `Value` is a plain `Copy` number, so `Array<Value, D>` already supports
+ * ndarray's elementwise arithmetic, `sum`, `mapv` and, through `Zero` and
+ * `One`, `dot`; scalar operands need the marker below. Reverse-mode
+ * variables and tensors are converted to and from arrays of `f32`.
+ */
+
+impl ScalarOperand for Value {}
+
+// An array of passive values, ready to have some tangents seeded.
+pub fn passive<S, D>(a: &ArrayBase<S, D>) -> Array<Value, D>
+where
+    S: Data<Elem = f32>,
+    D: Dimension,
+{
+    a.mapv(Value::passive)
+}
+
+// The primal values and the tangents of an array of `Value`s.
+pub fn split<S, D>(a: &ArrayBase<S, D>) -> (Array<f32, D>, Array<f32, D>)
+where
+    S: Data<Elem = Value>,
+    D: Dimension,
+{
+    (a.mapv(|x| x.value), a.mapv(|x| x.der))
+}
+
+// One fresh variable per element, on the current tape.
+pub fn variables<S, D>(a: &ArrayBase<S, D>) -> Array<Variable, D>
+where
+    S: Data<Elem = f32>,
+    D: Dimension,
+{
+    a.map(|&x| Variable::new(x, None))
+}
+
+pub fn values<S, D>(a: &ArrayBase<S, D>) -> Array<f32, D>
+where
+    S: Data<Elem = Variable>,
+    D: Dimension,
+{
+    a.map(|x| x.value)
+}
+
+impl<S, D> From<&ArrayBase<S, D>> for Tensor
+where
+    S: Data<Elem = f32>,
+    D: Dimension,
+{
+    fn from(a: &ArrayBase<S, D>) -> Self {
+        Tensor::new(a.shape(), a.iter().copied().collect(), None)
+    }
+}
+
+impl Tensor {
+    pub fn to_array(&self) -> ArrayD<f32> {
+        Array::from_shape_vec(IxDyn(&self.shape), self.data.to_vec()).unwrap()
+    }
+}
+
+// Gradient of `f` at `x`, by one reverse sweep on a scratch tape.
+pub fn gradient<F: Function, S: Data<Elem = f32>>(
+    f: &F,
+    x: &ArrayBase<S, ndarray::Ix1>,
+) -> Array1<f32> {
+    let (dy_dx, _) = scoped(|| {
+        let x: Vec<Variable> = x.iter().map(|&x| Variable::new(x, None)).collect();
+        let y = f.eval(&x);
+        grad(&y, &x)
+    });
+    dy_dx
+        .into_iter()
+        .map(|d| d.map_or(0.0, |d| d.value))
+        .collect()
+}
+
+// Jacobian of `f` at `x`, one row per output, by one forward pass per input.
+pub fn jacobian<F: VectorFunction, S: Data<Elem = f32>>(
+    f: &F,
+    x: &ArrayBase<S, ndarray::Ix1>,
+) -> Array2<f32> {
+    let n = x.len();
+    let mut columns = Vec::with_capacity(n);
+    for j in 0..n {
+        let seeded: Vec<Value> = x
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| Value::new(x, if i == j { 1.0 } else { 0.0 }))
+            .collect();
+        columns.push(f.eval(&seeded));
+    }
+
+    let m = columns.first().map_or(0, Vec::len);
+    Array2::from_shape_fn((m, n), |(i, j)| columns[j][i].der)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+    use ndarray::{array, Axis};
+
+    struct Rosenbrock;
+
+    impl Function for Rosenbrock {
+        fn eval<T: Scalar>(&self, x: &[T]) -> T {
+            let a = T::constant(1.0) - x[0].clone();
+            let b = x[1].clone() - x[0].clone() * x[0].clone();
+            a.clone() * a + T::constant(100.0) * b.clone() * b
+        }
+    }
+
+    struct Ratios;
+
+    impl VectorFunction for Ratios {
+        fn eval<T: Scalar>(&self, x: &[T]) -> Vec<T> {
+            vec![
+                x[0].clone() * x[1].clone(),
+                x[0].clone() / x[1].clone(),
+                x[1].clone().pow(2.0),
+            ]
+        }
+    }
+
+    #[test]
+    fn test_forward_arithmetic() {
+        let x = array![[1.0, 2.0], [3.0, 4.0]];
+        let mut v = passive(&x);
+        v[[0, 1]] = Value::new(2.0, 1.0);
+
+        // sum(2 X X) = 108; along x01 its derivative is 2 times the sum of
+        // row 1 and column 0, 2 (7 + 4) = 22.
+        let y = (&v * Value::passive(2.0)).dot(&v).sum();
+        assert_eq!(y.value, 108.0);
+        assert_eq!(y.der, 22.0);
+
+        let (values, ders) = split(&v.sum_axis(Axis(0)));
+        assert_eq!(values, array![4.0, 6.0]);
+        assert_eq!(ders, array![0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_variables() {
+        let x = array![1.0, -2.0, 3.0];
+        let v = variables(&x);
+        let loss = v
+            .iter()
+            .cloned()
+            .fold(Variable::new(0.0, None), |acc, x| acc + x.clone() * x);
+
+        assert_eq!(values(&v), x);
+        assert_eq!(loss.value, 14.0);
+    }
+
+    #[test]
+    fn test_tensor_round_trip() {
+        let a = array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let t = Tensor::from(&a);
+
+        assert_eq!(t.shape, [2, 3]);
+        assert_eq!(t.get(&[1, 0]), 4.0);
+        assert_eq!(t.to_array(), a.into_dyn());
+
+        // Views iterate in logical order, so a transposed view converts
+        // to the transposed tensor.
+        let t = Tensor::from(&array![[1.0, 2.0], [3.0, 4.0]].t());
+        assert_eq!(*t.data, [1.0, 3.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let g = gradient(&Rosenbrock, &array![1.5, 2.0]);
+
+        // -2 (1 - x0) - 400 x0 (x1 - x0^2) and 200 (x1 - x0^2).
+        assert!((g[0] - 151.0).abs() < 1e-3);
+        assert!((g[1] + 50.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let j = jacobian(&Ratios, &array![3.0, 2.0]);
+
+        assert_eq!(j, array![[2.0, 3.0], [0.5, -0.75], [0.0, 4.0]]);
+    }
+}
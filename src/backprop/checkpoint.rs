@@ -0,0 +1,303 @@
+use super::globals::GRADIENT_TAPE;
+use super::grad::backpropagate;
+use super::tape::{scoped, TapeEntry};
+use super::variable::Variable;
+use std::collections::HashMap;
+
+/*
+ * Gradient checkpointing.
+ *
+ * A checkpointed segment leaves a single entry on the tape, holding only
+ * the segment's inputs and outputs. Its own sub-tape is dropped right after
+ * the forward pass and rebuilt from the stored inputs once the backward
+ * pass reaches the segment, trading recomputation for memory.
+ *
+ * Only `inputs` are differentiated through the segment. A `Variable` the
+ * closure captures is recomputed with it but treated as a constant: it
+ * gets no gradient from the segment, and nothing reports that. Pass every
+ * variable that needs a gradient in `inputs`.
+ */
+
+pub fn checkpoint<F>(inputs: &[Variable], segment: F) -> Vec<Variable>
+where
+    F: Fn(&[Variable]) -> Vec<Variable> + Send + Sync + Clone + 'static,
+{
+    let (results, _) = scoped(|| segment(inputs));
+
+    // Fresh outputs, so that a segment handing back one of its inputs
+    // does not alias the input on the tape.
+    let outputs: Vec<Variable> = results
+        .iter()
+        .map(|result| Variable::new(result.value, None))
+        .collect();
+    println!(
+        "{:?} = checkpoint({:?})",
+        outputs.iter().map(|v| &v.name).collect::<Vec<_>>(),
+        inputs.iter().map(|v| &v.name).collect::<Vec<_>>()
+    );
+
+    let saved_inputs = inputs.to_vec();
+    let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
+        let (recomputed, sub_tape) = scoped(|| segment(&saved_inputs));
+
+        let mut seeds: HashMap<String, Variable> = HashMap::new();
+        for (output, dloss_doutput) in recomputed.iter().zip(dloss_doutputs) {
+            if let Some(dloss_doutput) = dloss_doutput {
+                seeds
+                    .entry(output.name.clone())
+                    .and_modify(|seed| seed.value += dloss_doutput.value)
+                    .or_insert_with(|| dloss_doutput.clone());
+            }
+        }
+
+        let dloss_d = backpropagate(&sub_tape.entries, seeds);
+
+        // The sub-tape yields the total adjoint of every input, so a
+        // variable passed in several times is credited only once.
+        let mut dloss_dinputs = Vec::with_capacity(saved_inputs.len());
+        for (i, input) in saved_inputs.iter().enumerate() {
+            let first = saved_inputs.iter().position(|x| x.name == input.name) == Some(i);
+            let dloss_dinput = match dloss_d.get(&input.name) {
+                Some(dloss_dinput) if first => dloss_dinput.value,
+                _ => 0.0,
+            };
+            dloss_dinputs.push(Variable::new(dloss_dinput, None));
+        }
+        dloss_dinputs
+    };
+
+    let tape_entry = TapeEntry::new(
+        "checkpoint",
+        inputs.to_vec(),
+        outputs.clone(),
+        Box::new(propagate),
+    );
+    GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
+
+    outputs
+}
+
+/*
+ * Binomial (revolve-style) checkpointing for loops.
+ *
+ * With `s` snapshots and every step recomputed at most `r` times, at most
+ * beta(s, r) = C(s + r, s) steps can be reversed. Splitting `l` steps as
+ * beta(s, r - 1) + beta(s - 1, r) leaves the left part to be reversed with
+ * all snapshots and one repetition fewer, and the right part with one
+ * snapshot fewer.
+ */
+
+fn beta(snapshots: usize, repetitions: usize) -> usize {
+    // C(s + r, s), built incrementally to stay within integers.
+    let mut value = 1;
+    for i in 1..=snapshots {
+        value = value * (repetitions + i) / i;
+    }
+    value
+}
+
+// Number of steps to advance from the current snapshot before storing the
+// next one, when `steps` steps remain and `snapshots` snapshots are free.
+pub fn binomial_split(steps: usize, snapshots: usize) -> usize {
+    if steps <= 1 || snapshots == 0 {
+        return steps;
+    }
+
+    let mut repetitions = 1;
+    while beta(snapshots, repetitions) < steps {
+        repetitions += 1;
+    }
+    beta(snapshots, repetitions - 1).clamp(1, steps - 1)
+}
+
+// Steps at which the forward sweep of a `steps`-long loop stores a
+// snapshot when `snapshots` of them may be held at once.
+pub fn revolve_schedule(steps: usize, snapshots: usize) -> Vec<usize> {
+    let mut schedule = Vec::new();
+    let mut position = 0;
+    let mut remaining = steps;
+    let mut snapshots = snapshots;
+    while remaining > 1 && snapshots > 0 {
+        schedule.push(position);
+        let advance = binomial_split(remaining, snapshots);
+        position += advance;
+        remaining -= advance;
+        snapshots -= 1;
+    }
+    schedule
+}
+
+// Applies `step` to `state` `steps` times, checkpointing the loop
+// according to `revolve_schedule`.
+pub fn checkpointed_loop<F>(
+    state: &[Variable],
+    steps: usize,
+    snapshots: usize,
+    step: F,
+) -> Vec<Variable>
+where
+    F: Fn(&[Variable]) -> Vec<Variable> + Send + Sync + Clone + 'static,
+{
+    let mut state = state.to_vec();
+    let mut remaining = steps;
+    let mut snapshots = snapshots;
+    while remaining > 0 {
+        if remaining == 1 || snapshots == 0 {
+            state = step(&state);
+            remaining -= 1;
+            continue;
+        }
+
+        let advance = binomial_split(remaining, snapshots);
+        let free = snapshots;
+        let segment_step = step.clone();
+        state = checkpoint(&state, move |x| {
+            checkpointed_loop(x, advance, free, segment_step.clone())
+        });
+        remaining -= advance;
+        snapshots -= 1;
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grad::grad;
+    use super::*;
+
+    fn logistic_step(x: &[Variable]) -> Vec<Variable> {
+        // x <- x + 0.1 * x * (1 - x)
+        let rate = Variable::new(0.1, None);
+        let one = Variable::new(1.0, None);
+        vec![x[0].clone() + rate * x[0].clone() * (one - x[0].clone())]
+    }
+
+    #[test]
+    fn test_checkpoint_matches_plain() {
+        let a = Variable::new(3.0, Some("a".to_string()));
+        let b = Variable::new(2.0, Some("b".to_string()));
+        let segment =
+            |x: &[Variable]| vec![x[0].clone() * x[1].clone() / x[1].clone() * x[0].clone()];
+
+        let plain = segment(&[a.clone(), b.clone()])[0].clone() * b.clone();
+        let dplain_d = grad(&plain, &[a.clone(), b.clone()]);
+
+        let checkpointed = checkpoint(&[a.clone(), b.clone()], segment)[0].clone() * b.clone();
+        let dcheckpointed_d = grad(&checkpointed, &[a, b]);
+
+        assert_eq!(plain.value, checkpointed.value);
+        for (plain, checkpointed) in dplain_d.iter().zip(&dcheckpointed_d) {
+            assert_eq!(
+                plain.as_ref().unwrap().value,
+                checkpointed.as_ref().unwrap().value
+            );
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_records_single_entry() {
+        let a = Variable::new(3.0, None);
+        let b = Variable::new(2.0, None);
+        let (_, tape) = scoped(|| {
+            checkpoint(&[a, b], |x| {
+                vec![x[0].clone() * x[1].clone() + x[0].clone() - x[1].clone()]
+            })
+        });
+
+        assert_eq!(tape.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_checkpoint_partial_outputs() {
+        let a = Variable::new(3.0, Some("a".to_string()));
+        let b = Variable::new(2.0, Some("b".to_string()));
+        let outputs = checkpoint(&[a.clone(), b.clone()], |x| {
+            vec![x[0].clone() * x[1].clone(), x[0].clone() + x[1].clone()]
+        });
+
+        let dloss_d = grad(&outputs[0], &[a, b]);
+        assert_eq!(dloss_d[0].as_ref().unwrap().value, 2.0);
+        assert_eq!(dloss_d[1].as_ref().unwrap().value, 3.0);
+    }
+
+    #[test]
+    fn test_checkpoint_identity_segment() {
+        let a = Variable::new(3.0, Some("a".to_string()));
+        let outputs = checkpoint(&[a.clone(), a.clone()], |x| vec![x[0].clone()]);
+        let loss = outputs[0].clone() * a.clone();
+
+        let dloss_d = grad(&loss, &[a]);
+        assert_eq!(dloss_d[0].as_ref().unwrap().value, 6.0);
+    }
+
+    #[test]
+    fn test_checkpoint_captured_variable_is_constant() {
+        let a = Variable::new(3.0, Some("a".to_string()));
+        let w = Variable::new(2.0, Some("w".to_string()));
+        let captured = w.clone();
+        let outputs = checkpoint(std::slice::from_ref(&a), move |x| {
+            vec![x[0].clone() * captured.clone()]
+        });
+
+        let dloss_d = grad(&outputs[0], &[a, w]);
+        assert_eq!(dloss_d[0].as_ref().unwrap().value, 2.0);
+        assert!(dloss_d[1].is_none());
+    }
+
+    #[test]
+    fn test_binomial_split() {
+        assert_eq!(binomial_split(10, 3), 4);
+        assert_eq!(binomial_split(6, 2), 3);
+        assert_eq!(binomial_split(3, 1), 2);
+        assert_eq!(binomial_split(1, 3), 1);
+        assert_eq!(binomial_split(5, 0), 5);
+    }
+
+    #[test]
+    fn test_revolve_schedule() {
+        assert_eq!(revolve_schedule(10, 3), vec![0, 4, 7]);
+        assert_eq!(revolve_schedule(4, 1), vec![0]);
+        assert_eq!(revolve_schedule(1, 3), Vec::<usize>::new());
+        assert_eq!(revolve_schedule(8, 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_checkpointed_loop_matches_plain() {
+        let x0 = Variable::new(0.2, Some("x0".to_string()));
+
+        let mut state = vec![x0.clone()];
+        for _ in 0..20 {
+            state = logistic_step(&state);
+        }
+        let dplain = grad(&state[0], std::slice::from_ref(&x0))[0]
+            .clone()
+            .unwrap();
+
+        let checkpointed = checkpointed_loop(std::slice::from_ref(&x0), 20, 3, logistic_step);
+        let dcheckpointed = grad(&checkpointed[0], &[x0])[0].clone().unwrap();
+
+        assert!((state[0].value - checkpointed[0].value).abs() < 1e-6);
+        assert!((dplain.value - dcheckpointed.value).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_checkpointed_loop_tape_size() {
+        let x0 = Variable::new(0.2, None);
+        let (_, plain) = scoped(|| {
+            let mut state = vec![x0.clone()];
+            for _ in 0..20 {
+                state = logistic_step(&state);
+            }
+            state
+        });
+        let (_, checkpointed) =
+            scoped(|| checkpointed_loop(std::slice::from_ref(&x0), 20, 3, logistic_step));
+
+        // One entry per snapshot plus the final step, taped as usual.
+        assert_eq!(plain.entries.len(), 20 * 4);
+        assert_eq!(
+            checkpointed.entries.len(),
+            revolve_schedule(20, 3).len() + 4
+        );
+    }
+}
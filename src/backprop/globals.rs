@@ -1,8 +1,14 @@
 use super::tape::GradientTape;
-use once_cell::sync::Lazy;
-use std::sync::atomic::AtomicUsize;
-use std::sync::{Arc, Mutex};
+use super::tensor::Tensor;
+use std::cell::{Cell, RefCell};
 
-pub static NAME_IDX: AtomicUsize = AtomicUsize::new(0); // AtomicUsize is thread-safe
-pub static GRADIENT_TAPE: Lazy<Arc<Mutex<GradientTape>>> =
-    Lazy::new(|| Arc::new(Mutex::new(GradientTape::new())));
+// Each thread records onto its own tapes with their own name counters, so
+// independent computations (e.g. tests running in parallel) never
+// interleave their entries. Variables and tensors are counted separately,
+// so clearing one tape cannot reuse names still recorded on the other.
+thread_local! {
+    pub static NAME_IDX: Cell<usize> = const { Cell::new(0) };
+    pub static TENSOR_NAME_IDX: Cell<usize> = const { Cell::new(0) };
+    pub static GRADIENT_TAPE: RefCell<GradientTape> = const { RefCell::new(GradientTape::new()) };
+    pub static TENSOR_TAPE: RefCell<GradientTape<Tensor>> = const { RefCell::new(GradientTape::new()) };
+}
@@ -1,52 +1,59 @@
-use super::globals::GRADIENT_TAPE;
-use super::variable::Variable;
+use super::tape::{Node, TapeEntry};
+use crate::anomaly;
 use std::collections::HashMap;
 
-fn grad(loss: &Variable, desired_results: &Vec<Variable>) -> Vec<Option<Variable>> {
-    let mut dloss_d = HashMap::new();
-    dloss_d.insert(loss.name.clone(), Variable::new(1.0, None));
-
-    fn gather_grad(
-        entries: &Vec<Variable>,
-        dloss_d: &HashMap<String, Variable>,
-    ) -> Vec<Option<Variable>> {
-        entries
-            .iter()
-            .map(|entry| {
-                if let Some(dloss_dentry) = dloss_d.get(&entry.name) {
-                    Some(dloss_dentry.clone())
-                } else {
-                    None
-                }
-            })
-            .collect()
-    }
-
-    let entries = {
-        let tape = GRADIENT_TAPE.lock().unwrap();
-        tape.entries.clone()
-    };
+fn gather_grad<N: Node>(entries: &[N], dloss_d: &HashMap<String, N>) -> Vec<Option<N>> {
+    entries
+        .iter()
+        .map(|entry| dloss_d.get(entry.name()).cloned())
+        .collect()
+}
 
-    println!("d{}:\n-----------", loss.name);
-    for entry in &entries {
+/*
+ * Sweeps the entries from the most recent to the oldest, so every entry
+ * sees the complete adjoint of its outputs before propagating it to its
+ * inputs. `dloss_d` holds the seeds on the way in and all accumulated
+ * adjoints on the way out.
+ */
+pub(crate) fn backpropagate<N: Node>(
+    entries: &[TapeEntry<N>],
+    mut dloss_d: HashMap<String, N>,
+) -> HashMap<String, N> {
+    for entry in entries.iter().rev() {
         let dloss_doutputs = gather_grad(&entry.outputs, &dloss_d);
         if dloss_doutputs.iter().all(|x| x.is_none()) {
             continue;
         }
 
         let dloss_dinputs = (entry.propagate)(&dloss_doutputs);
+        anomaly::check_adjoints(entry, &dloss_doutputs, &dloss_dinputs);
         for (i, input) in entry.inputs.iter().enumerate() {
             let dloss_dinput = dloss_dinputs.get(i);
-            if dloss_d.contains_key(&input.name) {
-                let current = dloss_d.get_mut(&input.name).unwrap();
-                current.value += dloss_dinput.unwrap().value;
+            if dloss_d.contains_key(input.name()) {
+                let current = dloss_d.get_mut(input.name()).unwrap();
+                current.accumulate(dloss_dinput.unwrap());
             } else {
-                dloss_d.insert(input.name.clone(), dloss_dinput.unwrap().clone());
+                dloss_d.insert(input.name().to_string(), dloss_dinput.unwrap().clone());
             }
         }
     }
+
+    dloss_d
+}
+
+pub fn grad<N: Node>(loss: &N, desired_results: &[N]) -> Vec<Option<N>> {
+    let mut seeds = HashMap::new();
+    seeds.insert(loss.name().to_string(), loss.seed());
+
+    let entries = N::tape().with_borrow(|tape| tape.entries.clone());
+
+    println!("d{}:\n-----------", loss.name());
+    let dloss_d = backpropagate(&entries, seeds);
     for (name, value) in &dloss_d {
-        println!("d{}_d{} = {}", loss.name, name, value.value);
+        match value.values() {
+            [value] => println!("d{}_d{} = {}", loss.name(), name, value),
+            values => println!("d{}_d{} = {:?}", loss.name(), name, values),
+        }
     }
     println!("-----------");
 
@@ -55,6 +62,7 @@ fn grad(loss: &Variable, desired_results: &Vec<Variable>) -> Vec<Option<Variable
 
 #[cfg(test)]
 mod tests {
+    use super::super::variable::Variable;
     use super::*;
 
     #[test]
@@ -62,8 +70,8 @@ mod tests {
         let a = Variable::new(3.0, Some('a'.to_string()));
         let b = Variable::new(2.0, Some('b'.to_string()));
         let loss = a.clone() + b.clone();
-        let dloss_d = grad(&loss, &vec![a, b]);
-        let dloss_da = dloss_d.get(0).unwrap().clone().unwrap();
+        let dloss_d = grad(&loss, &[a, b]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
         let dloss_db = dloss_d.get(1).unwrap().clone().unwrap();
         assert_eq!(dloss_da.value, 1.0);
         assert_eq!(dloss_db.value, 1.0);
@@ -74,8 +82,8 @@ mod tests {
         let a = Variable::new(3.0, Some('a'.to_string()));
         let b = Variable::new(2.0, Some('b'.to_string()));
         let loss = a.clone() - b.clone();
-        let dloss_d = grad(&loss, &vec![a, b]);
-        let dloss_da = dloss_d.get(0).unwrap().clone().unwrap();
+        let dloss_d = grad(&loss, &[a, b]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
         let dloss_db = dloss_d.get(1).unwrap().clone().unwrap();
         assert_eq!(dloss_da.value, 1.0);
         assert_eq!(dloss_db.value, -1.0);
@@ -86,8 +94,8 @@ mod tests {
         let a = Variable::new(3.0, Some('a'.to_string()));
         let b = Variable::new(2.0, Some('b'.to_string()));
         let loss = a.clone() * b.clone();
-        let dloss_d = grad(&loss, &vec![a, b]);
-        let dloss_da = dloss_d.get(0).unwrap().clone().unwrap();
+        let dloss_d = grad(&loss, &[a, b]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
         let dloss_db = dloss_d.get(1).unwrap().clone().unwrap();
         assert_eq!(dloss_da.value, 2.0);
         assert_eq!(dloss_db.value, 3.0);
@@ -98,8 +106,8 @@ mod tests {
         let a = Variable::new(3.0, Some('a'.to_string()));
         let b = Variable::new(2.0, Some('b'.to_string()));
         let loss = a.clone() / b.clone();
-        let dloss_d = grad(&loss, &vec![a, b]);
-        let dloss_da = dloss_d.get(0).unwrap().clone().unwrap();
+        let dloss_d = grad(&loss, &[a, b]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
         let dloss_db = dloss_d.get(1).unwrap().clone().unwrap();
         assert_eq!(dloss_da.value, 0.5);
         assert_eq!(dloss_db.value, -0.75);
@@ -109,18 +117,70 @@ mod tests {
     fn test_backprop_simple_neg() {
         let a = Variable::new(3.0, None);
         let loss = -a.clone();
-        let dloss_d = grad(&loss, &vec![a]);
-        let dloss_da = dloss_d.get(0).unwrap().clone().unwrap();
+        let dloss_d = grad(&loss, &[a]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
         assert_eq!(dloss_da.value, -1.0);
     }
 
+    #[test]
+    fn test_backprop_chain() {
+        let a = Variable::new(3.0, Some('a'.to_string()));
+        let b = Variable::new(2.0, Some('b'.to_string()));
+        let loss = a.clone() * b.clone() + a.clone();
+        let dloss_d = grad(&loss, &[a, b]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
+        let dloss_db = dloss_d.get(1).unwrap().clone().unwrap();
+        assert_eq!(dloss_da.value, 3.0);
+        assert_eq!(dloss_db.value, 3.0);
+    }
+
+    #[test]
+    fn test_backprop_kinks_follow_policy() {
+        use crate::subgradient::{with_subgradient, Subgradient};
+
+        fn kinks() -> Vec<f32> {
+            let zero = Variable::new(0.0, None);
+            let a = Variable::new(1.0, None);
+            let b = Variable::new(1.0, None);
+            let cases = [
+                (zero.clone().relu(), &zero),
+                (zero.clone().abs(), &zero),
+                (a.clone().max(b.clone()), &a),
+                (a.clone().min(b.clone()), &a),
+                (a.clone().clamp(1.0, 2.0), &a),
+                (a.clone().clamp(0.0, 1.0), &a),
+                (zero.clone().step(), &zero),
+            ];
+            cases
+                .iter()
+                .map(|(loss, wrt)| {
+                    let dloss_d = grad(loss, std::slice::from_ref(*wrt));
+                    dloss_d[0].as_ref().map_or(0.0, |d| d.value)
+                })
+                .collect()
+        }
+
+        assert_eq!(
+            with_subgradient(Subgradient::Left, kinks),
+            [0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 0.0]
+        );
+        assert_eq!(
+            with_subgradient(Subgradient::Right, kinks),
+            [1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            with_subgradient(Subgradient::Midpoint, kinks),
+            [0.5, 0.0, 0.5, 0.5, 0.5, 0.5, 0.0]
+        );
+    }
+
     #[test]
     fn test_backprop_zero_grad() {
         let a = Variable::new(3.0, Some('a'.to_string()));
         let b = Variable::new(2.0, Some('b'.to_string()));
         let loss = a.clone() * a.clone();
-        let dloss_d = grad(&loss, &vec![a, b]);
-        let dloss_da = dloss_d.get(0).unwrap().clone().unwrap();
+        let dloss_d = grad(&loss, &[a, b]);
+        let dloss_da = dloss_d.first().unwrap().clone().unwrap();
         let dloss_db = dloss_d.get(1);
         assert_eq!(dloss_da.value, 6.0);
         assert_eq!(dloss_db, Some(None).as_ref());
@@ -1,4 +1,8 @@
+pub mod checkpoint;
 pub mod globals;
 pub mod grad;
+pub mod multi;
+pub mod softmax;
 pub mod tape;
+pub mod tensor;
 pub mod variable;
@@ -0,0 +1,339 @@
+use super::globals::GRADIENT_TAPE;
+use super::tape::TapeEntry;
+use super::variable::Variable;
+
+/*
+ * Operations with several outputs.
+ *
+ * Each records a single tape entry. During the backward pass only some of
+ * the outputs may have an adjoint (e.g. when the loss uses the sine but not
+ * the cosine of `sin_cos`); outputs without one contribute nothing.
+ */
+
+// Records `op` given the values of its outputs and the Jacobian of the
+// outputs with respect to the inputs, one row per output.
+fn record(
+    op: &'static str,
+    inputs: Vec<Variable>,
+    values: &[f32],
+    jacobian: Vec<Vec<f32>>,
+) -> Vec<Variable> {
+    let outputs: Vec<Variable> = values.iter().map(|&v| Variable::new(v, None)).collect();
+    println!(
+        "{:?} = {}({:?}) = {:?}",
+        outputs.iter().map(|v| &v.name).collect::<Vec<_>>(),
+        op,
+        inputs.iter().map(|v| &v.name).collect::<Vec<_>>(),
+        values
+    );
+
+    let n_inputs = inputs.len();
+    let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
+        let mut dloss_dinputs = vec![0.0; n_inputs];
+        for (doutput_dinputs, dloss_doutput) in jacobian.iter().zip(dloss_doutputs) {
+            if let Some(dloss_doutput) = dloss_doutput {
+                for (dloss_dinput, doutput_dinput) in dloss_dinputs.iter_mut().zip(doutput_dinputs)
+                {
+                    *dloss_dinput += dloss_doutput.value * doutput_dinput;
+                }
+            }
+        }
+        dloss_dinputs
+            .into_iter()
+            .map(|dloss_dinput| Variable::new(dloss_dinput, None))
+            .collect()
+    };
+
+    let tape_entry = TapeEntry::new(op, inputs, outputs.clone(), Box::new(propagate));
+    GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
+
+    outputs
+}
+
+impl Variable {
+    pub fn sin_cos(self) -> (Variable, Variable) {
+        let (sin, cos) = self.value.sin_cos();
+        let outputs = record(
+            "sin_cos",
+            vec![self],
+            &[sin, cos],
+            vec![vec![cos], vec![-sin]],
+        );
+        (outputs[0].clone(), outputs[1].clone())
+    }
+
+    // Truncated quotient and remainder, like `/` and `%` on integers. The
+    // quotient is piecewise constant, hence has a zero derivative.
+    pub fn div_rem(self, rhs: Variable) -> (Variable, Variable) {
+        let quotient = (self.value / rhs.value).trunc();
+        let remainder = self.value - rhs.value * quotient;
+        let outputs = record(
+            "div_rem",
+            vec![self, rhs],
+            &[quotient, remainder],
+            vec![vec![0.0, 0.0], vec![1.0, -quotient]],
+        );
+        (outputs[0].clone(), outputs[1].clone())
+    }
+
+    // Fractional and integral parts, both with the sign of `self`.
+    pub fn modf(self) -> (Variable, Variable) {
+        let integral = self.value.trunc();
+        let fractional = self.value - integral;
+        let outputs = record(
+            "modf",
+            vec![self],
+            &[fractional, integral],
+            vec![vec![1.0], vec![0.0]],
+        );
+        (outputs[0].clone(), outputs[1].clone())
+    }
+
+    // Splits `self` into shares proportional to `weights`, which must not
+    // be empty or sum to 0.
+    pub fn split(self, weights: &[f32]) -> Vec<Variable> {
+        let total: f32 = weights.iter().sum();
+        assert!(total != 0.0, "split by weights {:?} summing to 0", weights);
+        let shares: Vec<f32> = weights.iter().map(|w| w / total).collect();
+        let values: Vec<f32> = shares.iter().map(|share| self.value * share).collect();
+        let jacobian = shares.iter().map(|&share| vec![share]).collect();
+        record("split", vec![self], &values, jacobian)
+    }
+}
+
+// Largest of `inputs` and its position; ties go to the first occurrence.
+// The index is not differentiable and stays off the tape. Panics if
+// `inputs` is empty.
+pub fn max_with_index(inputs: &[Variable]) -> (Variable, usize) {
+    assert!(!inputs.is_empty(), "max_with_index of no inputs");
+    let mut index = 0;
+    for (i, input) in inputs.iter().enumerate() {
+        if input.value > inputs[index].value {
+            index = i;
+        }
+    }
+
+    let mut doutput_dinputs = vec![0.0; inputs.len()];
+    doutput_dinputs[index] = 1.0;
+    let outputs = record(
+        "max_with_index",
+        inputs.to_vec(),
+        &[inputs[index].value],
+        vec![doutput_dinputs],
+    );
+    (outputs[0].clone(), index)
+}
+
+// Solves the 2x2 system `a x = b` by Cramer's rule. Panics if `a` is
+// singular.
+pub fn solve2(a: [[Variable; 2]; 2], b: [Variable; 2]) -> [Variable; 2] {
+    let [[a00, a01], [a10, a11]] = a.clone().map(|row| row.map(|x| x.value));
+    let [b0, b1] = b.clone().map(|x| x.value);
+
+    let det = a00 * a11 - a01 * a10;
+    assert!(
+        det != 0.0,
+        "solve2 of a singular matrix {:?}",
+        [[a00, a01], [a10, a11]]
+    );
+    let inv = [[a11 / det, -a01 / det], [-a10 / det, a00 / det]];
+    let x = [
+        inv[0][0] * b0 + inv[0][1] * b1,
+        inv[1][0] * b0 + inv[1][1] * b1,
+    ];
+
+    // dx/db = A^-1 and dx_i/dA_kl = -(A^-1)_ik x_l, with the inputs
+    // ordered as a00, a01, a10, a11, b0, b1.
+    let jacobian = (0..2)
+        .map(|i| {
+            vec![
+                -inv[i][0] * x[0],
+                -inv[i][0] * x[1],
+                -inv[i][1] * x[0],
+                -inv[i][1] * x[1],
+                inv[i][0],
+                inv[i][1],
+            ]
+        })
+        .collect();
+
+    let [[a00, a01], [a10, a11]] = a;
+    let [b0, b1] = b;
+    let outputs = record("solve2", vec![a00, a01, a10, a11, b0, b1], &x, jacobian);
+    [outputs[0].clone(), outputs[1].clone()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grad::grad;
+    use super::*;
+
+    fn named(value: f32, name: &str) -> Variable {
+        Variable::new(value, Some(name.to_string()))
+    }
+
+    fn adjoints(loss: &Variable, wrt: &[Variable]) -> Vec<f32> {
+        grad(loss, wrt)
+            .iter()
+            .map(|d| d.as_ref().map_or(0.0, |d| d.value))
+            .collect()
+    }
+
+    #[test]
+    fn test_sin_cos() {
+        let x = named(0.5, "x");
+        let (sin, cos) = x.clone().sin_cos();
+
+        assert_eq!(sin.value, 0.5_f32.sin());
+        assert_eq!(cos.value, 0.5_f32.cos());
+
+        let loss = sin * cos;
+        let dloss_dx = adjoints(&loss, &[x])[0];
+        assert!((dloss_dx - 1.0_f32.cos()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sin_cos_partial_outputs() {
+        let x = named(0.5, "x");
+        let (sin, cos) = x.clone().sin_cos();
+
+        assert_eq!(adjoints(&sin, std::slice::from_ref(&x)), [0.5_f32.cos()]);
+        assert_eq!(adjoints(&cos, &[x]), [-0.5_f32.sin()]);
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let a = named(7.5, "a");
+        let b = named(2.0, "b");
+        let (quotient, remainder) = a.clone().div_rem(b.clone());
+
+        assert_eq!(quotient.value, 3.0);
+        assert_eq!(remainder.value, 1.5);
+        assert_eq!(adjoints(&remainder, &[a.clone(), b.clone()]), [1.0, -3.0]);
+        assert_eq!(adjoints(&quotient, &[a, b]), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_div_rem_negative() {
+        let (quotient, remainder) = named(-7.5, "a").div_rem(named(2.0, "b"));
+
+        assert_eq!(quotient.value, -3.0);
+        assert_eq!(remainder.value, -7.5 % 2.0);
+    }
+
+    #[test]
+    fn test_modf() {
+        let x = named(-2.25, "x");
+        let (fractional, integral) = x.clone().modf();
+
+        assert_eq!(fractional.value, -0.25);
+        assert_eq!(integral.value, -2.0);
+
+        let loss = fractional * integral;
+        assert_eq!(adjoints(&loss, &[x]), [-2.0]);
+    }
+
+    #[test]
+    fn test_split() {
+        let x = named(6.0, "x");
+        let shares = x.clone().split(&[1.0, 2.0, 3.0]);
+
+        assert_eq!(
+            shares.iter().map(|s| s.value).collect::<Vec<_>>(),
+            [1.0, 2.0, 3.0]
+        );
+
+        let loss = shares[0].clone() * shares[2].clone();
+        assert_eq!(adjoints(&loss, std::slice::from_ref(&x)), [1.0]);
+        assert_eq!(adjoints(&shares[1], &[x]), [1.0 / 3.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "summing to 0")]
+    fn test_split_by_zero_total() {
+        named(6.0, "x").split(&[1.0, -1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "summing to 0")]
+    fn test_split_without_weights() {
+        named(6.0, "x").split(&[]);
+    }
+
+    #[test]
+    fn test_max_with_index() {
+        let x = [
+            named(1.0, "x0"),
+            named(4.0, "x1"),
+            named(4.0, "x2"),
+            named(-2.0, "x3"),
+        ];
+        let (max, index) = max_with_index(&x);
+
+        assert_eq!(max.value, 4.0);
+        assert_eq!(index, 1);
+
+        let loss = max.clone() * max;
+        assert_eq!(adjoints(&loss, &x), [0.0, 8.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "max_with_index of no inputs")]
+    fn test_max_with_index_empty() {
+        max_with_index(&[]);
+    }
+
+    #[test]
+    fn test_solve2() {
+        // [2 1; 1 3] x = [3 5] => x = [0.8 1.4]
+        let a = [
+            [named(2.0, "a00"), named(1.0, "a01")],
+            [named(1.0, "a10"), named(3.0, "a11")],
+        ];
+        let b = [named(3.0, "b0"), named(5.0, "b1")];
+        let x = solve2(a.clone(), b.clone());
+
+        assert!((x[0].value - 0.8).abs() < 1e-6);
+        assert!((x[1].value - 1.4).abs() < 1e-6);
+
+        // Only x0 feeds the loss: the adjoint of b is the first row of A^-1
+        // and the adjoint of A is -(A^-T e0) x^T.
+        let wrt = [
+            a[0][0].clone(),
+            a[0][1].clone(),
+            a[1][0].clone(),
+            a[1][1].clone(),
+            b[0].clone(),
+            b[1].clone(),
+        ];
+        let dx0 = adjoints(&x[0], &wrt);
+        let expected = [-0.6 * 0.8, -0.6 * 1.4, 0.2 * 0.8, 0.2 * 1.4, 0.6, -0.2];
+        for (d, e) in dx0.iter().zip(expected) {
+            assert!((d - e).abs() < 1e-6, "{:?} != {:?}", dx0, expected);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "singular")]
+    fn test_solve2_singular() {
+        let a = [
+            [named(1.0, "a00"), named(2.0, "a01")],
+            [named(2.0, "a10"), named(4.0, "a11")],
+        ];
+        solve2(a, [named(1.0, "b0"), named(1.0, "b1")]);
+    }
+
+    #[test]
+    fn test_partial_outputs_combined_with_other_ops() {
+        let x = named(0.5, "x");
+        let y = named(2.0, "y");
+        let (sin, _) = x.clone().sin_cos();
+        let (_, remainder) = y.clone().div_rem(x.clone());
+
+        // d/dx: cos(x) * r + sin(x) * (-q), d/dy: sin(x)
+        let loss = sin.clone() * remainder.clone();
+        let dloss_d = adjoints(&loss, &[x, y]);
+        assert!((dloss_d[0] - (0.5_f32.cos() * remainder.value - sin.value * 4.0)).abs() < 1e-6);
+        assert!((dloss_d[1] - sin.value).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,187 @@
+use super::globals::GRADIENT_TAPE;
+use super::tape::TapeEntry;
+use super::variable::Variable;
+use crate::softmax as kernels;
+
+/*
+ * Softmax and friends over a slice of variables, each recorded as a single
+ * tape entry instead of one per `exp`, `+` and `/`. The primal is shifted
+ * by the maximum, and with s = softmax(x) the adjoints need only s:
+ *   logsumexp:   dx = g s
+ *   log_softmax: dx = g - s sum(g)
+ *   softmax:     dx = s (g - <g, s>)
+ * where g holds the adjoints of the outputs (0 for unused ones).
+ */
+
+fn record<F>(op: &'static str, inputs: &[Variable], values: Vec<f32>, backward: F) -> Vec<Variable>
+where
+    F: Fn(&[f32]) -> Vec<f32> + Clone + Send + Sync + 'static,
+{
+    let outputs: Vec<Variable> = values.iter().map(|&v| Variable::new(v, None)).collect();
+    println!(
+        "{:?} = {}({:?}) = {:?}",
+        outputs.iter().map(|v| &v.name).collect::<Vec<_>>(),
+        op,
+        inputs.iter().map(|v| &v.name).collect::<Vec<_>>(),
+        values
+    );
+
+    let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
+        let g: Vec<f32> = dloss_doutputs
+            .iter()
+            .map(|d| d.as_ref().map_or(0.0, |d| d.value))
+            .collect();
+        backward(&g)
+            .into_iter()
+            .map(|dloss_dinput| Variable::new(dloss_dinput, None))
+            .collect()
+    };
+
+    let tape_entry = TapeEntry::new(op, inputs.to_vec(), outputs.clone(), Box::new(propagate));
+    GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
+
+    outputs
+}
+
+fn values(x: &[Variable]) -> Vec<f32> {
+    x.iter().map(|x| x.value).collect()
+}
+
+pub fn logsumexp(x: &[Variable]) -> Variable {
+    let values = values(x);
+    let s = kernels::softmax(&values);
+    let backward = move |g: &[f32]| s.iter().map(|s| g[0] * s).collect();
+    record("logsumexp", x, vec![kernels::logsumexp(&values)], backward).remove(0)
+}
+
+pub fn softmax(x: &[Variable]) -> Vec<Variable> {
+    let s = kernels::softmax(&values(x));
+    let probabilities = s.clone();
+    let backward = move |g: &[f32]| {
+        let mean: f32 = g.iter().zip(&s).map(|(g, s)| g * s).sum();
+        g.iter().zip(&s).map(|(g, s)| s * (g - mean)).collect()
+    };
+    record("softmax", x, probabilities, backward)
+}
+
+pub fn log_softmax(x: &[Variable]) -> Vec<Variable> {
+    let l = kernels::log_softmax(&values(x));
+    let s: Vec<f32> = l.iter().map(|l| l.exp()).collect();
+    let backward = move |g: &[f32]| {
+        let total: f32 = g.iter().sum();
+        g.iter().zip(&s).map(|(g, s)| g - s * total).collect()
+    };
+    record("log_softmax", x, l, backward)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grad::grad;
+    use super::super::tape::scoped;
+    use super::*;
+
+    fn inputs(x: &[f32]) -> Vec<Variable> {
+        x.iter()
+            .enumerate()
+            .map(|(i, &x)| Variable::new(x, Some(format!("x{}", i))))
+            .collect()
+    }
+
+    fn adjoints(loss: &Variable, wrt: &[Variable]) -> Vec<f32> {
+        grad(loss, wrt)
+            .iter()
+            .map(|d| d.as_ref().map_or(0.0, |d| d.value))
+            .collect()
+    }
+
+    // Compares the fused `f` to the same function composed from scalar ops.
+    fn assert_matches_composed(
+        fused: impl Fn(&[Variable]) -> Variable,
+        composed: impl Fn(&[Variable]) -> Variable,
+    ) {
+        let x = [0.3, -1.2, 2.0, 0.7];
+        let (expected, _) = scoped(|| {
+            let x = inputs(&x);
+            let y = composed(&x);
+            (y.value, adjoints(&y, &x))
+        });
+        let ((value, dy_dx), _) = scoped(|| {
+            let x = inputs(&x);
+            let y = fused(&x);
+            (y.value, adjoints(&y, &x))
+        });
+
+        assert!((value - expected.0).abs() < 1e-5);
+        for (a, b) in dy_dx.iter().zip(&expected.1) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", dy_dx, expected.1);
+        }
+    }
+
+    fn naive_lse(x: &[Variable]) -> Variable {
+        x.iter()
+            .fold(Variable::new(0.0, None), |acc, x| acc + x.clone().exp())
+            .ln()
+    }
+
+    // A weighted sum of the outputs, which all get an adjoint.
+    fn weigh(y: Vec<Variable>) -> Variable {
+        y.into_iter()
+            .enumerate()
+            .fold(Variable::new(0.0, None), |acc, (i, y)| {
+                acc + Variable::new(i as f32 + 1.0, None) * y
+            })
+    }
+
+    #[test]
+    fn test_matches_composed() {
+        assert_matches_composed(logsumexp, naive_lse);
+        assert_matches_composed(
+            |x| weigh(log_softmax(x)),
+            |x| weigh(x.iter().map(|xi| xi.clone() - naive_lse(x)).collect()),
+        );
+        assert_matches_composed(
+            |x| weigh(softmax(x)),
+            |x| {
+                weigh(
+                    x.iter()
+                        .map(|xi| (xi.clone() - naive_lse(x)).exp())
+                        .collect(),
+                )
+            },
+        );
+    }
+
+    #[test]
+    fn test_single_entry_and_unused_outputs() {
+        let x = inputs(&[1.0, 2.0, 3.0]);
+        let (s, tape) = scoped(|| softmax(&x));
+        assert_eq!(tape.entries.len(), 1);
+
+        // Only s[2] is used: ds2/dx = s2 (e2 - s).
+        let (dx, _) = scoped(|| {
+            let s = softmax(&x);
+            adjoints(&s[2], &x)
+        });
+        let expected = [
+            -s[2].value * s[0].value,
+            -s[2].value * s[1].value,
+            s[2].value * (1.0 - s[2].value),
+        ];
+        for (a, b) in dx.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_large_logits() {
+        let x = inputs(&[1000.0, 1000.0, -1000.0]);
+        let (dx, _) = scoped(|| {
+            let l = log_softmax(&x);
+            assert!((l[0].value + 2f32.ln()).abs() < 1e-6);
+            adjoints(&l[0], &x)
+        });
+
+        assert!((dx[0] - 0.5).abs() < 1e-6 && (dx[1] + 0.5).abs() < 1e-6);
+        assert_eq!(dx[2], 0.0);
+    }
+}
@@ -1,52 +1,141 @@
-use super::globals::NAME_IDX;
+use super::globals::{GRADIENT_TAPE, NAME_IDX};
 use super::variable::Variable;
-use std::sync::atomic::Ordering;
+use crate::anomaly;
+use std::cell::{Cell, RefCell};
+use std::thread::LocalKey;
 
-pub struct GradientTape {
-    pub entries: Vec<TapeEntry>,
+/*
+ * What the tape records: scalar `Variable`s or array-valued `Tensor`s.
+ * Each kind has a thread-local tape of its own. Adjoints have the same
+ * kind (and shape) as the node they belong to.
+ */
+pub trait Node: Clone + Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    // Primal values, in row-major order for tensors.
+    fn values(&self) -> &[f32];
+
+    // An adjoint of ones shaped like `self`, to seed a backward pass.
+    fn seed(&self) -> Self;
+
+    // Adds `other`, an adjoint shaped like `self`, into `self`.
+    fn accumulate(&mut self, other: &Self);
+
+    fn tape() -> &'static LocalKey<RefCell<GradientTape<Self>>>;
+
+    // Numbers the anonymous nodes of this kind; restarts when its tape is cleared.
+    fn counter() -> &'static LocalKey<Cell<usize>>;
 }
 
-impl GradientTape {
-    pub fn new() -> Self {
+impl Node for Variable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn values(&self) -> &[f32] {
+        std::slice::from_ref(&self.value)
+    }
+
+    fn seed(&self) -> Self {
+        Variable::new(1.0, None)
+    }
+
+    fn accumulate(&mut self, other: &Self) {
+        self.value += other.value;
+    }
+
+    fn tape() -> &'static LocalKey<RefCell<GradientTape<Self>>> {
+        &GRADIENT_TAPE
+    }
+
+    fn counter() -> &'static LocalKey<Cell<usize>> {
+        &NAME_IDX
+    }
+}
+
+pub struct GradientTape<N: Node = Variable> {
+    pub entries: Vec<TapeEntry<N>>,
+}
+
+impl<N: Node> Default for GradientTape<N> {
+    fn default() -> Self {
+        GradientTape::new()
+    }
+}
+
+impl<N: Node> GradientTape<N> {
+    pub const fn new() -> Self {
         GradientTape {
             entries: Vec::new(),
         }
     }
 
-    pub fn add_entry(&mut self, entry: TapeEntry) {
+    pub fn add_entry(&mut self, entry: TapeEntry<N>) {
+        anomaly::check_entry(&entry);
         self.entries.push(entry);
     }
 
     pub fn clear(&mut self) {
         self.entries.clear();
-        NAME_IDX.store(0, Ordering::SeqCst);
+        N::counter().set(0);
     }
 }
 
-pub trait CloneableFn: Fn(&Vec<Option<Variable>>) -> Vec<Variable> + Send + Sync {
-    fn clone_box(&self) -> Box<dyn CloneableFn>;
+/*
+ * Runs `f` against a fresh tape and hands back whatever it recorded,
+ * restoring the thread's tape afterwards. Useful to keep intermediate
+ * computations off the main tape.
+ */
+pub fn scoped<R>(f: impl FnOnce() -> R) -> (R, GradientTape) {
+    scoped_on::<Variable, R>(f)
+}
+
+// The same for the tape of any kind of node.
+pub fn scoped_on<N: Node, R>(f: impl FnOnce() -> R) -> (R, GradientTape<N>) {
+    // Puts the outer tape back even if `f` unwinds.
+    struct Restore<N: Node>(Option<GradientTape<N>>);
+
+    impl<N: Node> Drop for Restore<N> {
+        fn drop(&mut self) {
+            if let Some(outer) = self.0.take() {
+                N::tape().set(outer);
+            }
+        }
+    }
+
+    let mut outer = Restore(Some(N::tape().with_borrow_mut(std::mem::take)));
+    let result = f();
+    let inner = N::tape().replace(outer.0.take().unwrap());
+    (result, inner)
+}
+
+pub trait CloneableFn<N: Node = Variable>: Fn(&Vec<Option<N>>) -> Vec<N> + Send + Sync {
+    fn clone_box(&self) -> Box<dyn CloneableFn<N>>;
 }
 
-impl<T> CloneableFn for T
+impl<N, T> CloneableFn<N> for T
 where
-    T: 'static + Fn(&Vec<Option<Variable>>) -> Vec<Variable> + Send + Sync + Clone,
+    N: Node,
+    T: 'static + Fn(&Vec<Option<N>>) -> Vec<N> + Send + Sync + Clone,
 {
-    fn clone_box(&self) -> Box<dyn CloneableFn> {
+    fn clone_box(&self) -> Box<dyn CloneableFn<N>> {
         Box::new(self.clone())
     }
 }
 
-type GradientFunction = Box<dyn CloneableFn>;
+type GradientFunction<N> = Box<dyn CloneableFn<N>>;
 
-pub struct TapeEntry {
-    pub inputs: Vec<Variable>,
-    pub outputs: Vec<Variable>,
-    pub propagate: GradientFunction,
+pub struct TapeEntry<N: Node = Variable> {
+    pub op: &'static str,
+    pub inputs: Vec<N>,
+    pub outputs: Vec<N>,
+    pub propagate: GradientFunction<N>,
 }
 
-impl Clone for TapeEntry {
+impl<N: Node> Clone for TapeEntry<N> {
     fn clone(&self) -> Self {
         TapeEntry {
+            op: self.op,
             inputs: self.inputs.clone(),
             outputs: self.outputs.clone(),
             propagate: self.propagate.clone_box(), // This needs a workaround
@@ -54,9 +143,15 @@ impl Clone for TapeEntry {
     }
 }
 
-impl TapeEntry {
-    pub fn new(inputs: Vec<Variable>, outputs: Vec<Variable>, propagate: GradientFunction) -> Self {
+impl<N: Node> TapeEntry<N> {
+    pub fn new(
+        op: &'static str,
+        inputs: Vec<N>,
+        outputs: Vec<N>,
+        propagate: GradientFunction<N>,
+    ) -> Self {
         TapeEntry {
+            op,
             inputs,
             outputs,
             propagate,
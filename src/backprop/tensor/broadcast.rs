@@ -0,0 +1,156 @@
+/*
+ * NumPy-style broadcasting.
+ *
+ * Shapes are aligned on their trailing dimensions; a dimension of 1 (or a
+ * missing leading one) is stretched to match the other operand. A
+ * broadcast operand is read with a stride of 0 along stretched
+ * dimensions, and its adjoint is the sum over those dimensions.
+ */
+
+// Shape of the result of an elementwise operation on `a` and `b`.
+pub fn broadcast_shapes(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let ndim = a.len().max(b.len());
+    let dim = |shape: &[usize], k: usize| {
+        let offset = ndim - shape.len();
+        if k < offset {
+            1
+        } else {
+            shape[k - offset]
+        }
+    };
+
+    (0..ndim)
+        .map(|k| match (dim(a, k), dim(b, k)) {
+            (x, y) if x == y => x,
+            (1, y) => y,
+            (x, 1) => x,
+            _ => panic!("cannot broadcast shapes {:?} and {:?}", a, b),
+        })
+        .collect()
+}
+
+// Row-major strides of a contiguous array.
+pub(crate) fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for k in (0..shape.len().saturating_sub(1)).rev() {
+        strides[k] = strides[k + 1] * shape[k + 1];
+    }
+    strides
+}
+
+// Strides to read an array of `shape` as if it had `out_shape`.
+pub(crate) fn broadcast_strides(shape: &[usize], out_shape: &[usize]) -> Vec<usize> {
+    let offset = out_shape.len() - shape.len();
+    let own = strides(shape);
+    (0..out_shape.len())
+        .map(|k| {
+            if k < offset || shape[k - offset] == 1 {
+                0
+            } else {
+                own[k - offset]
+            }
+        })
+        .collect()
+}
+
+/*
+ * Calls `f` with the flat index into an array of `out_shape` and the
+ * matching offsets into each operand, given the operands' (broadcast)
+ * strides, walking the output in row-major order.
+ */
+pub(crate) fn for_each_offset(
+    out_shape: &[usize],
+    operand_strides: &[Vec<usize>],
+    mut f: impl FnMut(usize, &[usize]),
+) {
+    let len: usize = out_shape.iter().product();
+    let mut index = vec![0; out_shape.len()];
+    let mut offsets = vec![0; operand_strides.len()];
+
+    for flat in 0..len {
+        f(flat, &offsets);
+
+        // Increments the multi-index, carrying into the leading dimensions.
+        for k in (0..out_shape.len()).rev() {
+            index[k] += 1;
+            for (offset, strides) in offsets.iter_mut().zip(operand_strides) {
+                *offset += strides[k];
+            }
+            if index[k] < out_shape[k] {
+                break;
+            }
+            for (offset, strides) in offsets.iter_mut().zip(operand_strides) {
+                *offset -= strides[k] * out_shape[k];
+            }
+            index[k] = 0;
+        }
+    }
+}
+
+// Sums `values`, laid out as `out_shape`, down to `shape`.
+pub(crate) fn reduce_to(values: &[f32], out_shape: &[usize], shape: &[usize]) -> Vec<f32> {
+    let mut reduced = vec![0.0; shape.iter().product()];
+    let strides = [broadcast_strides(shape, out_shape)];
+    for_each_offset(out_shape, &strides, |flat, offsets| {
+        reduced[offsets[0]] += values[flat];
+    });
+    reduced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broadcast_shapes() {
+        assert_eq!(broadcast_shapes(&[2, 3], &[2, 3]), [2, 3]);
+        assert_eq!(broadcast_shapes(&[2, 3], &[3]), [2, 3]);
+        assert_eq!(broadcast_shapes(&[4, 1, 3], &[2, 1]), [4, 2, 3]);
+        assert_eq!(broadcast_shapes(&[], &[5]), [5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot broadcast shapes [2, 3] and [2]")]
+    fn test_incompatible_shapes() {
+        broadcast_shapes(&[2, 3], &[2]);
+    }
+
+    #[test]
+    fn test_strides() {
+        assert_eq!(strides(&[2, 3, 4]), [12, 4, 1]);
+        assert_eq!(broadcast_strides(&[3, 1], &[2, 3, 4]), [0, 1, 0]);
+    }
+
+    #[test]
+    fn test_for_each_offset() {
+        let mut visited = Vec::new();
+        let operand_strides = [
+            broadcast_strides(&[2, 1], &[2, 3]),
+            broadcast_strides(&[3], &[2, 3]),
+        ];
+        for_each_offset(&[2, 3], &operand_strides, |flat, offsets| {
+            visited.push((flat, offsets[0], offsets[1]));
+        });
+
+        assert_eq!(
+            visited,
+            [
+                (0, 0, 0),
+                (1, 0, 1),
+                (2, 0, 2),
+                (3, 1, 0),
+                (4, 1, 1),
+                (5, 1, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reduce_to() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+
+        assert_eq!(reduce_to(&values, &[2, 3], &[3]), [5.0, 7.0, 9.0]);
+        assert_eq!(reduce_to(&values, &[2, 3], &[2, 1]), [6.0, 15.0]);
+        assert_eq!(reduce_to(&values, &[2, 3], &[]), [21.0]);
+    }
+}
@@ -0,0 +1,482 @@
+use super::{record, Tensor};
+use crate::linalg::{matmul, transpose};
+use std::sync::Arc;
+
+/*
+ * Convolution and pooling over [batch, channels, length] (1-D) and
+ * [batch, channels, height, width] (2-D) tensors.
+ *
+ * Convolutions go through im2col: each input plane is unrolled into a
+ * matrix with one column per output position and one row per (channel,
+ * kernel tap), so the forward pass is a single matrix product with the
+ * [out_channels, in_channels * taps] weights. The backward pass multiplies
+ * by the transposes and folds the columns back (col2im), summing where
+ * windows overlap. A 1-D convolution is the 2-D one on a plane of height 1.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConvOptions {
+    pub stride: usize,
+    // Zeros added on both sides of every spatial axis.
+    pub padding: usize,
+    // Spacing between kernel taps.
+    pub dilation: usize,
+}
+
+impl Default for ConvOptions {
+    fn default() -> Self {
+        ConvOptions {
+            stride: 1,
+            padding: 0,
+            dilation: 1,
+        }
+    }
+}
+
+// Sizes along the (height, width) axes of one plane.
+#[derive(Debug, Clone, Copy)]
+struct Geometry {
+    input: [usize; 2],
+    kernel: [usize; 2],
+    stride: [usize; 2],
+    padding: [usize; 2],
+    dilation: [usize; 2],
+    output: [usize; 2],
+}
+
+impl Geometry {
+    // `input` and `kernel` hold only the spatial axes, one or two of them.
+    fn new(input: &[usize], kernel: &[usize], options: ConvOptions) -> Self {
+        // A 1-D plane has a height of 1, which the options leave alone.
+        let lift = |x: &[usize], default: usize, value: usize| match x.len() {
+            1 => [default, value],
+            _ => [value, value],
+        };
+        let mut geometry = Geometry {
+            input: [0; 2],
+            kernel: [0; 2],
+            stride: lift(input, 1, options.stride),
+            padding: lift(input, 0, options.padding),
+            dilation: lift(input, 1, options.dilation),
+            output: [0; 2],
+        };
+        let offset = 2 - input.len();
+        for k in 0..2 {
+            geometry.input[k] = if k < offset { 1 } else { input[k - offset] };
+            geometry.kernel[k] = if k < offset { 1 } else { kernel[k - offset] };
+            let padded = geometry.input[k] + 2 * geometry.padding[k];
+            // An empty kernel has no span, so it is ruled out before one is computed.
+            let span = |kernel: usize| geometry.dilation[k] * (kernel - 1) + 1;
+            assert!(
+                geometry.stride[k] > 0
+                    && geometry.kernel[k] > 0
+                    && span(geometry.kernel[k]) <= padded,
+                "a kernel of {:?} with {:?} does not fit an input of {:?}",
+                kernel,
+                options,
+                input
+            );
+            let span = span(geometry.kernel[k]);
+            geometry.output[k] = (padded - span) / geometry.stride[k] + 1;
+        }
+        geometry
+    }
+
+    fn plane(&self) -> usize {
+        self.input[0] * self.input[1]
+    }
+
+    fn taps(&self) -> usize {
+        self.kernel[0] * self.kernel[1]
+    }
+
+    fn windows(&self) -> usize {
+        self.output[0] * self.output[1]
+    }
+
+    // Offset into an input plane of `tap` in `window`, or None in the padding.
+    fn source(&self, window: usize, tap: usize) -> Option<usize> {
+        let position = [window / self.output[1], window % self.output[1]];
+        let tap = [tap / self.kernel[1], tap % self.kernel[1]];
+        let mut index = [0; 2];
+        for k in 0..2 {
+            let i = (position[k] * self.stride[k] + tap[k] * self.dilation[k])
+                .checked_sub(self.padding[k])?;
+            if i >= self.input[k] {
+                return None;
+            }
+            index[k] = i;
+        }
+        Some(index[0] * self.input[1] + index[1])
+    }
+
+    // The spatial axes of an output, one or two of them.
+    fn output_shape(&self, ndim: usize) -> Vec<usize> {
+        self.output[2 - ndim..].to_vec()
+    }
+}
+
+// [channels * taps, windows] columns of `channels` consecutive input planes.
+fn im2col(x: &[f32], channels: usize, g: &Geometry) -> Vec<f32> {
+    let (taps, windows) = (g.taps(), g.windows());
+    let mut col = vec![0.0; channels * taps * windows];
+    for c in 0..channels {
+        let plane = &x[c * g.plane()..(c + 1) * g.plane()];
+        for t in 0..taps {
+            let row = &mut col[(c * taps + t) * windows..(c * taps + t + 1) * windows];
+            for (w, entry) in row.iter_mut().enumerate() {
+                if let Some(offset) = g.source(w, t) {
+                    *entry = plane[offset];
+                }
+            }
+        }
+    }
+    col
+}
+
+// Adds the columns back onto the input planes they were read from.
+fn col2im(col: &[f32], channels: usize, g: &Geometry, dx: &mut [f32]) {
+    let (taps, windows) = (g.taps(), g.windows());
+    for c in 0..channels {
+        let plane = &mut dx[c * g.plane()..(c + 1) * g.plane()];
+        for t in 0..taps {
+            for w in 0..windows {
+                if let Some(offset) = g.source(w, t) {
+                    plane[offset] += col[(c * taps + t) * windows + w];
+                }
+            }
+        }
+    }
+}
+
+impl Tensor {
+    // [batch, in, length] with [out, in, kernel] weights.
+    pub fn conv1d(self, weight: Tensor, options: ConvOptions) -> Tensor {
+        self.convolve("conv1d", weight, 1, options)
+    }
+
+    // [batch, in, height, width] with [out, in, kernel_h, kernel_w] weights.
+    pub fn conv2d(self, weight: Tensor, options: ConvOptions) -> Tensor {
+        self.convolve("conv2d", weight, 2, options)
+    }
+
+    fn convolve(
+        self,
+        op: &'static str,
+        weight: Tensor,
+        ndim: usize,
+        options: ConvOptions,
+    ) -> Tensor {
+        assert!(
+            self.ndim() == ndim + 2
+                && weight.ndim() == ndim + 2
+                && weight.shape[1] == self.shape[1],
+            "{} of {:?} with weights {:?}",
+            op,
+            self.shape,
+            weight.shape
+        );
+        let (batch, channels, out_channels) = (self.shape[0], self.shape[1], weight.shape[0]);
+        let g = Geometry::new(&self.shape[2..], &weight.shape[2..], options);
+        let (rows, windows) = (channels * g.taps(), g.windows());
+
+        let mut values = Vec::with_capacity(batch * out_channels * windows);
+        for b in 0..batch {
+            let x = &self.data[b * channels * g.plane()..(b + 1) * channels * g.plane()];
+            let col = im2col(x, channels, &g);
+            values.extend(matmul(&weight.data, &col, out_channels, rows, windows));
+        }
+        let mut out_shape = vec![batch, out_channels];
+        out_shape.extend(g.output_shape(ndim));
+        let result = Tensor::new(&out_shape, values, None);
+
+        let (x, w) = (Arc::clone(&self.data), Arc::clone(&weight.data));
+        let (x_shape, w_shape) = (self.shape.clone(), weight.shape.clone());
+        let backward = move |dloss_dresult: &Tensor| {
+            let w_t = transpose(&w, out_channels, rows);
+            let mut dloss_dx = vec![0.0; x.len()];
+            let mut dloss_dw = vec![0.0; w.len()];
+            for b in 0..batch {
+                let dy = &dloss_dresult.data
+                    [b * out_channels * windows..(b + 1) * out_channels * windows];
+                let x_b = &x[b * channels * g.plane()..(b + 1) * channels * g.plane()];
+                let col_t = transpose(&im2col(x_b, channels, &g), rows, windows);
+                for (d, e) in
+                    dloss_dw
+                        .iter_mut()
+                        .zip(matmul(dy, &col_t, out_channels, windows, rows))
+                {
+                    *d += e;
+                }
+                let dcol = matmul(&w_t, dy, rows, out_channels, windows);
+                let dx_b = &mut dloss_dx[b * channels * g.plane()..(b + 1) * channels * g.plane()];
+                col2im(&dcol, channels, &g, dx_b);
+            }
+            vec![
+                Tensor::new(&x_shape, dloss_dx, None),
+                Tensor::new(&w_shape, dloss_dw, None),
+            ]
+        };
+        record(op, vec![self, weight], result, backward)
+    }
+
+    /*
+     * Max pooling over windows of `kernel` positions. The adjoint flows to
+     * the first maximum of each window; padding never wins, so every window
+     * must read some input, which with dilation can fail even for padding
+     * below half the kernel.
+     */
+    pub fn max_pool1d(self, kernel: usize, options: ConvOptions) -> Tensor {
+        self.pool("max_pool1d", &[kernel], options, Pooling::Max)
+    }
+
+    pub fn max_pool2d(self, kernel: [usize; 2], options: ConvOptions) -> Tensor {
+        self.pool("max_pool2d", &kernel, options, Pooling::Max)
+    }
+
+    // Average pooling; padding counts as zeros in the average.
+    pub fn avg_pool1d(self, kernel: usize, options: ConvOptions) -> Tensor {
+        self.pool("avg_pool1d", &[kernel], options, Pooling::Average)
+    }
+
+    pub fn avg_pool2d(self, kernel: [usize; 2], options: ConvOptions) -> Tensor {
+        self.pool("avg_pool2d", &kernel, options, Pooling::Average)
+    }
+
+    fn pool(
+        self,
+        op: &'static str,
+        kernel: &[usize],
+        options: ConvOptions,
+        pooling: Pooling,
+    ) -> Tensor {
+        let ndim = kernel.len();
+        assert_eq!(self.ndim(), ndim + 2, "{} of {:?}", op, self.shape);
+        let g = Geometry::new(&self.shape[2..], kernel, options);
+        let planes = self.shape[0] * self.shape[1];
+        let (taps, windows) = (g.taps(), g.windows());
+        if pooling == Pooling::Max {
+            assert!(
+                (0..windows).all(|w| (0..taps).any(|t| g.source(w, t).is_some())),
+                "{} of {:?} with kernel {:?} and {:?} has a window entirely in the padding",
+                op,
+                self.shape,
+                kernel,
+                options
+            );
+        }
+
+        // For every output, the input offsets it reads and their weights.
+        let mut sources: Vec<Vec<(usize, f32)>> = Vec::with_capacity(planes * windows);
+        let mut values = Vec::with_capacity(planes * windows);
+        for p in 0..planes {
+            let plane = &self.data[p * g.plane()..(p + 1) * g.plane()];
+            for w in 0..windows {
+                let inside = (0..taps).filter_map(|t| g.source(w, t));
+                match pooling {
+                    Pooling::Max => {
+                        let best = inside
+                            .reduce(|best, o| if plane[o] > plane[best] { o } else { best })
+                            .expect("a window with some input");
+                        values.push(plane[best]);
+                        sources.push(vec![(p * g.plane() + best, 1.0)]);
+                    }
+                    Pooling::Average => {
+                        let weight = 1.0 / taps as f32;
+                        let source: Vec<_> = inside.map(|o| (p * g.plane() + o, weight)).collect();
+                        values.push(source.iter().map(|&(o, w)| w * self.data[o]).sum());
+                        sources.push(source);
+                    }
+                }
+            }
+        }
+        let mut out_shape = self.shape[..2].to_vec();
+        out_shape.extend(g.output_shape(ndim));
+        let result = Tensor::new(&out_shape, values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; shape.iter().product()];
+            for (source, d) in sources.iter().zip(dloss_dresult.data.iter()) {
+                for &(o, w) in source {
+                    dloss_dself[o] += w * d;
+                }
+            }
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record(op, vec![self], result, backward)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pooling {
+    Max,
+    Average,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{assert_gradients, random_tensor};
+    use super::*;
+
+    fn options(stride: usize, padding: usize, dilation: usize) -> ConvOptions {
+        ConvOptions {
+            stride,
+            padding,
+            dilation,
+        }
+    }
+
+    // Direct definition of a 2-D convolution, for reference.
+    fn naive_conv2d(x: &Tensor, w: &Tensor, o: ConvOptions) -> Vec<f32> {
+        let (b, c, h, wd) = (x.shape[0], x.shape[1], x.shape[2], x.shape[3]);
+        let (oc, kh, kw) = (w.shape[0], w.shape[2], w.shape[3]);
+        let out =
+            |n: usize, k: usize| (n + 2 * o.padding - o.dilation * (k - 1) - 1) / o.stride + 1;
+        let (oh, ow) = (out(h, kh), out(wd, kw));
+        let mut y = Vec::new();
+        for n in 0..b {
+            for f in 0..oc {
+                for i in 0..oh {
+                    for j in 0..ow {
+                        let mut sum = 0.0;
+                        for ch in 0..c {
+                            for ki in 0..kh {
+                                for kj in 0..kw {
+                                    let r = (i * o.stride + ki * o.dilation) as isize
+                                        - o.padding as isize;
+                                    let s = (j * o.stride + kj * o.dilation) as isize
+                                        - o.padding as isize;
+                                    if r >= 0 && s >= 0 && (r as usize) < h && (s as usize) < wd {
+                                        sum += x.get(&[n, ch, r as usize, s as usize])
+                                            * w.get(&[f, ch, ki, kj]);
+                                    }
+                                }
+                            }
+                        }
+                        y.push(sum);
+                    }
+                }
+            }
+        }
+        y
+    }
+
+    #[test]
+    fn test_conv2d_matches_definition() {
+        let x = random_tensor(&[2, 3, 6, 5], 1, "x");
+        let w = random_tensor(&[4, 3, 3, 2], 2, "w");
+        for o in [options(1, 0, 1), options(2, 1, 1), options(1, 2, 2)] {
+            let y = x.clone().conv2d(w.clone(), o);
+            let expected = naive_conv2d(&x, &w, o);
+
+            assert_eq!(y.len(), expected.len());
+            for (a, b) in y.data.iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+        assert_eq!(x.conv2d(w, options(2, 1, 1)).shape, [2, 4, 3, 3]);
+    }
+
+    #[test]
+    fn test_conv1d() {
+        // A moving difference.
+        let x = Tensor::new(&[1, 1, 5], vec![1.0, 4.0, 9.0, 16.0, 25.0], None);
+        let w = Tensor::new(&[1, 1, 2], vec![-1.0, 1.0], None);
+
+        assert_eq!(
+            *x.clone().conv1d(w.clone(), ConvOptions::default()).data,
+            [3.0, 5.0, 7.0, 9.0]
+        );
+        let padded = x.conv1d(w, options(2, 1, 1));
+        assert_eq!(*padded.data, [1.0, 5.0, 9.0]);
+    }
+
+    #[test]
+    fn test_conv_gradients() {
+        let x = random_tensor(&[2, 2, 7], 3, "x");
+        let w = random_tensor(&[3, 2, 3], 4, "w");
+        for o in [options(1, 0, 1), options(2, 1, 2)] {
+            assert_gradients(
+                |t| t[0].clone().conv1d(t[1].clone(), o).pow(2.0),
+                &[x.clone(), w.clone()],
+            );
+        }
+
+        let x = random_tensor(&[2, 2, 5, 4], 5, "x");
+        let w = random_tensor(&[2, 2, 2, 3], 6, "w");
+        for o in [options(1, 0, 1), options(2, 1, 1), options(1, 1, 2)] {
+            assert_gradients(
+                |t| t[0].clone().conv2d(t[1].clone(), o).pow(2.0),
+                &[x.clone(), w.clone()],
+            );
+        }
+    }
+
+    #[test]
+    fn test_pooling() {
+        let x = Tensor::new(
+            &[1, 1, 2, 4],
+            vec![1.0, 5.0, 2.0, 0.0, 3.0, 4.0, 8.0, 6.0],
+            None,
+        );
+        let stride_2 = options(2, 0, 1);
+
+        assert_eq!(*x.clone().max_pool2d([2, 2], stride_2).data, [5.0, 8.0]);
+        assert_eq!(*x.clone().avg_pool2d([2, 2], stride_2).data, [3.25, 4.0]);
+
+        let row = Tensor::new(&[1, 1, 4], vec![1.0, 5.0, 2.0, 0.0], None);
+        assert_eq!(
+            *row.clone().max_pool1d(2, options(2, 1, 1)).data,
+            [1.0, 5.0, 0.0]
+        );
+        assert_eq!(*row.avg_pool1d(2, options(2, 1, 1)).data, [0.5, 3.5, 0.0]);
+    }
+
+    #[test]
+    fn test_pooling_gradients() {
+        // Distinct values, so no window has a tie.
+        let data = (0..60).map(|i| ((i * 37) % 60) as f32 * 0.1).collect();
+        let x = Tensor::new(&[1, 3, 4, 5], data, Some("x".to_string()));
+        for o in [options(1, 0, 1), options(2, 1, 1)] {
+            assert_gradients(
+                |t| t[0].clone().max_pool2d([2, 3], o).pow(2.0),
+                std::slice::from_ref(&x),
+            );
+            assert_gradients(
+                |t| t[0].clone().avg_pool2d([3, 2], o).pow(2.0),
+                std::slice::from_ref(&x),
+            );
+        }
+
+        let x = x.reshape(&[3, 4, 5]);
+        let x = Tensor::new(&x.shape, x.data.to_vec(), Some("x".to_string()));
+        assert_gradients(
+            |t| t[0].clone().max_pool1d(3, options(2, 1, 1)).pow(2.0),
+            std::slice::from_ref(&x),
+        );
+        assert_gradients(
+            |t| t[0].clone().avg_pool1d(2, options(1, 1, 2)).pow(2.0),
+            &[x],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "has a window entirely in the padding")]
+    fn test_max_pool_window_in_padding() {
+        // Both taps of the only window, at -1 and 1, miss the input.
+        Tensor::zeros(&[1, 1, 1]).max_pool1d(2, options(1, 1, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit")]
+    fn test_kernel_too_large() {
+        Tensor::zeros(&[1, 1, 3]).conv1d(Tensor::zeros(&[1, 1, 4]), ConvOptions::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit")]
+    fn test_empty_kernel() {
+        Tensor::zeros(&[1, 1, 3]).conv1d(Tensor::zeros(&[1, 1, 0]), ConvOptions::default());
+    }
+}
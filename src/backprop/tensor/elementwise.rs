@@ -0,0 +1,239 @@
+use super::broadcast::{broadcast_shapes, broadcast_strides, for_each_offset, reduce_to};
+use super::{record, Tensor};
+use crate::subgradient;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::sync::Arc;
+
+/*
+ * Elementwise operations. Binary ones broadcast their operands, and
+ * reduce each adjoint back to the shape of its operand.
+ */
+
+impl Tensor {
+    // Records an elementwise function of one input along with its derivative.
+    fn map(self, op: &'static str, f: impl Fn(f32) -> (f32, f32)) -> Tensor {
+        let (values, dresult_dself): (Vec<f32>, Vec<f32>) = self.data.iter().map(|&x| f(x)).unzip();
+        let result = Tensor::new(&self.shape, values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let dloss_dself = dloss_dresult
+                .data
+                .iter()
+                .zip(&dresult_dself)
+                .map(|(g, d)| g * d)
+                .collect();
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record(op, vec![self], result, backward)
+    }
+
+    /*
+     * Records a broadcasting function of two inputs. `partials` gives the
+     * derivatives with respect to each operand and is evaluated during the
+     * backward pass.
+     */
+    fn zip(
+        self,
+        op: &'static str,
+        rhs: Tensor,
+        f: fn(f32, f32) -> f32,
+        partials: fn(f32, f32) -> (f32, f32),
+    ) -> Tensor {
+        let out_shape = broadcast_shapes(&self.shape, &rhs.shape);
+        let operand_strides = [
+            broadcast_strides(&self.shape, &out_shape),
+            broadcast_strides(&rhs.shape, &out_shape),
+        ];
+
+        let mut values = vec![0.0; out_shape.iter().product()];
+        for_each_offset(&out_shape, &operand_strides, |flat, offsets| {
+            values[flat] = f(self.data[offsets[0]], rhs.data[offsets[1]]);
+        });
+        let result = Tensor::new(&out_shape, values, None);
+
+        let (a, b) = (Arc::clone(&self.data), Arc::clone(&rhs.data));
+        let (a_shape, b_shape) = (self.shape.clone(), rhs.shape.clone());
+        let backward = move |dloss_dresult: &Tensor| {
+            let len = dloss_dresult.len();
+            let (mut dloss_da, mut dloss_db) = (vec![0.0; len], vec![0.0; len]);
+            for_each_offset(&out_shape, &operand_strides, |flat, offsets| {
+                let (da, db) = partials(a[offsets[0]], b[offsets[1]]);
+                dloss_da[flat] = dloss_dresult.data[flat] * da;
+                dloss_db[flat] = dloss_dresult.data[flat] * db;
+            });
+            vec![
+                Tensor::new(&a_shape, reduce_to(&dloss_da, &out_shape, &a_shape), None),
+                Tensor::new(&b_shape, reduce_to(&dloss_db, &out_shape, &b_shape), None),
+            ]
+        };
+        record(op, vec![self, rhs], result, backward)
+    }
+
+    pub fn pow(self, exp: f32) -> Tensor {
+        self.map("pow", |x| (x.powf(exp), exp * x.powf(exp - 1.0)))
+    }
+
+    pub fn sqrt(self) -> Tensor {
+        self.map("sqrt", |x| (x.sqrt(), 0.5 / x.sqrt()))
+    }
+
+    pub fn exp(self) -> Tensor {
+        self.map("exp", |x| (x.exp(), x.exp()))
+    }
+
+    pub fn ln(self) -> Tensor {
+        self.map("ln", |x| (x.ln(), 1.0 / x))
+    }
+
+    pub fn tanh(self) -> Tensor {
+        self.map("tanh", |x| (x.tanh(), 1.0 - x.tanh().powi(2)))
+    }
+
+    // The kinks take their derivative from the active subgradient policy.
+
+    pub fn relu(self) -> Tensor {
+        self.map("relu", |x| (x.max(0.0), subgradient::slope(x, 0.0, 1.0)))
+    }
+
+    pub fn abs(self) -> Tensor {
+        self.map("abs", |x| (x.abs(), subgradient::slope(x, -1.0, 1.0)))
+    }
+}
+
+impl Add for Tensor {
+    type Output = Tensor;
+
+    fn add(self, rhs: Tensor) -> Self::Output {
+        self.zip("add", rhs, |a, b| a + b, |_, _| (1.0, 1.0))
+    }
+}
+
+impl Sub for Tensor {
+    type Output = Tensor;
+
+    fn sub(self, rhs: Tensor) -> Self::Output {
+        self.zip("sub", rhs, |a, b| a - b, |_, _| (1.0, -1.0))
+    }
+}
+
+impl Mul for Tensor {
+    type Output = Tensor;
+
+    fn mul(self, rhs: Tensor) -> Self::Output {
+        self.zip("mul", rhs, |a, b| a * b, |a, b| (b, a))
+    }
+}
+
+impl Div for Tensor {
+    type Output = Tensor;
+
+    fn div(self, rhs: Tensor) -> Self::Output {
+        self.zip("div", rhs, |a, b| a / b, |a, b| (1.0 / b, -a / (b * b)))
+    }
+}
+
+impl Neg for Tensor {
+    type Output = Tensor;
+
+    fn neg(self) -> Self::Output {
+        self.map("neg", |x| (-x, -1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_gradients;
+    use super::*;
+    use crate::backprop::grad::grad;
+    use crate::backprop::tape::scoped_on;
+
+    fn matrix() -> Tensor {
+        Tensor::new(
+            &[2, 3],
+            vec![0.5, -1.0, 2.0, 1.5, 0.3, -0.7],
+            Some("a".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_broadcast_values() {
+        let a = matrix();
+        let b = Tensor::new(&[3], vec![1.0, 2.0, 3.0], None);
+        let c = a * b;
+
+        assert_eq!(c.shape, [2, 3]);
+        assert_eq!(*c.data, [0.5, -2.0, 6.0, 1.5, 0.6, -2.1]);
+    }
+
+    #[test]
+    fn test_broadcast_gradients() {
+        let a = matrix();
+        let b = Tensor::new(&[3], vec![1.0, 2.0, 3.0], Some("b".to_string()));
+        let ((), tape) = scoped_on::<Tensor, _>(|| {
+            let loss = (a.clone() * b.clone()).sum();
+            let d = grad(&loss, &[a.clone(), b.clone()]);
+
+            // d/db sums the rows of a; d/da repeats b on every row.
+            let (da, db) = (d[0].clone().unwrap(), d[1].clone().unwrap());
+            assert_eq!(db.shape, [3]);
+            assert!((db.data[0] - 2.0).abs() < 1e-6);
+            assert!((db.data[1] + 0.7).abs() < 1e-6);
+            assert!((db.data[2] - 1.3).abs() < 1e-6);
+            assert_eq!(*da.data, [1.0, 2.0, 3.0, 1.0, 2.0, 3.0]);
+        });
+
+        // One entry per operation, not per element.
+        let ops: Vec<_> = tape.entries.iter().map(|e| e.op).collect();
+        assert_eq!(ops, ["mul", "sum"]);
+    }
+
+    #[test]
+    fn test_arithmetic_gradients() {
+        let a = matrix();
+        let b = Tensor::new(&[2, 1], vec![2.0, -3.0], Some("b".to_string()));
+        let c = Tensor::scalar(1.5, Some("c".to_string()));
+
+        assert_gradients(
+            |x| x[0].clone() + x[1].clone() - x[2].clone(),
+            &[a.clone(), b.clone(), c.clone()],
+        );
+        assert_gradients(
+            |x| x[0].clone() * x[1].clone() / x[2].clone(),
+            &[a.clone(), b.clone(), c],
+        );
+        assert_gradients(
+            |x| x[1].clone() / (x[0].clone() * x[0].clone() + x[1].clone()),
+            &[a, b],
+        );
+    }
+
+    #[test]
+    fn test_unary_gradients() {
+        let a = Tensor::new(&[2, 2], vec![0.5, 1.2, 2.0, 0.8], Some("a".to_string()));
+
+        assert_gradients(|x| x[0].clone().pow(3.0), std::slice::from_ref(&a));
+        assert_gradients(|x| x[0].clone().sqrt(), std::slice::from_ref(&a));
+        assert_gradients(|x| x[0].clone().exp().ln(), std::slice::from_ref(&a));
+        assert_gradients(|x| -x[0].clone().tanh(), std::slice::from_ref(&a));
+    }
+
+    #[test]
+    fn test_piecewise_gradients() {
+        let a = matrix();
+
+        assert_gradients(|x| x[0].clone().relu() + x[0].clone().abs(), &[a]);
+    }
+
+    #[test]
+    fn test_scalar_loss() {
+        let a = matrix();
+        let loss = (a.clone() * a.clone()).sum();
+        let da = grad(&loss, std::slice::from_ref(&a))[0].clone().unwrap();
+
+        assert_eq!(loss.shape, Vec::<usize>::new());
+        for (d, x) in da.data.iter().zip(a.data.iter()) {
+            assert_eq!(*d, 2.0 * x);
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use super::reduce::split_at_axis;
+use super::{record, Tensor};
+
+/*
+ * Indexing with integer indices and boolean masks, which are plain slices
+ * rather than tensors since they are not differentiated. Reading an
+ * element twice reads its adjoint twice as well, so the adjoints of
+ * `index_select`, `gather` and `masked_select` scatter with `+=` and
+ * repeated indices accumulate.
+ */
+
+// Flat offsets into `shape` of an index of `index_shape` along `axis`.
+fn offsets_along(
+    shape: &[usize],
+    axis: usize,
+    index: &[usize],
+    index_shape: &[usize],
+) -> Vec<usize> {
+    let (outer, n, inner) = split_at_axis(shape, axis);
+    let agrees = index_shape.len() == shape.len()
+        && (0..shape.len()).all(|k| k == axis || index_shape[k] == shape[k]);
+    assert!(
+        agrees && index.len() == index_shape.iter().product::<usize>(),
+        "an index of shape {:?} along axis {} of shape {:?}",
+        index_shape,
+        axis,
+        shape
+    );
+
+    let m = index_shape[axis];
+    let mut offsets = Vec::with_capacity(index.len());
+    for o in 0..outer {
+        for i in 0..m {
+            for r in 0..inner {
+                let idx = index[(o * m + i) * inner + r];
+                assert!(
+                    idx < n,
+                    "index {} out of range for axis {} of shape {:?}",
+                    idx,
+                    axis,
+                    shape
+                );
+                offsets.push((o * n + idx) * inner + r);
+            }
+        }
+    }
+    offsets
+}
+
+impl Tensor {
+    // The slices with the given indices along `axis`, in order.
+    pub fn index_select(self, axis: usize, indices: &[usize]) -> Tensor {
+        let (outer, _, inner) = split_at_axis(&self.shape, axis);
+        let mut out_shape = self.shape.clone();
+        out_shape[axis] = indices.len();
+        let index: Vec<usize> = (0..outer)
+            .flat_map(|_| indices.iter().flat_map(|&i| std::iter::repeat_n(i, inner)))
+            .collect();
+        let offsets = offsets_along(&self.shape, axis, &index, &out_shape);
+        self.take("index_select", &out_shape, offsets)
+    }
+
+    /*
+     * Picks one element along `axis` for every position of `index`, whose
+     * shape agrees with `self` on the other axes:
+     *   result[.., i, ..] = self[.., index[.., i, ..], ..].
+     */
+    pub fn gather(self, axis: usize, index: &[usize], index_shape: &[usize]) -> Tensor {
+        let offsets = offsets_along(&self.shape, axis, index, index_shape);
+        self.take("gather", index_shape, offsets)
+    }
+
+    /*
+     * Adds each element of `src` into `self` at the position `gather`
+     * would read it from, with `index` of the shape of `src`; repeated
+     * indices add up.
+     */
+    pub fn scatter_add(self, axis: usize, index: &[usize], src: Tensor) -> Tensor {
+        let offsets = offsets_along(&self.shape, axis, index, &src.shape);
+        let mut values = self.data.to_vec();
+        for (&o, x) in offsets.iter().zip(src.data.iter()) {
+            values[o] += x;
+        }
+        let result = Tensor::new(&self.shape, values, None);
+
+        let src_shape = src.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let dloss_dsrc = offsets.iter().map(|&o| dloss_dresult.data[o]).collect();
+            vec![
+                dloss_dresult.clone(),
+                Tensor::new(&src_shape, dloss_dsrc, None),
+            ]
+        };
+        record("scatter_add", vec![self, src], result, backward)
+    }
+
+    // The elements where `mask` is set, in row-major order, as a vector.
+    pub fn masked_select(self, mask: &[bool]) -> Tensor {
+        self.check_mask(mask);
+        let offsets: Vec<usize> = (0..mask.len()).filter(|&i| mask[i]).collect();
+        self.take("masked_select", &[offsets.len()], offsets)
+    }
+
+    // Replaces the elements where `mask` is set by `value`, cutting their adjoints.
+    pub fn masked_fill(self, mask: &[bool], value: f32) -> Tensor {
+        self.check_mask(mask);
+        let values = self
+            .data
+            .iter()
+            .zip(mask)
+            .map(|(&x, &m)| if m { value } else { x })
+            .collect();
+        let result = Tensor::new(&self.shape, values, None);
+
+        let mask = mask.to_vec();
+        let backward = move |dloss_dresult: &Tensor| {
+            let dloss_dself = dloss_dresult
+                .data
+                .iter()
+                .zip(&mask)
+                .map(|(&d, &m)| if m { 0.0 } else { d })
+                .collect();
+            vec![Tensor::new(&dloss_dresult.shape, dloss_dself, None)]
+        };
+        record("masked_fill", vec![self], result, backward)
+    }
+
+    fn check_mask(&self, mask: &[bool]) {
+        assert_eq!(
+            mask.len(),
+            self.len(),
+            "a mask of {} elements for shape {:?}",
+            mask.len(),
+            self.shape
+        );
+    }
+
+    // The elements at `offsets`, as a tensor of `shape`.
+    fn take(self, op: &'static str, shape: &[usize], offsets: Vec<usize>) -> Tensor {
+        let values = offsets.iter().map(|&o| self.data[o]).collect();
+        let result = Tensor::new(shape, values, None);
+
+        let own_shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; own_shape.iter().product()];
+            for (&o, d) in offsets.iter().zip(dloss_dresult.data.iter()) {
+                dloss_dself[o] += d;
+            }
+            vec![Tensor::new(&own_shape, dloss_dself, None)]
+        };
+        record(op, vec![self], result, backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_gradients;
+    use super::*;
+    use crate::backprop::grad::grad;
+
+    fn matrix(name: &str) -> Tensor {
+        let data = (0..6).map(|i| i as f32 * 0.5 - 1.2).collect();
+        Tensor::new(&[2, 3], data, Some(name.to_string()))
+    }
+
+    #[test]
+    fn test_embedding_lookup() {
+        // Row 1 is looked up three times, row 0 never.
+        let table = Tensor::new(&[3, 2], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0], None);
+        let e = table.clone().index_select(0, &[1, 2, 1, 1]);
+
+        assert_eq!(e.shape, [4, 2]);
+        assert_eq!(*e.data, [2.0, 3.0, 4.0, 5.0, 2.0, 3.0, 2.0, 3.0]);
+        let g = grad(&e.sum(), std::slice::from_ref(&table));
+        assert_eq!(*g[0].as_ref().unwrap().data, [0.0, 0.0, 3.0, 3.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_index_select_gradients() {
+        let x = matrix("x");
+
+        let s = x.clone().index_select(1, &[2, 0]);
+        assert_eq!(s.get(&[0, 0]), x.get(&[0, 2]));
+        assert_eq!(s.get(&[1, 1]), x.get(&[1, 0]));
+        assert_gradients(
+            |t| t[0].clone().index_select(1, &[2, 0, 2]).pow(2.0),
+            std::slice::from_ref(&x),
+        );
+        assert_gradients(|t| t[0].clone().index_select(0, &[1, 1]).pow(2.0), &[x]);
+    }
+
+    #[test]
+    fn test_gather() {
+        let x = matrix("x");
+        let g = x.clone().gather(1, &[2, 2, 0, 1, 1, 1], &[2, 3]);
+
+        assert_eq!(g.get(&[0, 0]), x.get(&[0, 2]));
+        assert_eq!(g.get(&[1, 2]), x.get(&[1, 1]));
+        assert_gradients(
+            |t| {
+                t[0].clone()
+                    .gather(1, &[2, 2, 0, 1, 1, 1], &[2, 3])
+                    .pow(2.0)
+            },
+            std::slice::from_ref(&x),
+        );
+        // One element per column, as a classifier picks its targets.
+        assert_gradients(
+            |t| t[0].clone().gather(0, &[1, 0, 1], &[1, 3]).pow(2.0),
+            &[x],
+        );
+    }
+
+    #[test]
+    fn test_scatter_add() {
+        // Messages from three edges into two nodes; node 0 gets two of them.
+        let nodes = Tensor::zeros(&[2, 2]);
+        let messages = Tensor::new(&[3, 2], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], None);
+        let s = nodes.scatter_add(0, &[0, 0, 1, 1, 0, 0], messages);
+
+        assert_eq!(*s.data, [6.0, 8.0, 3.0, 4.0]);
+
+        let x = matrix("x");
+        let src = Tensor::new(
+            &[3, 3],
+            (0..9).map(|i| i as f32 * 0.3).collect(),
+            Some("src".to_string()),
+        );
+        let index = [1, 0, 1, 1, 1, 0, 0, 0, 1];
+        assert_gradients(
+            |t| t[0].clone().scatter_add(0, &index, t[1].clone()).pow(2.0),
+            &[x, src],
+        );
+    }
+
+    #[test]
+    fn test_masks() {
+        let x = matrix("x");
+        let mask = [true, false, false, true, true, false];
+
+        let selected = x.clone().masked_select(&mask);
+        assert_eq!(*selected.data, [x.data[0], x.data[3], x.data[4]]);
+        let filled = x.clone().masked_fill(&mask, f32::NEG_INFINITY);
+        assert_eq!(filled.get(&[0, 0]), f32::NEG_INFINITY);
+        assert_eq!(filled.get(&[0, 1]), x.get(&[0, 1]));
+
+        assert_gradients(
+            |t| t[0].clone().masked_select(&mask).pow(2.0),
+            std::slice::from_ref(&x),
+        );
+        assert_gradients(|t| t[0].clone().masked_fill(&mask, 2.0).pow(2.0), &[x]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_index_out_of_range() {
+        matrix("x").index_select(0, &[2]);
+    }
+}
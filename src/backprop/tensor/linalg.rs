@@ -0,0 +1,281 @@
+use super::{record, Tensor};
+use crate::linalg::{adjugate, cholesky, matmul, solve_lower, transpose, Lu};
+
+/*
+ * Linear algebra on square matrices, each recorded as a single tape entry
+ * with a closed-form adjoint (Giles, 2008) instead of the elementary
+ * operations of the factorization.
+ */
+
+impl Tensor {
+    pub(crate) fn square(&self, op: &str) -> usize {
+        let n = self.shape.first().copied().unwrap_or(0);
+        assert!(
+            self.shape == [n, n],
+            "{} of a tensor of shape {:?}",
+            op,
+            self.shape
+        );
+        n
+    }
+
+    // Only for the ops that need A^-1; `det` and `logdet` factor any A.
+    fn lu(&self, op: &str) -> Lu {
+        let n = self.square(op);
+        Lu::new(&self.data, n).unwrap_or_else(|| panic!("{} of a singular matrix", op))
+    }
+
+    /*
+     * X with A X = B, for B of shape [n] or [n, m].
+     * B' = A^-T X', A' = -B' X^T.
+     */
+    pub fn solve(self, b: Tensor) -> Tensor {
+        let lu = self.lu("solve");
+        let n = lu.n;
+        assert!(
+            b.shape.first() == Some(&n) && b.ndim() <= 2,
+            "cannot solve a system of shape {:?} for {:?}",
+            self.shape,
+            b.shape
+        );
+        let m = b.shape.get(1).copied().unwrap_or(1);
+        let result = Tensor::new(&b.shape, lu.solve(&b.data, m), None);
+
+        let (x, b_shape) = (result.data.clone(), b.shape.clone());
+        let backward = move |dloss_dresult: &Tensor| {
+            let dloss_db = lu.solve_transposed(&dloss_dresult.data, m);
+            let dloss_da = matmul(&dloss_db, &transpose(&x, n, m), n, m, n);
+            vec![
+                Tensor::new(&[n, n], dloss_da.iter().map(|d| -d).collect(), None),
+                Tensor::new(&b_shape, dloss_db, None),
+            ]
+        };
+        record("solve", vec![self, b], result, backward)
+    }
+
+    // Y = A^-1, with A' = -Y^T Y' Y^T.
+    pub fn inv(self) -> Tensor {
+        let lu = self.lu("inv");
+        let n = lu.n;
+        let result = Tensor::new(&[n, n], lu.inverse(), None);
+
+        let y_t = transpose(&result.data, n, n);
+        let backward = move |dloss_dresult: &Tensor| {
+            let left = matmul(&y_t, &dloss_dresult.data, n, n, n);
+            let dloss_da = matmul(&left, &y_t, n, n, n);
+            vec![Tensor::new(
+                &[n, n],
+                dloss_da.iter().map(|d| -d).collect(),
+                None,
+            )]
+        };
+        record("inv", vec![self], result, backward)
+    }
+
+    /*
+     * det A, with A' = det' adj(A)^T, which is det(A) A^-T for invertible A
+     * and stays defined for singular A, where det A = 0.
+     */
+    pub fn det(self) -> Tensor {
+        let n = self.square("det");
+        let result = Tensor::scalar(Lu::factor(&self.data, n).det(), None);
+
+        let a = self.data.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let scale = dloss_dresult.item();
+            let adj_t = transpose(&adjugate(&a, n), n, n);
+            vec![Tensor::new(
+                &[n, n],
+                adj_t.iter().map(|x| scale * x).collect(),
+                None,
+            )]
+        };
+        record("det", vec![self], result, backward)
+    }
+
+    /*
+     * ln |det A|, the log-determinant for the positive definite matrices
+     * it is mostly used on, with A' = logdet' A^-T. A singular A gives
+     * -inf, and an adjoint adj(A)^T / 0 that is not finite.
+     */
+    pub fn logdet(self) -> Tensor {
+        let n = self.square("logdet");
+        let lu = Lu::factor(&self.data, n);
+        let result = Tensor::scalar(lu.ln_abs_det(), None);
+
+        let a = self.data.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let scale = dloss_dresult.item();
+            let inv = if lu.is_singular() {
+                adjugate(&a, n).iter().map(|x| x / 0.0).collect()
+            } else {
+                lu.inverse()
+            };
+            let inv_t = transpose(&inv, n, n);
+            vec![Tensor::new(
+                &[n, n],
+                inv_t.iter().map(|x| scale * x).collect(),
+                None,
+            )]
+        };
+        record("logdet", vec![self], result, backward)
+    }
+
+    /*
+     * Lower triangular L with A = L L^T, for symmetric positive definite A;
+     * only the lower triangle of A is read. With Phi taking the lower
+     * triangle and halving the diagonal, S = L^-T Phi(L^T L') L^-1 and the
+     * adjoint is the symmetric (S + S^T) / 2 (Murray, 2016), as A can
+     * only move along symmetric directions.
+     */
+    pub fn cholesky(self) -> Tensor {
+        let n = self.square("cholesky");
+        let l = cholesky(&self.data, n)
+            .unwrap_or_else(|| panic!("cholesky of a matrix that is not positive definite"));
+        let result = Tensor::new(&[n, n], l, None);
+
+        let l = result.data.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut phi = matmul(&transpose(&l, n, n), &dloss_dresult.data, n, n, n);
+            for i in 0..n {
+                phi[i * n + i] *= 0.5;
+                for j in i + 1..n {
+                    phi[i * n + j] = 0.0;
+                }
+            }
+            // S = L^-T (L^-T Phi^T)^T.
+            let right = solve_lower(&l, &transpose(&phi, n, n), n, n, true);
+            let s = solve_lower(&l, &transpose(&right, n, n), n, n, true);
+            let mut dloss_da = vec![0.0; n * n];
+            for i in 0..n {
+                for j in 0..n {
+                    dloss_da[i * n + j] = 0.5 * (s[i * n + j] + s[j * n + i]);
+                }
+            }
+            vec![Tensor::new(&[n, n], dloss_da, None)]
+        };
+        record("cholesky", vec![self], result, backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{assert_gradients, random_tensor};
+    use super::*;
+    use crate::linalg::testing::random;
+
+    // X X^T + n I: an SPD matrix whose gradchecks move along symmetric
+    // directions only.
+    fn spd(x: &Tensor) -> Tensor {
+        let n = x.shape[0];
+        let mut eye = vec![0.0; n * n];
+        for i in 0..n {
+            eye[i * n + i] = n as f32;
+        }
+        x.clone().matmul(x.clone().transpose()) + Tensor::new(&[n, n], eye, None)
+    }
+
+    #[test]
+    fn test_values() {
+        let a = Tensor::new(&[2, 2], vec![4.0, 2.0, 2.0, 3.0], None);
+
+        assert!((a.clone().det().item() - 8.0).abs() < 1e-5);
+        assert!((a.clone().logdet().item() - 8f32.ln()).abs() < 1e-5);
+        assert_eq!(*a.clone().cholesky().data, [2.0, 0.0, 1.0, 2f32.sqrt()]);
+
+        let x = a.clone().solve(Tensor::new(&[2], vec![2.0, 1.0], None));
+        assert_eq!(x.shape, [2]);
+        assert!((x.data[0] - 0.5).abs() < 1e-6 && x.data[1].abs() < 1e-6);
+
+        let inv = a.inv();
+        assert!((inv.get(&[0, 1]) + 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_solve_gradients() {
+        let x = random_tensor(&[3, 3], 1, "x");
+        let b = random_tensor(&[3, 2], 2, "b");
+        let v = random_tensor(&[3], 3, "v");
+
+        assert_gradients(|t| spd(&t[0]).solve(t[1].clone()), &[x.clone(), b]);
+        assert_gradients(|t| spd(&t[0]).solve(t[1].clone()), &[x.clone(), v]);
+
+        // Unsymmetric systems exercise the transposed solve.
+        let mut data = random(9, 4);
+        for i in 0..3 {
+            data[i * 3 + i] += 2.0;
+        }
+        let a = Tensor::new(&[3, 3], data, Some("a".to_string()));
+        let b = random_tensor(&[3, 2], 5, "b");
+        assert_gradients(|t| t[0].clone().solve(t[1].clone()), &[a, b]);
+    }
+
+    #[test]
+    fn test_inv_gradients() {
+        let x = random_tensor(&[3, 3], 6, "x");
+        let weights = random_tensor(&[3, 3], 7, "w");
+
+        assert_gradients(|t| spd(&t[0]).inv() * t[1].clone(), &[x, weights]);
+    }
+
+    #[test]
+    fn test_det_gradients() {
+        let x = random_tensor(&[3, 3], 8, "x");
+
+        assert_gradients(|t| spd(&t[0]).det(), std::slice::from_ref(&x));
+        assert_gradients(|t| spd(&t[0]).logdet(), &[x]);
+    }
+
+    #[test]
+    fn test_cholesky_gradients() {
+        let x = random_tensor(&[4, 4], 9, "x");
+        let weights = random_tensor(&[4, 4], 10, "w");
+
+        assert_gradients(|t| spd(&t[0]).cholesky() * t[1].clone(), &[x, weights]);
+    }
+
+    #[test]
+    fn test_gaussian_log_likelihood() {
+        // -1/2 y^T K^-1 y - 1/2 ln det K through the Cholesky factor.
+        let x = random_tensor(&[3, 3], 11, "x");
+        let y = random_tensor(&[3, 1], 12, "y");
+
+        assert_gradients(
+            |t| {
+                let l = spd(&t[0]).cholesky();
+                let alpha = l.clone().solve(t[1].clone());
+                let half = Tensor::scalar(-0.5, None);
+                let fit = (alpha.clone() * alpha).sum() * half.clone();
+                fit - l.logdet()
+            },
+            &[x, y],
+        );
+    }
+
+    #[test]
+    fn test_singular_det() {
+        // Rank 2, where det A = 0 but its gradient adj(A)^T does not vanish.
+        let a = Tensor::new(
+            &[3, 3],
+            vec![1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 1.0, 0.0, 1.0],
+            Some("a".to_string()),
+        );
+        assert_eq!(a.clone().det().item(), 0.0);
+        assert_eq!(a.clone().logdet().item(), f32::NEG_INFINITY);
+        assert_gradients(|t| t[0].clone().det(), std::slice::from_ref(&a));
+
+        // Rank 1, where the gradient vanishes too.
+        let b = Tensor::new(
+            &[3, 3],
+            vec![1.0, 2.0, 4.0, 2.0, 4.0, 8.0, 4.0, 8.0, 16.0],
+            Some("b".to_string()),
+        );
+        assert_gradients(|t| t[0].clone().det(), &[b]);
+    }
+
+    #[test]
+    #[should_panic(expected = "inv of a singular matrix")]
+    fn test_singular() {
+        Tensor::new(&[2, 2], vec![1.0, 2.0, 2.0, 4.0], None).inv();
+    }
+}
@@ -0,0 +1,254 @@
+use super::Tensor;
+
+/*
+ * Loss functions, composed from tensor operations so their gradients come
+ * from the tape. Each takes predictions and targets of the same shape
+ * (class indices for `cross_entropy`) and a `Reduction` of the
+ * per-element losses.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Reduction {
+    // The per-element losses, in the shape of the predictions.
+    None,
+    #[default]
+    Mean,
+    Sum,
+}
+
+fn reduce(losses: Tensor, reduction: Reduction) -> Tensor {
+    match reduction {
+        Reduction::None => losses,
+        Reduction::Mean => losses.mean(),
+        Reduction::Sum => losses.sum(),
+    }
+}
+
+fn check(op: &str, input: &Tensor, target: &Tensor) {
+    assert_eq!(
+        input.shape, target.shape,
+        "{} of {:?} against targets {:?}",
+        op, input.shape, target.shape
+    );
+}
+
+fn constant(value: f32) -> Tensor {
+    Tensor::scalar(value, None)
+}
+
+// Mean squared error, (x - y)^2.
+pub fn mse(input: Tensor, target: Tensor, reduction: Reduction) -> Tensor {
+    check("mse", &input, &target);
+    reduce((input - target).pow(2.0), reduction)
+}
+
+// Mean absolute error, |x - y|.
+pub fn mae(input: Tensor, target: Tensor, reduction: Reduction) -> Tensor {
+    check("mae", &input, &target);
+    reduce((input - target).abs(), reduction)
+}
+
+/*
+ * Huber loss: quadratic for errors up to `delta`, linear beyond,
+ *   d^2 / 2 if |d| <= delta, else delta (|d| - delta / 2).
+ * With q = min(|d|, delta) that is q^2 / 2 + delta (|d| - q).
+ */
+pub fn huber(input: Tensor, target: Tensor, delta: f32, reduction: Reduction) -> Tensor {
+    check("huber", &input, &target);
+    let a = (input - target).abs();
+    let q = a.clone() - (a.clone() - constant(delta)).relu();
+    let losses = q.clone().pow(2.0) * constant(0.5) + constant(delta) * (a - q);
+    reduce(losses, reduction)
+}
+
+/*
+ * Binary cross-entropy of sigmoid(x) against targets in [0, 1], from the
+ * logits x. The form max(x, 0) - x y + ln(1 + exp(-|x|)) never
+ * exponentiates a positive number.
+ */
+pub fn bce_with_logits(logits: Tensor, target: Tensor, reduction: Reduction) -> Tensor {
+    check("bce_with_logits", &logits, &target);
+    let softplus = ((-logits.clone().abs()).exp() + constant(1.0)).ln();
+    let losses = logits.clone().relu() - logits * target + softplus;
+    reduce(losses, reduction)
+}
+
+/*
+ * Cross-entropy of softmax(logits) against class indices, for [batch,
+ * classes] logits and one target per row: logsumexp(x) - x[target].
+ */
+pub fn cross_entropy(logits: Tensor, targets: &[usize], reduction: Reduction) -> Tensor {
+    assert!(
+        logits.ndim() == 2 && logits.shape[0] == targets.len(),
+        "cross_entropy of {:?} against {} targets",
+        logits.shape,
+        targets.len()
+    );
+    let n = targets.len();
+    let picked = logits.log_softmax(1).gather(1, targets, &[n, 1]);
+    reduce(-picked.reshape(&[n]), reduction)
+}
+
+// Hinge loss of scores against labels of -1 or 1, max(0, 1 - x y).
+pub fn hinge(scores: Tensor, target: Tensor, reduction: Reduction) -> Tensor {
+    check("hinge", &scores, &target);
+    reduce((constant(1.0) - scores * target).relu(), reduction)
+}
+
+/*
+ * Kullback-Leibler divergence KL(p || q) of the target distribution p
+ * from q, given as ln q like the output of a log-softmax:
+ *   p (ln p - ln q), with 0 ln 0 = 0.
+ * The mean is over elements; sum and divide by the batch for the mean
+ * divergence per distribution.
+ */
+pub fn kl_div(log_q: Tensor, p: Tensor, reduction: Reduction) -> Tensor {
+    check("kl_div", &log_q, &p);
+    // ln 1 in place of ln 0 makes 0 ln 0 = 0, on the tape like the rest.
+    let zeros: Vec<bool> = p.data.iter().map(|&p| p <= 0.0).collect();
+    let ln_p = p.clone().masked_fill(&zeros, 1.0).ln();
+    reduce(p * (ln_p - log_q), reduction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{assert_gradients, random_tensor};
+    use super::*;
+    use crate::linalg::testing::random;
+
+    fn close(t: &Tensor, expected: &[f32]) -> bool {
+        t.data
+            .iter()
+            .zip(expected)
+            .all(|(a, b)| (a - b).abs() < 1e-5)
+    }
+
+    #[test]
+    fn test_regression_losses() {
+        let x = Tensor::new(&[4], vec![0.0, 1.0, 3.0, -2.0], None);
+        let y = Tensor::new(&[4], vec![0.5, 1.0, 0.0, 0.0], None);
+
+        assert!(close(&mse(x.clone(), y.clone(), Reduction::Sum), &[13.25]));
+        assert!(close(
+            &mae(x.clone(), y.clone(), Reduction::Mean),
+            &[5.5 / 4.0]
+        ));
+        let h = huber(x, y, 1.0, Reduction::None);
+        assert_eq!(h.shape, [4]);
+        assert!(close(&h, &[0.125, 0.0, 2.5, 1.5]));
+    }
+
+    #[test]
+    fn test_regression_gradients() {
+        let x = random_tensor(&[2, 3], 1, "x");
+        let y = random_tensor(&[2, 3], 2, "y");
+        for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+            assert_gradients(
+                |t| mse(t[0].clone(), t[1].clone(), reduction),
+                &[x.clone(), y.clone()],
+            );
+            assert_gradients(
+                |t| mae(t[0].clone(), t[1].clone(), reduction),
+                &[x.clone(), y.clone()],
+            );
+        }
+        // Errors on both sides of delta.
+        assert_gradients(
+            |t| huber(t[0].clone(), t[1].clone(), 0.4, Reduction::Sum),
+            &[x, y],
+        );
+    }
+
+    #[test]
+    fn test_bce_with_logits() {
+        let logits = Tensor::new(&[3], vec![0.0, 2.0, -100.0], None);
+        let targets = Tensor::new(&[3], vec![1.0, 0.0, 0.0], None);
+        let losses = bce_with_logits(logits, targets, Reduction::None);
+
+        let softplus_2 = (1.0 + 2f32.exp()).ln();
+        assert!(close(&losses, &[2f32.ln(), softplus_2, 0.0]));
+
+        // Logits far enough apart to exercise both branches of |x|.
+        let logits = random(6, 3).into_iter().map(|x| 4.0 * x).collect();
+        let x = Tensor::new(&[2, 3], logits, Some("x".to_string()));
+        let y = Tensor::new(
+            &[2, 3],
+            vec![0.0, 1.0, 0.3, 1.0, 0.0, 0.8],
+            Some("y".to_string()),
+        );
+        assert_gradients(
+            |t| bce_with_logits(t[0].clone(), t[1].clone(), Reduction::Mean),
+            &[x, y],
+        );
+    }
+
+    #[test]
+    fn test_cross_entropy() {
+        // Uniform logits cost ln(classes), whatever their size.
+        let logits = Tensor::new(&[2, 3], vec![0.0, 0.0, 0.0, 1000.0, 1000.0, 1000.0], None);
+        let losses = cross_entropy(logits, &[0, 2], Reduction::None);
+        assert!(close(&losses, &[3f32.ln(), 3f32.ln()]));
+
+        let logits = random_tensor(&[4, 3], 4, "logits");
+        for reduction in [Reduction::Mean, Reduction::Sum] {
+            assert_gradients(
+                |t| cross_entropy(t[0].clone(), &[2, 0, 1, 2], reduction),
+                std::slice::from_ref(&logits),
+            );
+        }
+    }
+
+    #[test]
+    fn test_hinge() {
+        let scores = Tensor::new(&[3], vec![2.0, 0.5, -0.5], None);
+        let labels = Tensor::new(&[3], vec![1.0, 1.0, 1.0], None);
+        assert!(close(
+            &hinge(scores, labels, Reduction::None),
+            &[0.0, 0.5, 1.5]
+        ));
+
+        let scores = Tensor::new(&[4], vec![1.7, 0.3, -0.6, -1.4], Some("s".to_string()));
+        let labels = Tensor::new(&[4], vec![1.0, 1.0, 1.0, -1.0], None);
+        assert_gradients(
+            |t| hinge(t[0].clone(), labels.clone(), Reduction::Sum),
+            &[scores],
+        );
+    }
+
+    #[test]
+    fn test_kl_div() {
+        let p = Tensor::new(&[2], vec![0.5, 0.5], None);
+        let same = Tensor::new(&[2], vec![0.5f32.ln(), 0.5f32.ln()], None);
+        assert!(close(&kl_div(same, p.clone(), Reduction::Sum), &[0.0]));
+
+        // A zero in p contributes nothing.
+        let p = Tensor::new(
+            &[2, 3],
+            vec![0.2, 0.8, 0.0, 0.3, 0.3, 0.4],
+            Some("p".to_string()),
+        );
+        let logits = random_tensor(&[2, 3], 5, "logits");
+        assert_gradients(
+            |t| kl_div(t[0].clone().log_softmax(1), p.clone(), Reduction::Sum),
+            std::slice::from_ref(&logits),
+        );
+        let q = Tensor::new(&[2, 3], vec![0.3, 0.3, 0.4, 0.1, 0.6, 0.3], None);
+        let loss = kl_div(q.ln(), p, Reduction::Sum);
+        let expected = 0.2 * (0.2f32 / 0.3).ln()
+            + 0.8 * (0.8f32 / 0.3).ln()
+            + 0.3 * 3f32.ln()
+            + 0.3 * 0.5f32.ln()
+            + 0.4 * (0.4f32 / 0.3).ln();
+        assert!(close(&loss, &[expected]));
+
+        // Both arguments on the tape, for a p without zeros.
+        let p = random_tensor(&[2, 3], 6, "p").softmax(1);
+        let p = Tensor::new(&p.shape, p.data.to_vec(), Some("p".to_string()));
+        for reduction in [Reduction::None, Reduction::Mean, Reduction::Sum] {
+            assert_gradients(
+                |t| kl_div(t[0].clone().log_softmax(1), t[1].clone(), reduction),
+                &[logits.clone(), p.clone()],
+            );
+        }
+    }
+}
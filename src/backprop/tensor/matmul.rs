@@ -0,0 +1,157 @@
+use super::{record, Tensor};
+use std::sync::Arc;
+
+/*
+ * Matrix products. Leading axes are batch axes and must agree; the last
+ * two are multiplied as matrices. For C = A B the adjoints are
+ * dA = dC B^T and dB = A^T dC.
+ */
+
+// c[m x n] += a[m x k] b[k x n], with either factor stored transposed.
+fn gemm(a: (&[f32], bool), b: (&[f32], bool), c: &mut [f32], (m, k, n): (usize, usize, usize)) {
+    let ((a, a_transposed), (b, b_transposed)) = (a, b);
+    for i in 0..m {
+        for p in 0..k {
+            let a_ip = if a_transposed {
+                a[p * m + i]
+            } else {
+                a[i * k + p]
+            };
+            for j in 0..n {
+                let b_pj = if b_transposed {
+                    b[j * k + p]
+                } else {
+                    b[p * n + j]
+                };
+                c[i * n + j] += a_ip * b_pj;
+            }
+        }
+    }
+}
+
+impl Tensor {
+    pub fn matmul(self, rhs: Tensor) -> Tensor {
+        let (nd, rhs_nd) = (self.ndim(), rhs.ndim());
+        assert!(
+            nd >= 2 && nd == rhs_nd && self.shape[..nd - 2] == rhs.shape[..nd - 2],
+            "cannot multiply shapes {:?} and {:?}",
+            self.shape,
+            rhs.shape
+        );
+        let (m, k, n) = (self.shape[nd - 2], self.shape[nd - 1], rhs.shape[nd - 1]);
+        assert_eq!(
+            k,
+            rhs.shape[nd - 2],
+            "cannot multiply shapes {:?} and {:?}",
+            self.shape,
+            rhs.shape
+        );
+        let batch: usize = self.shape[..nd - 2].iter().product();
+
+        let mut values = vec![0.0; batch * m * n];
+        for b in 0..batch {
+            gemm(
+                (&self.data[b * m * k..(b + 1) * m * k], false),
+                (&rhs.data[b * k * n..(b + 1) * k * n], false),
+                &mut values[b * m * n..(b + 1) * m * n],
+                (m, k, n),
+            );
+        }
+        let mut out_shape = self.shape.clone();
+        out_shape[nd - 1] = n;
+        let result = Tensor::new(&out_shape, values, None);
+
+        let (a, a_shape) = (Arc::clone(&self.data), self.shape.clone());
+        let (b_data, b_shape) = (Arc::clone(&rhs.data), rhs.shape.clone());
+        let backward = move |dloss_dresult: &Tensor| {
+            let g = &dloss_dresult.data;
+            let mut dloss_da = vec![0.0; batch * m * k];
+            let mut dloss_db = vec![0.0; batch * k * n];
+            for b in 0..batch {
+                let (a, bm) = (
+                    &a[b * m * k..(b + 1) * m * k],
+                    &b_data[b * k * n..(b + 1) * k * n],
+                );
+                let g = &g[b * m * n..(b + 1) * m * n];
+                gemm(
+                    (g, false),
+                    (bm, true),
+                    &mut dloss_da[b * m * k..(b + 1) * m * k],
+                    (m, n, k),
+                );
+                gemm(
+                    (a, true),
+                    (g, false),
+                    &mut dloss_db[b * k * n..(b + 1) * k * n],
+                    (k, m, n),
+                );
+            }
+            vec![
+                Tensor::new(&a_shape, dloss_da, None),
+                Tensor::new(&b_shape, dloss_db, None),
+            ]
+        };
+        record("matmul", vec![self, rhs], result, backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_gradients;
+    use super::*;
+
+    fn tensor(shape: &[usize], name: &str) -> Tensor {
+        let len = shape.iter().product();
+        let data = (0..len).map(|i| ((i * 5) % 7) as f32 * 0.3 - 0.9).collect();
+        Tensor::new(shape, data, Some(name.to_string()))
+    }
+
+    #[test]
+    fn test_matmul() {
+        let a = Tensor::new(&[2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], None);
+        let b = Tensor::new(&[3, 2], vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0], None);
+        let c = a.matmul(b);
+
+        assert_eq!(c.shape, [2, 2]);
+        assert_eq!(*c.data, [58.0, 64.0, 139.0, 154.0]);
+    }
+
+    #[test]
+    fn test_matmul_gradients() {
+        let a = tensor(&[2, 3], "a");
+        let b = tensor(&[3, 4], "b");
+
+        assert_gradients(|t| t[0].clone().matmul(t[1].clone()).pow(2.0), &[a, b]);
+    }
+
+    #[test]
+    fn test_batched_matmul_gradients() {
+        let a = tensor(&[2, 3, 2], "a");
+        let b = tensor(&[2, 2, 3], "b");
+
+        assert_eq!(a.clone().matmul(b.clone()).shape, [2, 3, 3]);
+        assert_gradients(|t| t[0].clone().matmul(t[1].clone()).pow(2.0), &[a, b]);
+    }
+
+    #[test]
+    fn test_dense_layer() {
+        // x W + b over a batch of rows, then a mean squared loss per row.
+        let x = tensor(&[4, 3], "x");
+        let w = tensor(&[3, 2], "w");
+        let b = Tensor::new(&[2], vec![0.1, -0.2], Some("b".to_string()));
+
+        assert_gradients(
+            |t| {
+                let y = t[0].clone().matmul(t[1].clone()) + t[2].clone();
+                y.tanh().pow(2.0).mean_axis(1, false)
+            },
+            &[x, w, b],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot multiply")]
+    fn test_matmul_mismatch() {
+        tensor(&[2, 3], "a").matmul(tensor(&[2, 3], "b"));
+    }
+}
@@ -0,0 +1,340 @@
+pub mod broadcast;
+pub mod conv;
+pub mod elementwise;
+pub mod index;
+pub mod linalg;
+pub mod loss;
+pub mod matmul;
+pub mod reduce;
+pub mod shape;
+pub mod softmax;
+pub mod spectral;
+
+use super::globals::{TENSOR_NAME_IDX, TENSOR_TAPE};
+use super::tape::{GradientTape, Node, TapeEntry};
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use std::thread::LocalKey;
+
+/*
+ * An array-valued variable: a shape and its elements, contiguous in
+ * row-major order.
+ *
+ * Every tensor operation records a single entry on the tensor tape, no
+ * matter how many elements it touches, and its adjoints are tensors of
+ * the same shape. The elements are shared, so clones are cheap.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    pub shape: Vec<usize>,
+    pub data: Arc<Vec<f32>>,
+    pub name: String,
+}
+
+impl Tensor {
+    pub fn new(shape: &[usize], data: Vec<f32>, name: Option<String>) -> Self {
+        assert_eq!(
+            shape.iter().product::<usize>(),
+            data.len(),
+            "shape {:?} does not match {} elements",
+            shape,
+            data.len()
+        );
+        let name = match name {
+            Some(n) => n,
+            None => {
+                let idx = TENSOR_NAME_IDX.get();
+                TENSOR_NAME_IDX.set(idx + 1);
+                format!("t{}", idx)
+            }
+        };
+
+        Tensor {
+            shape: shape.to_vec(),
+            data: Arc::new(data),
+            name,
+        }
+    }
+
+    // A tensor of shape [], holding a single value.
+    pub fn scalar(value: f32, name: Option<String>) -> Self {
+        Tensor::new(&[], vec![value], name)
+    }
+
+    pub fn full(shape: &[usize], value: f32) -> Self {
+        Tensor::new(shape, vec![value; shape.iter().product()], None)
+    }
+
+    pub fn zeros(shape: &[usize]) -> Self {
+        Tensor::full(shape, 0.0)
+    }
+
+    pub fn ones(shape: &[usize]) -> Self {
+        Tensor::full(shape, 1.0)
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // The value of a tensor with a single element.
+    pub fn item(&self) -> f32 {
+        assert_eq!(
+            self.len(),
+            1,
+            "item() of a tensor of shape {:?}",
+            self.shape
+        );
+        self.data[0]
+    }
+
+    pub fn get(&self, index: &[usize]) -> f32 {
+        assert_eq!(
+            index.len(),
+            self.ndim(),
+            "index {:?} into shape {:?}",
+            index,
+            self.shape
+        );
+        for (&i, &n) in index.iter().zip(&self.shape) {
+            assert!(i < n, "index {:?} into shape {:?}", index, self.shape);
+        }
+        let offset = index
+            .iter()
+            .zip(broadcast::strides(&self.shape))
+            .map(|(i, stride)| i * stride)
+            .sum::<usize>();
+        self.data[offset]
+    }
+}
+
+impl Node for Tensor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn values(&self) -> &[f32] {
+        &self.data
+    }
+
+    fn seed(&self) -> Self {
+        Tensor::ones(&self.shape)
+    }
+
+    fn accumulate(&mut self, other: &Self) {
+        assert_eq!(self.shape, other.shape);
+        for (x, y) in Arc::make_mut(&mut self.data)
+            .iter_mut()
+            .zip(other.data.iter())
+        {
+            *x += y;
+        }
+    }
+
+    fn tape() -> &'static LocalKey<RefCell<GradientTape<Self>>> {
+        &TENSOR_TAPE
+    }
+
+    fn counter() -> &'static LocalKey<Cell<usize>> {
+        &TENSOR_NAME_IDX
+    }
+}
+
+/*
+ * Records an operation with a single output. `backward` maps the adjoint
+ * of the output to one adjoint per input, shaped like that input.
+ */
+pub(crate) fn record<B>(
+    op: &'static str,
+    inputs: Vec<Tensor>,
+    output: Tensor,
+    backward: B,
+) -> Tensor
+where
+    B: Fn(&Tensor) -> Vec<Tensor> + Clone + Send + Sync + 'static,
+{
+    let backward = move |dloss_doutputs: &[Tensor]| backward(&dloss_doutputs[0]);
+    record_outputs(op, inputs, vec![output], backward).remove(0)
+}
+
+/*
+ * The same for several outputs. Outputs the loss does not depend on get
+ * an adjoint of zeros, so `backward` always sees one per output.
+ */
+pub(crate) fn record_outputs<B>(
+    op: &'static str,
+    inputs: Vec<Tensor>,
+    outputs: Vec<Tensor>,
+    backward: B,
+) -> Vec<Tensor>
+where
+    B: Fn(&[Tensor]) -> Vec<Tensor> + Clone + Send + Sync + 'static,
+{
+    let names = |tensors: &[Tensor]| {
+        tensors
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    println!(
+        "{} = {}({}) : {}",
+        names(&outputs),
+        op,
+        names(&inputs),
+        outputs
+            .iter()
+            .map(|x| format!("{:?}", x.shape))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let shapes: Vec<Vec<usize>> = outputs.iter().map(|x| x.shape.clone()).collect();
+    let propagate = move |dloss_doutputs: &Vec<Option<Tensor>>| -> Vec<Tensor> {
+        let dloss_doutputs: Vec<Tensor> = dloss_doutputs
+            .iter()
+            .zip(&shapes)
+            .map(|(d, shape)| d.clone().unwrap_or_else(|| Tensor::zeros(shape)))
+            .collect();
+        backward(&dloss_doutputs)
+    };
+
+    let tape_entry = TapeEntry::new(op, inputs, outputs.clone(), Box::new(propagate));
+    TENSOR_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
+
+    outputs
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use crate::backprop::grad::backpropagate;
+    use crate::backprop::tape::scoped_on;
+    use crate::gradcheck::{directional_difference, unit, Error};
+    use crate::linalg::testing::random;
+    use std::collections::HashMap;
+
+    // A named tensor of the given shape with entries uniform in [-1, 1).
+    pub(crate) fn random_tensor(shape: &[usize], seed: u64, name: &str) -> Tensor {
+        let len = shape.iter().product();
+        Tensor::new(shape, random(len, seed), Some(name.to_string()))
+    }
+
+    /*
+     * Compares the reverse-mode gradient of sum(f(inputs)) with central
+     * differences, element by element.
+     */
+    pub(crate) fn assert_gradients(f: impl Fn(&[Tensor]) -> Tensor, inputs: &[Tensor]) {
+        let (output, tape) = scoped_on::<Tensor, _>(|| f(inputs));
+        let seeds = HashMap::from([(output.name.clone(), output.seed())]);
+        let adjoints = backpropagate(&tape.entries, seeds);
+
+        let total =
+            |inputs: &[Tensor]| -> f32 { scoped_on::<Tensor, _>(|| f(inputs)).0.data.iter().sum() };
+        for (k, input) in inputs.iter().enumerate() {
+            let total_at = |data: &[f32]| {
+                let mut inputs = inputs.to_vec();
+                inputs[k] = Tensor::new(&input.shape, data.to_vec(), Some(input.name.clone()));
+                vec![total(&inputs)]
+            };
+            for i in 0..input.len() {
+                let numerical =
+                    directional_difference(total_at, &input.data, &unit(input.len(), i), 1e-2)[0];
+                let reverse = adjoints.get(&input.name).map_or(0.0, |d| d.data[i]);
+                assert!(
+                    Error::new(reverse, numerical).within(1e-2),
+                    "input {} element {}: reverse {} != numerical {}",
+                    k,
+                    i,
+                    reverse,
+                    numerical
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backprop::grad::grad;
+    use crate::backprop::variable::Variable;
+
+    #[test]
+    fn test_new() {
+        let x = Tensor::new(
+            &[2, 3],
+            (0..6).map(|i| i as f32).collect(),
+            Some("x".to_string()),
+        );
+
+        assert_eq!(x.name, "x");
+        assert_eq!(x.ndim(), 2);
+        assert_eq!(x.len(), 6);
+        assert_eq!(x.get(&[1, 2]), 5.0);
+        assert_eq!(Tensor::scalar(4.0, None).item(), 4.0);
+        assert!(Tensor::zeros(&[3]).name.starts_with('t'));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not match")]
+    fn test_new_shape_mismatch() {
+        Tensor::new(&[2, 2], vec![1.0], None);
+    }
+
+    #[test]
+    #[should_panic(expected = "index [0, 5] into shape [2, 3]")]
+    fn test_get_out_of_range() {
+        Tensor::zeros(&[2, 3]).get(&[0, 5]);
+    }
+
+    #[test]
+    fn test_clones_share_data() {
+        let x = Tensor::ones(&[1000]);
+        let y = x.clone();
+
+        assert!(Arc::ptr_eq(&x.data, &y.data));
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let mut x = Tensor::ones(&[2]);
+        let y = x.clone();
+        x.accumulate(&Tensor::new(&[2], vec![1.0, 2.0], None));
+
+        assert_eq!(*x.data, [2.0, 3.0]);
+        assert_eq!(*y.data, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_grad_of_leaf() {
+        let x = Tensor::new(&[2], vec![1.0, 2.0], Some("x".to_string()));
+        let dx = grad(&x, std::slice::from_ref(&x));
+
+        assert_eq!(*dx[0].as_ref().unwrap().data, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_clear_keeps_the_other_counter() {
+        Variable::tape().with_borrow_mut(|tape| tape.clear());
+        let x = Variable::new(3.0, Some("x".to_string()));
+        let y = x.clone() * x.clone();
+
+        // Clearing the tensor tape must not hand out y's name again.
+        Tensor::tape().with_borrow_mut(|tape| tape.clear());
+        let z = y.clone() * x.clone();
+        assert_ne!(y.name, z.name);
+
+        let dz_dx = grad(&z, std::slice::from_ref(&x));
+        assert_eq!(dz_dx[0].as_ref().unwrap().value, 27.0);
+    }
+}
@@ -0,0 +1,181 @@
+use super::{record, Tensor};
+
+/*
+ * Reductions, over all elements or along one axis. An axis splits a
+ * row-major array into `outer` blocks of `n` slices of `inner` elements;
+ * reducing it leaves `outer * inner` elements.
+ */
+
+pub(crate) fn split_at_axis(shape: &[usize], axis: usize) -> (usize, usize, usize) {
+    assert!(axis < shape.len(), "axis {} of shape {:?}", axis, shape);
+    let outer = shape[..axis].iter().product();
+    let inner = shape[axis + 1..].iter().product();
+    (outer, shape[axis], inner)
+}
+
+pub(crate) fn reduced_shape(shape: &[usize], axis: usize, keepdim: bool) -> Vec<usize> {
+    let mut reduced = shape.to_vec();
+    if keepdim {
+        reduced[axis] = 1;
+    } else {
+        reduced.remove(axis);
+    }
+    reduced
+}
+
+impl Tensor {
+    // Sum of all elements, as a tensor of shape [].
+    pub fn sum(self) -> Tensor {
+        let result = Tensor::scalar(self.data.iter().sum(), None);
+
+        let shape = self.shape.clone();
+        let backward =
+            move |dloss_dresult: &Tensor| vec![Tensor::full(&shape, dloss_dresult.item())];
+        record("sum", vec![self], result, backward)
+    }
+
+    pub fn mean(self) -> Tensor {
+        let n = self.len() as f32;
+        let result = Tensor::scalar(self.data.iter().sum::<f32>() / n, None);
+
+        let shape = self.shape.clone();
+        let backward =
+            move |dloss_dresult: &Tensor| vec![Tensor::full(&shape, dloss_dresult.item() / n)];
+        record("mean", vec![self], result, backward)
+    }
+
+    // Sums along `axis`, which is dropped unless `keepdim`.
+    pub fn sum_axis(self, axis: usize, keepdim: bool) -> Tensor {
+        self.reduce_axis("sum_axis", axis, keepdim, 1.0)
+    }
+
+    pub fn mean_axis(self, axis: usize, keepdim: bool) -> Tensor {
+        let n = self.shape.get(axis).copied().unwrap_or(1) as f32;
+        self.reduce_axis("mean_axis", axis, keepdim, 1.0 / n)
+    }
+
+    // `scale` times the sum along `axis`.
+    fn reduce_axis(self, op: &'static str, axis: usize, keepdim: bool, scale: f32) -> Tensor {
+        let (outer, n, inner) = split_at_axis(&self.shape, axis);
+        let mut values = vec![0.0; outer * inner];
+        for o in 0..outer {
+            for j in 0..n {
+                for i in 0..inner {
+                    values[o * inner + i] += scale * self.data[(o * n + j) * inner + i];
+                }
+            }
+        }
+        let result = Tensor::new(&reduced_shape(&self.shape, axis, keepdim), values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; outer * n * inner];
+            for o in 0..outer {
+                for j in 0..n {
+                    for i in 0..inner {
+                        dloss_dself[(o * n + j) * inner + i] =
+                            scale * dloss_dresult.data[o * inner + i];
+                    }
+                }
+            }
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record(op, vec![self], result, backward)
+    }
+
+    /*
+     * Maximum along `axis`. The adjoint flows to a single element of each
+     * slice: the first maximum, if there are ties.
+     */
+    pub fn max_axis(self, axis: usize, keepdim: bool) -> Tensor {
+        let (outer, n, inner) = split_at_axis(&self.shape, axis);
+        let mut values = vec![f32::NEG_INFINITY; outer * inner];
+        let mut argmax = vec![0; outer * inner];
+        for o in 0..outer {
+            for j in 0..n {
+                for i in 0..inner {
+                    let x = self.data[(o * n + j) * inner + i];
+                    if x > values[o * inner + i] || j == 0 {
+                        values[o * inner + i] = x;
+                        argmax[o * inner + i] = j;
+                    }
+                }
+            }
+        }
+        let result = Tensor::new(&reduced_shape(&self.shape, axis, keepdim), values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; outer * n * inner];
+            for o in 0..outer {
+                for i in 0..inner {
+                    let j = argmax[o * inner + i];
+                    dloss_dself[(o * n + j) * inner + i] = dloss_dresult.data[o * inner + i];
+                }
+            }
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record("max_axis", vec![self], result, backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_gradients;
+    use super::*;
+
+    fn cube() -> Tensor {
+        let data = (0..24).map(|i| ((i * 7) % 24) as f32 / 4.0 - 2.0).collect();
+        Tensor::new(&[2, 3, 4], data, Some("x".to_string()))
+    }
+
+    #[test]
+    fn test_sum_and_mean_axis() {
+        let x = Tensor::new(&[2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], None);
+
+        assert_eq!(*x.clone().sum_axis(0, false).data, [5.0, 7.0, 9.0]);
+        assert_eq!(x.clone().sum_axis(1, true).shape, [2, 1]);
+        assert_eq!(*x.clone().mean_axis(1, false).data, [2.0, 5.0]);
+        assert_eq!(x.clone().mean().item(), 3.5);
+        assert_eq!(x.sum().item(), 21.0);
+    }
+
+    #[test]
+    fn test_max_axis() {
+        let x = Tensor::new(&[2, 3], vec![1.0, 5.0, 3.0, -4.0, -5.0, -6.0], None);
+        let m = x.max_axis(1, false);
+
+        assert_eq!(m.shape, [2]);
+        assert_eq!(*m.data, [5.0, -4.0]);
+    }
+
+    #[test]
+    fn test_reduction_gradients() {
+        let x = cube();
+
+        for axis in 0..3 {
+            assert_gradients(
+                |t| t[0].clone().sum_axis(axis, false).pow(2.0),
+                std::slice::from_ref(&x),
+            );
+            assert_gradients(
+                |t| t[0].clone().mean_axis(axis, true).pow(2.0),
+                std::slice::from_ref(&x),
+            );
+            assert_gradients(
+                |t| t[0].clone().max_axis(axis, false).pow(2.0),
+                std::slice::from_ref(&x),
+            );
+        }
+        assert_gradients(|t| t[0].clone().mean().pow(2.0), &[x]);
+    }
+
+    #[test]
+    fn test_max_ties() {
+        let x = Tensor::new(&[3], vec![2.0, 2.0, 1.0], Some("x".to_string()));
+        let m = x.clone().max_axis(0, false);
+        let dx = crate::backprop::grad::grad(&m, &[x])[0].clone().unwrap();
+
+        assert_eq!(*dx.data, [1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,292 @@
+use super::broadcast::{broadcast_shapes, broadcast_strides, for_each_offset, reduce_to, strides};
+use super::reduce::split_at_axis;
+use super::{record, Tensor};
+use std::ops::Range;
+
+/*
+ * Operations that move elements around without changing them. Their
+ * adjoints move the elements back: a slice scatters into zeros, a
+ * concatenation splits, a broadcast sums.
+ */
+
+impl Tensor {
+    pub fn reshape(self, shape: &[usize]) -> Tensor {
+        let result = Tensor::new(shape, self.data.to_vec(), None);
+
+        let own_shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            vec![Tensor::new(&own_shape, dloss_dresult.data.to_vec(), None)]
+        };
+        record("reshape", vec![self], result, backward)
+    }
+
+    // Reorders the axes: axis k of the result is axis `axes[k]` of `self`.
+    pub fn permute(self, axes: &[usize]) -> Tensor {
+        let mut sorted = axes.to_vec();
+        sorted.sort();
+        assert!(
+            sorted.iter().copied().eq(0..self.ndim()),
+            "{:?} is not a permutation of the axes of {:?}",
+            axes,
+            self.shape
+        );
+
+        let own_strides = strides(&self.shape);
+        let out_shape: Vec<usize> = axes.iter().map(|&k| self.shape[k]).collect();
+        let operand_strides = [axes.iter().map(|&k| own_strides[k]).collect::<Vec<_>>()];
+
+        let mut values = vec![0.0; self.len()];
+        for_each_offset(&out_shape, &operand_strides, |flat, offsets| {
+            values[flat] = self.data[offsets[0]];
+        });
+        let result = Tensor::new(&out_shape, values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; dloss_dresult.len()];
+            for_each_offset(&out_shape, &operand_strides, |flat, offsets| {
+                dloss_dself[offsets[0]] = dloss_dresult.data[flat];
+            });
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record("permute", vec![self], result, backward)
+    }
+
+    // Swaps the last two axes.
+    pub fn transpose(self) -> Tensor {
+        let n = self.ndim();
+        assert!(n >= 2, "transpose of a tensor of shape {:?}", self.shape);
+        let mut axes: Vec<usize> = (0..n).collect();
+        axes.swap(n - 2, n - 1);
+        self.permute(&axes)
+    }
+
+    pub fn broadcast_to(self, shape: &[usize]) -> Tensor {
+        assert_eq!(
+            broadcast_shapes(&self.shape, shape),
+            shape,
+            "cannot broadcast {:?} to {:?}",
+            self.shape,
+            shape
+        );
+
+        let operand_strides = [broadcast_strides(&self.shape, shape)];
+        let mut values = vec![0.0; shape.iter().product()];
+        for_each_offset(shape, &operand_strides, |flat, offsets| {
+            values[flat] = self.data[offsets[0]];
+        });
+        let result = Tensor::new(shape, values, None);
+
+        let (own_shape, out_shape) = (self.shape.clone(), shape.to_vec());
+        let backward = move |dloss_dresult: &Tensor| {
+            let dloss_dself = reduce_to(&dloss_dresult.data, &out_shape, &own_shape);
+            vec![Tensor::new(&own_shape, dloss_dself, None)]
+        };
+        record("broadcast_to", vec![self], result, backward)
+    }
+
+    // The elements with index `range` along `axis`.
+    pub fn slice(self, axis: usize, range: Range<usize>) -> Tensor {
+        let (outer, n, inner) = split_at_axis(&self.shape, axis);
+        assert!(
+            range.start <= range.end && range.end <= n,
+            "slice {:?} of axis {} of shape {:?}",
+            range,
+            axis,
+            self.shape
+        );
+
+        let len = range.len();
+        let mut values = Vec::with_capacity(outer * len * inner);
+        for o in 0..outer {
+            let start = (o * n + range.start) * inner;
+            values.extend_from_slice(&self.data[start..start + len * inner]);
+        }
+        let mut out_shape = self.shape.clone();
+        out_shape[axis] = len;
+        let result = Tensor::new(&out_shape, values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; outer * n * inner];
+            for o in 0..outer {
+                let start = (o * n + range.start) * inner;
+                dloss_dself[start..start + len * inner]
+                    .copy_from_slice(&dloss_dresult.data[o * len * inner..(o + 1) * len * inner]);
+            }
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record("slice", vec![self], result, backward)
+    }
+
+    // Joins tensors along an existing axis; the other axes must agree.
+    pub fn concat(tensors: &[Tensor], axis: usize) -> Tensor {
+        assert!(!tensors.is_empty(), "concat of no tensors");
+        let first = &tensors[0].shape;
+        for t in tensors {
+            let agrees = t.ndim() == first.len()
+                && (0..first.len()).all(|k| k == axis || t.shape[k] == first[k]);
+            assert!(
+                agrees,
+                "cannot concat {:?} and {:?} along axis {}",
+                first, t.shape, axis
+            );
+        }
+
+        let mut out_shape = first.clone();
+        out_shape[axis] = tensors.iter().map(|t| t.shape[axis]).sum();
+        Tensor::join("concat", tensors, axis, out_shape)
+    }
+
+    // Joins tensors of the same shape along a new axis.
+    pub fn stack(tensors: &[Tensor], axis: usize) -> Tensor {
+        assert!(!tensors.is_empty(), "stack of no tensors");
+        let first = &tensors[0].shape;
+        assert!(axis <= first.len(), "axis {} of shape {:?}", axis, first);
+        for t in tensors {
+            assert_eq!(
+                &t.shape, first,
+                "cannot stack {:?} and {:?}",
+                first, t.shape
+            );
+        }
+
+        let mut out_shape = first.clone();
+        out_shape.insert(axis, tensors.len());
+        Tensor::join("stack", tensors, axis, out_shape)
+    }
+
+    /*
+     * Lays the inputs side by side along `axis` of `out_shape`. Each input
+     * contributes `outer` contiguous chunks, one per block of the result.
+     */
+    fn join(op: &'static str, tensors: &[Tensor], axis: usize, out_shape: Vec<usize>) -> Tensor {
+        let (outer, _, _) = split_at_axis(&out_shape, axis);
+        let chunks: Vec<usize> = tensors.iter().map(|t| t.len() / outer.max(1)).collect();
+
+        let mut values = Vec::with_capacity(out_shape.iter().product());
+        for o in 0..outer {
+            for (t, &chunk) in tensors.iter().zip(&chunks) {
+                values.extend_from_slice(&t.data[o * chunk..(o + 1) * chunk]);
+            }
+        }
+        let result = Tensor::new(&out_shape, values, None);
+
+        let shapes: Vec<Vec<usize>> = tensors.iter().map(|t| t.shape.clone()).collect();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dinputs: Vec<Vec<f32>> = chunks
+                .iter()
+                .map(|c| Vec::with_capacity(c * outer))
+                .collect();
+            let mut offset = 0;
+            for _ in 0..outer {
+                for (dloss_dinput, &chunk) in dloss_dinputs.iter_mut().zip(&chunks) {
+                    dloss_dinput.extend_from_slice(&dloss_dresult.data[offset..offset + chunk]);
+                    offset += chunk;
+                }
+            }
+            dloss_dinputs
+                .into_iter()
+                .zip(&shapes)
+                .map(|(data, shape)| Tensor::new(shape, data, None))
+                .collect()
+        };
+        record(op, tensors.to_vec(), result, backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_gradients;
+    use super::*;
+
+    fn matrix(name: &str) -> Tensor {
+        let data = (0..6).map(|i| i as f32 * 0.5 - 1.2).collect();
+        Tensor::new(&[2, 3], data, Some(name.to_string()))
+    }
+
+    // A weighting that tells the positions of the result apart.
+    fn weigh(t: Tensor) -> Tensor {
+        let weights = (0..t.len()).map(|i| 1.0 + i as f32).collect();
+        let weights = Tensor::new(&t.shape, weights, None);
+        t * weights
+    }
+
+    #[test]
+    fn test_reshape_and_transpose() {
+        let x = matrix("x");
+        let t = x.clone().transpose();
+
+        assert_eq!(t.shape, [3, 2]);
+        assert_eq!(t.get(&[2, 1]), x.get(&[1, 2]));
+        assert_eq!(*x.clone().reshape(&[3, 2]).data, *x.data);
+        assert_gradients(
+            |t| weigh(t[0].clone().transpose()),
+            std::slice::from_ref(&x),
+        );
+        assert_gradients(|t| weigh(t[0].clone().reshape(&[6, 1])), &[x]);
+    }
+
+    #[test]
+    fn test_permute() {
+        let data = (0..24).map(|i| i as f32).collect();
+        let x = Tensor::new(&[2, 3, 4], data, Some("x".to_string()));
+        let p = x.clone().permute(&[2, 0, 1]);
+
+        assert_eq!(p.shape, [4, 2, 3]);
+        assert_eq!(p.get(&[3, 1, 2]), x.get(&[1, 2, 3]));
+        assert_gradients(|t| weigh(t[0].clone().permute(&[2, 0, 1])), &[x]);
+    }
+
+    #[test]
+    fn test_broadcast_to() {
+        let b = Tensor::new(&[3, 1], vec![1.0, 2.0, 3.0], Some("b".to_string()));
+        let c = b.clone().broadcast_to(&[2, 3, 2]);
+
+        assert_eq!(c.shape, [2, 3, 2]);
+        assert_eq!(c.get(&[1, 2, 1]), 3.0);
+        assert_gradients(|t| weigh(t[0].clone().broadcast_to(&[2, 3, 2])), &[b]);
+    }
+
+    #[test]
+    fn test_slice() {
+        let x = matrix("x");
+        let s = x.clone().slice(1, 1..3);
+
+        assert_eq!(s.shape, [2, 2]);
+        assert_eq!(s.get(&[1, 0]), x.get(&[1, 1]));
+        assert_gradients(
+            |t| weigh(t[0].clone().slice(1, 1..3)),
+            std::slice::from_ref(&x),
+        );
+        assert_gradients(|t| weigh(t[0].clone().slice(0, 1..2)), &[x]);
+    }
+
+    #[test]
+    fn test_concat_and_stack() {
+        let (x, y) = (matrix("x"), matrix("y").pow(2.0));
+        let z = Tensor::new(&[2, 1], vec![7.0, 8.0], Some("z".to_string()));
+
+        let c = Tensor::concat(&[x.clone(), z.clone()], 1);
+        assert_eq!(c.shape, [2, 4]);
+        assert_eq!(c.get(&[1, 3]), 8.0);
+        assert_eq!(c.get(&[1, 2]), x.get(&[1, 2]));
+
+        let s = Tensor::stack(&[x.clone(), y.clone()], 1);
+        assert_eq!(s.shape, [2, 2, 3]);
+        assert_eq!(s.get(&[1, 1, 2]), y.get(&[1, 2]));
+
+        assert_gradients(|t| weigh(Tensor::concat(t, 1)), &[x.clone(), z]);
+        assert_gradients(|t| weigh(Tensor::concat(t, 0)), &[x.clone(), y.clone()]);
+        for axis in 0..3 {
+            assert_gradients(|t| weigh(Tensor::stack(t, axis)), &[x.clone(), y.clone()]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot concat")]
+    fn test_concat_mismatch() {
+        let x = matrix("x");
+        Tensor::concat(&[x.clone(), x.transpose()], 0);
+    }
+}
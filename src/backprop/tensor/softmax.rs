@@ -0,0 +1,165 @@
+use super::reduce::{reduced_shape, split_at_axis};
+use super::{record, Tensor};
+use crate::softmax as kernels;
+
+/*
+ * Softmax, log-softmax and logsumexp along one axis, each one tape entry.
+ * Every lane along the axis goes through the max-shifted kernels, and the
+ * adjoints follow from s = softmax(x) alone, as for variables:
+ *   logsumexp:   dx = g s
+ *   log_softmax: dx = g - s sum(g)
+ *   softmax:     dx = s (g - sum(g s)).
+ */
+
+// The offsets of the elements of every lane along `axis`, lane by lane.
+fn lanes(shape: &[usize], axis: usize) -> Vec<Vec<usize>> {
+    let (outer, n, inner) = split_at_axis(shape, axis);
+    (0..outer)
+        .flat_map(|o| (0..inner).map(move |r| (0..n).map(|k| (o * n + k) * inner + r).collect()))
+        .collect()
+}
+
+fn gather(data: &[f32], lane: &[usize]) -> Vec<f32> {
+    lane.iter().map(|&o| data[o]).collect()
+}
+
+fn scatter(values: Vec<f32>, lane: &[usize], data: &mut [f32]) {
+    for (&o, x) in lane.iter().zip(values) {
+        data[o] = x;
+    }
+}
+
+impl Tensor {
+    pub fn softmax(self, axis: usize) -> Tensor {
+        let lanes = lanes(&self.shape, axis);
+        let mut s = vec![0.0; self.len()];
+        for lane in &lanes {
+            scatter(kernels::softmax(&gather(&self.data, lane)), lane, &mut s);
+        }
+        let result = Tensor::new(&self.shape, s, None);
+
+        let s = result.data.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; s.len()];
+            for lane in &lanes {
+                let (s, g) = (gather(&s, lane), gather(&dloss_dresult.data, lane));
+                let mean: f32 = g.iter().zip(&s).map(|(g, s)| g * s).sum();
+                let dx = g.iter().zip(&s).map(|(g, s)| s * (g - mean)).collect();
+                scatter(dx, lane, &mut dloss_dself);
+            }
+            vec![Tensor::new(&dloss_dresult.shape, dloss_dself, None)]
+        };
+        record("softmax", vec![self], result, backward)
+    }
+
+    pub fn log_softmax(self, axis: usize) -> Tensor {
+        let lanes = lanes(&self.shape, axis);
+        let mut l = vec![0.0; self.len()];
+        for lane in &lanes {
+            scatter(
+                kernels::log_softmax(&gather(&self.data, lane)),
+                lane,
+                &mut l,
+            );
+        }
+        let result = Tensor::new(&self.shape, l, None);
+
+        let l = result.data.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = vec![0.0; l.len()];
+            for lane in &lanes {
+                let g = gather(&dloss_dresult.data, lane);
+                let total: f32 = g.iter().sum();
+                let dx = g
+                    .iter()
+                    .zip(gather(&l, lane))
+                    .map(|(g, l)| g - l.exp() * total)
+                    .collect();
+                scatter(dx, lane, &mut dloss_dself);
+            }
+            vec![Tensor::new(&dloss_dresult.shape, dloss_dself, None)]
+        };
+        record("log_softmax", vec![self], result, backward)
+    }
+
+    // ln sum exp along `axis`, which is dropped unless `keepdim`.
+    pub fn logsumexp(self, axis: usize, keepdim: bool) -> Tensor {
+        let lanes = lanes(&self.shape, axis);
+        let mut s = vec![0.0; self.len()];
+        let mut values = Vec::with_capacity(lanes.len());
+        for lane in &lanes {
+            let x = gather(&self.data, lane);
+            values.push(kernels::logsumexp(&x));
+            scatter(kernels::softmax(&x), lane, &mut s);
+        }
+        let result = Tensor::new(&reduced_shape(&self.shape, axis, keepdim), values, None);
+
+        let shape = self.shape.clone();
+        let backward = move |dloss_dresult: &Tensor| {
+            let mut dloss_dself = s.clone();
+            for (lane, g) in lanes.iter().zip(dloss_dresult.data.iter()) {
+                for &o in lane {
+                    dloss_dself[o] *= g;
+                }
+            }
+            vec![Tensor::new(&shape, dloss_dself, None)]
+        };
+        record("logsumexp", vec![self], result, backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{assert_gradients, random_tensor};
+    use super::*;
+    use crate::backprop::tape::scoped_on;
+
+    // A weighting that tells the positions of the result apart.
+    fn weigh(t: Tensor) -> Tensor {
+        let weights = (0..t.len()).map(|i| 1.0 + i as f32).collect();
+        let weights = Tensor::new(&t.shape, weights, None);
+        t * weights
+    }
+
+    #[test]
+    fn test_values() {
+        let x = Tensor::new(&[2, 2], vec![0.0, 1000.0, 1.0, 1.0], None);
+        let s = x.clone().softmax(0);
+        let l = x.clone().log_softmax(1);
+        let lse = x.logsumexp(1, false);
+
+        assert!((s.get(&[0, 0]) - 1.0 / (1.0 + 1f32.exp())).abs() < 1e-6);
+        assert_eq!(s.get(&[1, 1]), 0.0);
+        assert_eq!(l.get(&[0, 0]), -1000.0);
+        assert!((l.get(&[1, 0]) + 2f32.ln()).abs() < 1e-6);
+        assert_eq!(lse.shape, [2]);
+        assert!((lse.data[1] - (1.0 + 2f32.ln())).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_gradients() {
+        let x = random_tensor(&[2, 3, 4], 1, "x");
+        for axis in 0..3 {
+            assert_gradients(
+                |t| weigh(t[0].clone().softmax(axis)),
+                std::slice::from_ref(&x),
+            );
+            assert_gradients(
+                |t| weigh(t[0].clone().log_softmax(axis)),
+                std::slice::from_ref(&x),
+            );
+            assert_gradients(
+                |t| weigh(t[0].clone().logsumexp(axis, axis == 1)),
+                std::slice::from_ref(&x),
+            );
+        }
+    }
+
+    #[test]
+    fn test_one_entry() {
+        let x = random_tensor(&[3, 5], 2, "x");
+        let (_, tape) = scoped_on::<Tensor, _>(|| x.log_softmax(1));
+
+        assert_eq!(tape.entries.len(), 1);
+    }
+}
@@ -0,0 +1,294 @@
+use super::{record_outputs, Tensor};
+use crate::linalg::{self, matmul, transpose};
+
+/*
+ * Spectral decompositions. Eigenvectors and singular vectors are only
+ * defined up to sign, and within a repeated eigenvalue (or singular
+ * value) up to any rotation of their subspace, so only losses that do not
+ * depend on those choices have well-defined derivatives.
+ *
+ * The adjoints divide by gaps between eigenvalues. Pairs closer than
+ * `DEGENERACY_TOL` relative to the spectrum are treated as degenerate and
+ * their coupling term is dropped: the result is then exact for losses
+ * that are invariant to rotations within the degenerate subspace, such as
+ * functions of the eigenvalues or of the projector onto the subspace, and
+ * finite (rather than infinite) otherwise.
+ */
+
+pub const DEGENERACY_TOL: f32 = 1e-5;
+
+// F[i][j] = 1 / (d[j] - d[i]), or 0 on the diagonal and for degenerate pairs.
+fn inverse_gaps(d: &[f32]) -> Vec<f32> {
+    let k = d.len();
+    let scale = d.iter().fold(1f32, |m, x| m.max(x.abs()));
+    let mut f = vec![0.0; k * k];
+    for i in 0..k {
+        for j in 0..k {
+            let gap = d[j] - d[i];
+            if i != j && gap.abs() > DEGENERACY_TOL * scale {
+                f[i * k + j] = 1.0 / gap;
+            }
+        }
+    }
+    f
+}
+
+// a[rows x k] diag(d).
+fn scale_columns(a: &[f32], d: &[f32]) -> Vec<f32> {
+    a.chunks_exact(d.len())
+        .flat_map(|row| row.iter().zip(d).map(|(x, y)| x * y))
+        .collect()
+}
+
+impl Tensor {
+    /*
+     * Eigenvalues (ascending) and eigenvectors (as columns) of a symmetric
+     * matrix A = V diag(L) V^T. With F the inverse eigenvalue gaps,
+     * A' = V (diag(L') + F o (V^T V')) V^T, symmetrized.
+     */
+    pub fn eigh(self) -> (Tensor, Tensor) {
+        let n = self.square("eigh");
+        let (values, vectors) = linalg::symmetric_eigen(&self.data, n);
+        let outputs = vec![
+            Tensor::new(&[n], values, None),
+            Tensor::new(&[n, n], vectors, None),
+        ];
+
+        let (lambda, v) = (outputs[0].data.clone(), outputs[1].data.clone());
+        let backward = move |dloss_doutputs: &[Tensor]| {
+            let (dlambda, dv) = (&dloss_doutputs[0].data, &dloss_doutputs[1].data);
+            let mut inner = matmul(&transpose(&v, n, n), dv, n, n, n);
+            for (x, f) in inner.iter_mut().zip(inverse_gaps(&lambda)) {
+                *x *= f;
+            }
+            for i in 0..n {
+                inner[i * n + i] = dlambda[i];
+            }
+            let s = matmul(&matmul(&v, &inner, n, n, n), &transpose(&v, n, n), n, n, n);
+            vec![Tensor::new(&[n, n], symmetrize(&s, n), None)]
+        };
+
+        let mut outputs = record_outputs("eigh", vec![self], outputs, backward);
+        let vectors = outputs.pop().unwrap();
+        (outputs.pop().unwrap(), vectors)
+    }
+
+    /*
+     * Thin SVD A = U diag(S) V^T of an [m, n] matrix; U is [m, k], S is [k]
+     * (descending) and V is [n, k] for k = min(m, n). The adjoint follows
+     * Townsend (2016): with F[i][j] = 1 / (s_j^2 - s_i^2),
+     *   A' = U (F o (U^T U' - U'^T U) S + diag(S') + S F o (V^T V' - V'^T V)) V^T
+     *      + (I - U U^T) U' S^-1 V^T + U S^-1 V'^T (I - V V^T).
+     * Zero singular values contribute nothing through S^-1.
+     */
+    pub fn svd(self) -> (Tensor, Tensor, Tensor) {
+        assert_eq!(self.ndim(), 2, "svd of a tensor of shape {:?}", self.shape);
+        let (m, n) = (self.shape[0], self.shape[1]);
+        let k = m.min(n);
+        let (u, s, v) = linalg::svd(&self.data, m, n);
+        let outputs = vec![
+            Tensor::new(&[m, k], u, None),
+            Tensor::new(&[k], s, None),
+            Tensor::new(&[n, k], v, None),
+        ];
+
+        let (u, s, v) = (
+            outputs[0].data.clone(),
+            outputs[1].data.clone(),
+            outputs[2].data.clone(),
+        );
+        let backward = move |dloss_doutputs: &[Tensor]| {
+            let (du, ds, dv) = (
+                &dloss_doutputs[0].data,
+                &dloss_doutputs[1].data,
+                &dloss_doutputs[2].data,
+            );
+            let (u_t, v_t) = (transpose(&u, m, k), transpose(&v, n, k));
+            let squares: Vec<f32> = s.iter().map(|x| x * x).collect();
+            let f = inverse_gaps(&squares);
+            let scale = s.first().copied().unwrap_or(0.0);
+            let s_inv: Vec<f32> = s
+                .iter()
+                .map(|&x| {
+                    if x > DEGENERACY_TOL * scale {
+                        1.0 / x
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+
+            // F o (X^T X' - X'^T X), antisymmetric.
+            let coupling = |x_t: &[f32], dx: &[f32], rows: usize| {
+                let j = matmul(x_t, dx, k, rows, k);
+                let mut c = vec![0.0; k * k];
+                for a in 0..k {
+                    for b in 0..k {
+                        c[a * k + b] = f[a * k + b] * (j[a * k + b] - j[b * k + a]);
+                    }
+                }
+                c
+            };
+            let cu = coupling(&u_t, du, m);
+            let cv = coupling(&v_t, dv, n);
+            let mut middle = vec![0.0; k * k];
+            for a in 0..k {
+                for b in 0..k {
+                    middle[a * k + b] = cu[a * k + b] * s[b] + s[a] * cv[a * k + b];
+                }
+                middle[a * k + a] += ds[a];
+            }
+            let mut da = matmul(&matmul(&u, &middle, m, k, k), &v_t, m, k, n);
+
+            // (I - U U^T) U' S^-1 V^T.
+            let mut du_perp = du.to_vec();
+            let proj = matmul(&u, &matmul(&u_t, du, k, m, k), m, k, k);
+            for (x, p) in du_perp.iter_mut().zip(proj) {
+                *x -= p;
+            }
+            let left = matmul(&scale_columns(&du_perp, &s_inv), &v_t, m, k, n);
+
+            // U S^-1 V'^T (I - V V^T).
+            let mut dv_perp = dv.to_vec();
+            let proj = matmul(&v, &matmul(&v_t, dv, k, n, k), n, k, k);
+            for (x, p) in dv_perp.iter_mut().zip(proj) {
+                *x -= p;
+            }
+            let right = matmul(
+                &scale_columns(&u, &s_inv),
+                &transpose(&dv_perp, n, k),
+                m,
+                k,
+                n,
+            );
+
+            for ((x, l), r) in da.iter_mut().zip(left).zip(right) {
+                *x += l + r;
+            }
+            vec![Tensor::new(&[m, n], da, None)]
+        };
+
+        let mut outputs = record_outputs("svd", vec![self], outputs, backward);
+        let v = outputs.pop().unwrap();
+        let s = outputs.pop().unwrap();
+        (outputs.pop().unwrap(), s, v)
+    }
+}
+
+fn symmetrize(a: &[f32], n: usize) -> Vec<f32> {
+    let mut sym = vec![0.0; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            sym[i * n + j] = 0.5 * (a[i * n + j] + a[j * n + i]);
+        }
+    }
+    sym
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{assert_gradients, random_tensor};
+    use super::*;
+
+    // (X + X^T) / 2 through the tape, so gradchecks move symmetrically.
+    fn symmetric(x: &Tensor) -> Tensor {
+        let half = Tensor::scalar(0.5, None);
+        (x.clone() + x.clone().transpose()) * half
+    }
+
+    // Weighted squares of the vectors, which do not depend on their signs.
+    fn sign_free(vectors: Tensor, weights: &Tensor) -> Tensor {
+        vectors.clone() * vectors * weights.clone()
+    }
+
+    #[test]
+    fn test_eigh_values() {
+        let a = Tensor::new(&[2, 2], vec![2.0, 1.0, 1.0, 2.0], None);
+        let (values, vectors) = a.eigh();
+
+        assert!((values.data[0] - 1.0).abs() < 1e-6);
+        assert!((values.data[1] - 3.0).abs() < 1e-6);
+        assert!((vectors.get(&[0, 1]).abs() - 0.5f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_eigh_gradients() {
+        let x = random_tensor(&[4, 4], 1, "x");
+        let w = random_tensor(&[4], 2, "w");
+        let w_vectors = random_tensor(&[4, 4], 3, "wv");
+
+        assert_gradients(
+            |t| symmetric(&t[0]).eigh().0 * t[1].clone(),
+            &[x.clone(), w],
+        );
+        assert_gradients(
+            |t| sign_free(symmetric(&t[0]).eigh().1, &t[1]),
+            &[x, w_vectors],
+        );
+    }
+
+    #[test]
+    fn test_eigh_degenerate() {
+        // A double eigenvalue: the trace and the projector onto the double
+        // eigenspace are still differentiable, and the coupling dropped
+        // within the eigenspace does not change their gradients.
+        let base = [2.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 5.0];
+        let x = Tensor::new(&[3, 3], base.to_vec(), Some("x".to_string()));
+
+        assert_gradients(
+            |t| symmetric(&t[0]).eigh().0.sum(),
+            std::slice::from_ref(&x),
+        );
+        let weights = Tensor::new(&[3], vec![1.0, 1.0, 0.0], None);
+        assert_gradients(
+            |t| {
+                let (_, vectors) = symmetric(&t[0]).eigh();
+                // V diag(w) V^T, the projector onto the double eigenspace.
+                let scaled = vectors.clone() * weights.clone();
+                scaled.matmul(vectors.transpose())
+            },
+            &[x],
+        );
+    }
+
+    #[test]
+    fn test_svd_values() {
+        let a = Tensor::new(&[2, 2], vec![3.0, 0.0, 0.0, -2.0], None);
+        let (_, s, _) = a.svd();
+
+        assert_eq!(s.shape, [2]);
+        assert!((s.data[0] - 3.0).abs() < 1e-6 && (s.data[1] - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_svd_gradients() {
+        for (m, n) in [(4, 3), (3, 4), (3, 3)] {
+            let k = m.min(n);
+            let x = random_tensor(&[m, n], 4, "x");
+            let ws = random_tensor(&[k], 5, "ws");
+            let wu = random_tensor(&[m, k], 6, "wu");
+            let wv = random_tensor(&[n, k], 7, "wv");
+
+            assert_gradients(|t| t[0].clone().svd().1 * t[1].clone(), &[x.clone(), ws]);
+            assert_gradients(|t| sign_free(t[0].clone().svd().0, &t[1]), &[x.clone(), wu]);
+            assert_gradients(|t| sign_free(t[0].clone().svd().2, &t[1]), &[x, wv]);
+        }
+    }
+
+    #[test]
+    fn test_low_rank_reconstruction() {
+        // The best rank-one approximation u1 s1 v1^T, a typical PCA loss.
+        let x = random_tensor(&[4, 3], 8, "x");
+
+        assert_gradients(
+            |t| {
+                let (u, s, v) = t[0].clone().svd();
+                let u1 = u.slice(1, 0..1);
+                let v1 = v.slice(1, 0..1);
+                let s1 = s.slice(0, 0..1);
+                (u1 * s1).matmul(v1.transpose())
+            },
+            &[x],
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use super::globals::{GRADIENT_TAPE, NAME_IDX};
 use super::tape::TapeEntry;
+use crate::subgradient;
 use std::ops::{Add, Div, Mul, Neg, Sub};
-use std::sync::atomic::Ordering;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Variable {
@@ -14,13 +14,152 @@ impl Variable {
         let name = match name {
             Some(n) => n,
             None => {
-                let idx = NAME_IDX.fetch_add(1, Ordering::SeqCst); // SeqCst is the most stringent memory ordering ensuring thread-thread-safety
+                let idx = NAME_IDX.get();
+                NAME_IDX.set(idx + 1);
                 format!("v{}", idx)
             }
         };
 
         Variable { value, name }
     }
+
+    pub fn pow(self, exp: f32) -> Self {
+        let dresult_dself = exp * self.value.powf(exp - 1.0);
+        let value = self.value.powf(exp);
+        self.unary("pow", value, dresult_dself)
+    }
+
+    pub fn sqrt(self) -> Self {
+        let dresult_dself = 0.5 * self.value.powf(-0.5);
+        let value = self.value.sqrt();
+        self.unary("sqrt", value, dresult_dself)
+    }
+
+    pub fn exp(self) -> Self {
+        let value = self.value.exp();
+        self.unary("exp", value, value)
+    }
+
+    pub fn ln(self) -> Self {
+        let dresult_dself = 1.0 / self.value;
+        let value = self.value.ln();
+        self.unary("ln", value, dresult_dself)
+    }
+
+    pub fn tanh(self) -> Self {
+        let value = self.value.tanh();
+        self.unary("tanh", value, 1.0 - value * value)
+    }
+
+    pub fn sigmoid(self) -> Self {
+        let value = 1.0 / (1.0 + (-self.value).exp());
+        self.unary("sigmoid", value, value * (1.0 - value))
+    }
+
+    /*
+     * The piecewise-linear functions below take their derivative at the
+     * kink from the `subgradient::Subgradient` policy active when they are
+     * recorded.
+     */
+
+    pub fn relu(self) -> Self {
+        let dresult_dself = subgradient::slope(self.value, 0.0, 1.0);
+        let value = self.value.max(0.0);
+        self.unary("relu", value, dresult_dself)
+    }
+
+    pub fn abs(self) -> Self {
+        let dresult_dself = subgradient::slope(self.value, -1.0, 1.0);
+        let value = self.value.abs();
+        self.unary("abs", value, dresult_dself)
+    }
+
+    pub fn max(self, rhs: Variable) -> Self {
+        let dresult_dself = subgradient::slope(self.value - rhs.value, 0.0, 1.0);
+        let value = self.value.max(rhs.value);
+        self.binary("max", rhs, value, dresult_dself, 1.0 - dresult_dself)
+    }
+
+    pub fn min(self, rhs: Variable) -> Self {
+        let dresult_dself = subgradient::slope(self.value - rhs.value, 1.0, 0.0);
+        let value = self.value.min(rhs.value);
+        self.binary("min", rhs, value, dresult_dself, 1.0 - dresult_dself)
+    }
+
+    pub fn clamp(self, lo: f32, hi: f32) -> Self {
+        let dresult_dself = subgradient::clamp_slope(self.value, lo, hi);
+        let value = self.value.clamp(lo, hi);
+        self.unary("clamp", value, dresult_dself)
+    }
+
+    // Heaviside step; flat everywhere, the policy only sets its value at 0.
+    pub fn step(self) -> Self {
+        let value = subgradient::slope(self.value, 0.0, 1.0);
+        self.unary("step", value, 0.0)
+    }
+
+    // Records an operation of one input whose local derivative is already known.
+    fn unary(self, op: &'static str, value: f32, dresult_dself: f32) -> Self {
+        let result = Variable::new(value, None);
+        println!(
+            "{} = {}({}) = {}({}) = {}",
+            result.name, op, self.name, op, self.value, result.value
+        );
+
+        let inputs = vec![self];
+        let outputs = vec![result.clone()];
+
+        let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
+            let dloss_dresult = dloss_doutputs.first().unwrap().clone().unwrap();
+
+            let dloss_dself = dloss_dresult.value * dresult_dself;
+
+            let dloss_dinputs = vec![Variable::new(dloss_dself, None)];
+            dloss_dinputs
+        };
+
+        let tape_entry = TapeEntry::new(op, inputs, outputs, Box::new(propagate));
+        GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
+
+        result
+    }
+
+    // Records an operation of two inputs whose local derivatives are already known.
+    fn binary(
+        self,
+        op: &'static str,
+        rhs: Variable,
+        value: f32,
+        dresult_dself: f32,
+        dresult_drhs: f32,
+    ) -> Self {
+        let result = Variable::new(value, None);
+        println!(
+            "{} = {}({}, {}) = {}({}, {}) = {}",
+            result.name, op, self.name, rhs.name, op, self.value, rhs.value, result.value
+        );
+
+        let inputs = vec![self, rhs];
+        let outputs = vec![result.clone()];
+
+        let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
+            let dloss_dresult = dloss_doutputs.first().unwrap().clone().unwrap();
+
+            let dloss_dself = dloss_dresult.value * dresult_dself;
+            let dloss_drhs = dloss_dresult.value * dresult_drhs;
+
+            let dloss_dinputs = vec![
+                Variable::new(dloss_dself, None),
+                Variable::new(dloss_drhs, None),
+            ];
+            dloss_dinputs
+        };
+
+        let tape_entry = TapeEntry::new(op, inputs, outputs, Box::new(propagate));
+        GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
+
+        result
+    }
 }
 
 unsafe impl Send for Variable {}
@@ -29,6 +168,8 @@ unsafe impl Sync for Variable {}
 impl Add for Variable {
     type Output = Variable;
 
+    // The adjoints of a sum scale the result's adjoint by 1 and 1.
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Variable) -> Self::Output {
         let result = Variable::new(self.value + rhs.value, None);
         println!(
@@ -40,20 +181,23 @@ impl Add for Variable {
         let outputs = vec![result.clone()];
 
         let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
-            let dloss_dresult = dloss_doutputs.get(0).unwrap().clone().unwrap();
+            let dloss_dresult = dloss_doutputs.first().unwrap().clone().unwrap();
 
-            let dresult_dself = Variable::new(1.0, None);
-            let dresult_drhs = Variable::new(1.0, None);
+            let dresult_dself = 1.0;
+            let dresult_drhs = 1.0;
 
-            let dloss_dself = dloss_dresult.clone() * dresult_dself;
-            let dloss_drhs = dloss_dresult * dresult_drhs;
+            let dloss_dself = dloss_dresult.value * dresult_dself;
+            let dloss_drhs = dloss_dresult.value * dresult_drhs;
 
-            let dloss_dinputs = vec![dloss_dself, dloss_drhs];
+            let dloss_dinputs = vec![
+                Variable::new(dloss_dself, None),
+                Variable::new(dloss_drhs, None),
+            ];
             dloss_dinputs
         };
 
-        let tape_entry = TapeEntry::new(inputs, outputs, Box::new(propagate));
-        GRADIENT_TAPE.lock().unwrap().add_entry(tape_entry);
+        let tape_entry = TapeEntry::new("add", inputs, outputs, Box::new(propagate));
+        GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
 
         result
     }
@@ -62,6 +206,8 @@ impl Add for Variable {
 impl Sub for Variable {
     type Output = Variable;
 
+    // The adjoints of a difference scale the result's adjoint by 1 and -1.
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn sub(self, rhs: Variable) -> Self::Output {
         let result = Variable::new(self.value - rhs.value, None);
         println!(
@@ -73,20 +219,23 @@ impl Sub for Variable {
         let outputs = vec![result.clone()];
 
         let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
-            let dloss_dresult = dloss_doutputs.get(0).unwrap().clone().unwrap();
+            let dloss_dresult = dloss_doutputs.first().unwrap().clone().unwrap();
 
-            let dresult_dself = Variable::new(1.0, None);
-            let dresult_drhs = Variable::new(-1.0, None);
+            let dresult_dself = 1.0;
+            let dresult_drhs = -1.0;
 
-            let dloss_dself = dloss_dresult.clone() * dresult_dself;
-            let dloss_drhs = dloss_dresult * dresult_drhs;
+            let dloss_dself = dloss_dresult.value * dresult_dself;
+            let dloss_drhs = dloss_dresult.value * dresult_drhs;
 
-            let dloss_dinputs = vec![dloss_dself, dloss_drhs];
+            let dloss_dinputs = vec![
+                Variable::new(dloss_dself, None),
+                Variable::new(dloss_drhs, None),
+            ];
             dloss_dinputs
         };
 
-        let tape_entry = TapeEntry::new(inputs, outputs, Box::new(propagate));
-        GRADIENT_TAPE.lock().unwrap().add_entry(tape_entry);
+        let tape_entry = TapeEntry::new("sub", inputs, outputs, Box::new(propagate));
+        GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
 
         result
     }
@@ -106,7 +255,7 @@ impl Mul for Variable {
         let outputs = vec![result.clone()];
 
         let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
-            let dloss_dresult = dloss_doutputs.get(0).unwrap().clone().unwrap();
+            let dloss_dresult = dloss_doutputs.first().unwrap().clone().unwrap();
 
             let dresult_dself = rhs.value;
             let dresult_drhs = self.value;
@@ -121,8 +270,8 @@ impl Mul for Variable {
             dloss_dinputs
         };
 
-        let tape_entry = TapeEntry::new(inputs, outputs, Box::new(propagate));
-        GRADIENT_TAPE.lock().unwrap().add_entry(tape_entry);
+        let tape_entry = TapeEntry::new("mul", inputs, outputs, Box::new(propagate));
+        GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
 
         result
     }
@@ -131,6 +280,8 @@ impl Mul for Variable {
 impl Div for Variable {
     type Output = Variable;
 
+    // The adjoints of a quotient multiply by 1 / rhs and -self / rhs^2.
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, rhs: Variable) -> Self::Output {
         let result = Variable::new(self.value / rhs.value, None);
         println!(
@@ -142,7 +293,7 @@ impl Div for Variable {
         let outputs = vec![result.clone()];
 
         let propagate = move |dloss_doutputs: &Vec<Option<Variable>>| -> Vec<Variable> {
-            let dloss_dresult = dloss_doutputs.get(0).unwrap().clone().unwrap();
+            let dloss_dresult = dloss_doutputs.first().unwrap().clone().unwrap();
 
             let dresult_dself = 1.0 / rhs.value;
             let dresult_drhs = -self.value / (rhs.value).powf(2.0);
@@ -157,8 +308,8 @@ impl Div for Variable {
             dloss_dinputs
         };
 
-        let tape_entry = TapeEntry::new(inputs, outputs, Box::new(propagate));
-        GRADIENT_TAPE.lock().unwrap().add_entry(tape_entry);
+        let tape_entry = TapeEntry::new("div", inputs, outputs, Box::new(propagate));
+        GRADIENT_TAPE.with_borrow_mut(|tape| tape.add_entry(tape_entry));
 
         result
     }
@@ -196,7 +347,7 @@ mod tests {
             index
         }
 
-        NAME_IDX.store(0, Ordering::SeqCst);
+        NAME_IDX.set(0);
         let v0 = Variable::new(3.0, None);
         let v1 = Variable::new(3.0, None);
         let v0_name_idx = get_name_index(&v0);
@@ -236,6 +387,56 @@ mod tests {
         assert_eq!(c.value, 4.0);
     }
 
+    #[test]
+    fn test_simple_pow() {
+        let a = Variable::new(2.0, None);
+        let b = a.pow(3.0);
+        assert_eq!(b.value, 8.0);
+    }
+
+    #[test]
+    fn test_simple_sqrt() {
+        let a = Variable::new(9.0, None);
+        let b = a.sqrt();
+        assert_eq!(b.value, 3.0);
+    }
+
+    #[test]
+    fn test_simple_smooth() {
+        use crate::backprop::grad::grad;
+
+        let x = Variable::new(0.5, Some("x".to_string()));
+        let y = x.clone().tanh();
+        let z = x.clone().sigmoid();
+        assert_eq!(y.value, 0.5f32.tanh());
+        assert!((x.clone().exp().ln().value - 0.5).abs() < 1e-6);
+
+        let dy_dx = grad(&y, std::slice::from_ref(&x))[0].clone().unwrap();
+        assert!((dy_dx.value - (1.0 - y.value * y.value)).abs() < 1e-6);
+        let dz_dx = grad(&z, std::slice::from_ref(&x))[0].clone().unwrap();
+        assert!((dz_dx.value - z.value * (1.0 - z.value)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_simple_relu() {
+        let a = Variable::new(-2.0, None);
+        let b = Variable::new(2.0, None);
+        assert_eq!(a.relu().value, 0.0);
+        assert_eq!(b.relu().value, 2.0);
+    }
+
+    #[test]
+    fn test_simple_piecewise() {
+        let a = Variable::new(-2.0, None);
+        let b = Variable::new(3.0, None);
+        assert_eq!(a.clone().abs().value, 2.0);
+        assert_eq!(a.clone().max(b.clone()).value, 3.0);
+        assert_eq!(a.clone().min(b.clone()).value, -2.0);
+        assert_eq!(b.clone().clamp(0.0, 1.0).value, 1.0);
+        assert_eq!(a.step().value, 0.0);
+        assert_eq!(b.step().value, 1.0);
+    }
+
     #[test]
     fn test_simple_neg() {
         let a = Variable::new(3.0, None);
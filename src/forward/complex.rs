@@ -0,0 +1,281 @@
+use num_complex::Complex32;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/*
+ * A dual number with complex primal and tangent parts.
+ *
+ * The tangent `der` is a direction in the complex plane, seen as R^2, so
+ * every operation propagates the real directional derivative
+ *
+ *     df = df/dz dz + df/dconj(z) conj(dz)
+ *
+ * For holomorphic operations the second term vanishes and `der` is the
+ * complex derivative times `dz`. `abs`, `arg` and `conj` are not
+ * holomorphic but are still handled exactly. The Wirtinger derivatives
+ * df/dz and df/dconj(z) are recovered by `wirtinger` from two passes.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexValue {
+    pub value: Complex32,
+    pub der: Complex32,
+}
+
+impl ComplexValue {
+    pub fn new(value: Complex32, der: Complex32) -> Self {
+        ComplexValue { value, der }
+    }
+
+    pub fn passive(value: Complex32) -> Self {
+        ComplexValue::new(value, Complex32::new(0.0, 0.0))
+    }
+
+    // Seeds the derivative along the real axis, i.e. dz = 1.
+    pub fn variable(value: Complex32) -> Self {
+        ComplexValue::new(value, Complex32::new(1.0, 0.0))
+    }
+
+    // Applies a holomorphic function given its value and derivative.
+    fn holomorphic(self, value: Complex32, dvalue_dz: Complex32) -> Self {
+        ComplexValue::new(value, dvalue_dz * self.der)
+    }
+
+    pub fn exp(self) -> Self {
+        let exp = self.value.exp();
+        self.holomorphic(exp, exp)
+    }
+
+    // Principal branch.
+    pub fn ln(self) -> Self {
+        self.holomorphic(self.value.ln(), self.value.inv())
+    }
+
+    pub fn sqrt(self) -> Self {
+        let sqrt = self.value.sqrt();
+        self.holomorphic(sqrt, 0.5 / sqrt)
+    }
+
+    pub fn powf(self, exp: f32) -> Self {
+        let dvalue_dz = exp * self.value.powf(exp - 1.0);
+        self.holomorphic(self.value.powf(exp), dvalue_dz)
+    }
+
+    pub fn powi(self, exp: i32) -> Self {
+        let dvalue_dz = exp as f32 * self.value.powi(exp - 1);
+        self.holomorphic(self.value.powi(exp), dvalue_dz)
+    }
+
+    pub fn sin(self) -> Self {
+        self.holomorphic(self.value.sin(), self.value.cos())
+    }
+
+    pub fn cos(self) -> Self {
+        self.holomorphic(self.value.cos(), -self.value.sin())
+    }
+
+    pub fn conj(self) -> Self {
+        ComplexValue::new(self.value.conj(), self.der.conj())
+    }
+
+    pub fn re(self) -> Self {
+        ComplexValue::new(self.value.re.into(), self.der.re.into())
+    }
+
+    pub fn im(self) -> Self {
+        ComplexValue::new(self.value.im.into(), self.der.im.into())
+    }
+
+    // |z|^2 = z conj(z), real valued.
+    pub fn norm_sqr(self) -> Self {
+        let der = 2.0 * (self.value.conj() * self.der).re;
+        ComplexValue::new(self.value.norm_sqr().into(), der.into())
+    }
+
+    // |z|, real valued; d|z| = Re(conj(z) dz) / |z|.
+    pub fn abs(self) -> Self {
+        let abs = self.value.norm();
+        let der = (self.value.conj() * self.der).re / abs;
+        ComplexValue::new(abs.into(), der.into())
+    }
+
+    // Principal argument, real valued; d arg(z) = Im(dz / z).
+    pub fn arg(self) -> Self {
+        let der = (self.der / self.value).im;
+        ComplexValue::new(self.value.arg().into(), der.into())
+    }
+}
+
+impl From<Complex32> for ComplexValue {
+    fn from(value: Complex32) -> Self {
+        ComplexValue::passive(value)
+    }
+}
+
+impl Add for ComplexValue {
+    type Output = ComplexValue;
+
+    fn add(self, rhs: ComplexValue) -> Self::Output {
+        ComplexValue::new(self.value + rhs.value, self.der + rhs.der)
+    }
+}
+
+impl Sub for ComplexValue {
+    type Output = ComplexValue;
+
+    fn sub(self, rhs: ComplexValue) -> Self::Output {
+        ComplexValue::new(self.value - rhs.value, self.der - rhs.der)
+    }
+}
+
+impl Mul for ComplexValue {
+    type Output = ComplexValue;
+
+    fn mul(self, rhs: ComplexValue) -> Self::Output {
+        let value = self.value * rhs.value;
+        let der = rhs.value * self.der + self.value * rhs.der;
+        ComplexValue::new(value, der)
+    }
+}
+
+impl Div for ComplexValue {
+    type Output = ComplexValue;
+
+    fn div(self, rhs: ComplexValue) -> Self::Output {
+        let value = self.value / rhs.value;
+        let der = (self.der - value * rhs.der) / rhs.value;
+        ComplexValue::new(value, der)
+    }
+}
+
+impl Neg for ComplexValue {
+    type Output = ComplexValue;
+
+    fn neg(self) -> Self::Output {
+        ComplexValue::new(-self.value, -self.der)
+    }
+}
+
+// Wirtinger derivatives (df/dz, df/dconj(z)) of `f` at `z`, from the
+// partial derivatives along the real and imaginary axes:
+// df/dz = (df/dx - i df/dy) / 2 and df/dconj(z) = (df/dx + i df/dy) / 2.
+pub fn wirtinger(f: impl Fn(ComplexValue) -> ComplexValue, z: Complex32) -> (Complex32, Complex32) {
+    let df_dx = f(ComplexValue::new(z, Complex32::new(1.0, 0.0))).der;
+    let df_dy = f(ComplexValue::new(z, Complex32::new(0.0, 1.0))).der;
+    let i_df_dy = Complex32::i() * df_dy;
+    (0.5 * (df_dx - i_df_dy), 0.5 * (df_dx + i_df_dy))
+}
+
+// Gradient of a real-valued `f` at `z` as a complex number, df/dx + i df/dy,
+// which equals 2 df/dconj(z): the direction of steepest ascent.
+pub fn real_gradient(f: impl Fn(ComplexValue) -> ComplexValue, z: Complex32) -> Complex32 {
+    2.0 * wirtinger(f, z).1
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(actual: Complex32, expected: Complex32) {
+        assert!(
+            (actual - expected).norm() <= 1e-5 * (1.0 + expected.norm()),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_holomorphic_arithmetic() {
+        // f(z) = (z^2 + 1) / z, f'(z) = 1 - 1 / z^2
+        let z = Complex32::new(1.0, 2.0);
+        let x = ComplexValue::variable(z);
+        let one = ComplexValue::passive(Complex32::new(1.0, 0.0));
+        let y = (x * x + one) / x - ComplexValue::passive(z);
+
+        assert_close(y.value, (z * z + 1.0) / z - z);
+        assert_close(y.der, 1.0 - 1.0 / (z * z));
+        assert_close((-x).der, Complex32::new(-1.0, 0.0));
+    }
+
+    #[test]
+    fn test_elementary_functions() {
+        let z = Complex32::new(0.3, -0.8);
+        let x = ComplexValue::variable(z);
+
+        assert_close(x.exp().der, z.exp());
+        assert_close(x.ln().der, 1.0 / z);
+        assert_close(x.sqrt().der, 0.5 / z.sqrt());
+        assert_close(x.powf(2.5).der, 2.5 * z.powf(1.5));
+        assert_close(x.powi(-2).der, -2.0 * z.powi(-3));
+        assert_close(x.sin().der, z.cos());
+        assert_close(x.cos().der, -z.sin());
+    }
+
+    #[test]
+    fn test_directional_derivative_along_imaginary_axis() {
+        // For holomorphic f, moving along dz = i scales the derivative by i.
+        let z = Complex32::new(0.3, -0.8);
+        let y = ComplexValue::new(z, Complex32::i()).exp();
+
+        assert_close(y.der, Complex32::i() * z.exp());
+    }
+
+    #[test]
+    fn test_holomorphic_wirtinger() {
+        // Cauchy-Riemann: df/dconj(z) = 0.
+        let z = Complex32::new(0.5, 1.5);
+        let (df_dz, df_dconj) = wirtinger(|x| x * x.exp(), z);
+
+        assert_close(df_dz, (1.0 + z) * z.exp());
+        assert_close(df_dconj, Complex32::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_non_holomorphic_wirtinger() {
+        let z = Complex32::new(3.0, -4.0);
+
+        // conj: d/dz = 0, d/dconj(z) = 1
+        let (df_dz, df_dconj) = wirtinger(|x| x.conj(), z);
+        assert_close(df_dz, Complex32::new(0.0, 0.0));
+        assert_close(df_dconj, Complex32::new(1.0, 0.0));
+
+        // |z|^2 = z conj(z): d/dz = conj(z), d/dconj(z) = z
+        let (df_dz, df_dconj) = wirtinger(|x| x.norm_sqr(), z);
+        assert_close(df_dz, z.conj());
+        assert_close(df_dconj, z);
+    }
+
+    #[test]
+    fn test_abs_arg_gradients() {
+        let z = Complex32::new(3.0, -4.0);
+
+        // grad |z| = z / |z|
+        assert_close(real_gradient(|x| x.abs(), z), z / 5.0);
+
+        // grad arg(z) = i z / |z|^2
+        assert_close(real_gradient(|x| x.arg(), z), Complex32::i() * z / 25.0);
+
+        let x = ComplexValue::variable(z);
+        assert_close(x.re().der, Complex32::new(1.0, 0.0));
+        assert_close(x.im().der, Complex32::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_impedance_sensitivity() {
+        // Series RLC: Z(w) = R + i w L + 1 / (i w C), dZ/dw = i L - 1 / (i w^2 C)
+        let (r, l, c, w) = (50.0, 1e-3, 1e-6, 2e4);
+        let i = ComplexValue::passive(Complex32::i());
+        let constant = |v: f32| ComplexValue::passive(v.into());
+
+        let omega = ComplexValue::variable(w.into());
+        let z = constant(r) + i * omega * constant(l) + constant(1.0) / (i * omega * constant(c));
+        let magnitude = z.abs();
+
+        let dz_dw = Complex32::new(0.0, l) - 1.0 / Complex32::new(0.0, w * w * c);
+        assert_close(z.der, dz_dw);
+        assert_close(
+            magnitude.der,
+            ((z.value.conj() * dz_dw).re / z.value.norm()).into(),
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use super::value::Value;
+use num_traits::{Float, Num, NumCast, ToPrimitive};
+use std::num::FpCategory;
+
+/*
+ * `num_traits::Float` for `Value`, so that code written generically over
+ * `Float` can be differentiated in the tangent mode as is.
+ *
+ * Constants and conversions (`nan`, `epsilon`, `NumCast::from`, ...) give
+ * passive values, predicates look at the primal value, and the rounding
+ * functions are piecewise constant with a zero derivative.
+ */
+
+impl Value {
+    // Applies a unary function given its value and derivative at `self.value`.
+    fn chain(self, op: &str, value: f32, dvalue_dself: f32) -> Self {
+        Value::new(value, dvalue_dself * self.der).checked(op, &[self])
+    }
+}
+
+impl ToPrimitive for Value {
+    fn to_i64(&self) -> Option<i64> {
+        self.value.to_i64()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.value.to_u64()
+    }
+
+    fn to_f32(&self) -> Option<f32> {
+        Some(self.value)
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.value as f64)
+    }
+}
+
+impl NumCast for Value {
+    fn from<T: ToPrimitive>(n: T) -> Option<Self> {
+        n.to_f32().map(Value::passive)
+    }
+}
+
+impl Num for Value {
+    type FromStrRadixErr = <f32 as Num>::FromStrRadixErr;
+
+    fn from_str_radix(str: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+        f32::from_str_radix(str, radix).map(Value::passive)
+    }
+}
+
+impl Float for Value {
+    fn nan() -> Self {
+        Value::passive(f32::NAN)
+    }
+
+    fn infinity() -> Self {
+        Value::passive(f32::INFINITY)
+    }
+
+    fn neg_infinity() -> Self {
+        Value::passive(f32::NEG_INFINITY)
+    }
+
+    fn neg_zero() -> Self {
+        Value::passive(-0.0)
+    }
+
+    fn min_value() -> Self {
+        Value::passive(f32::MIN)
+    }
+
+    fn min_positive_value() -> Self {
+        Value::passive(f32::MIN_POSITIVE)
+    }
+
+    fn epsilon() -> Self {
+        Value::passive(f32::EPSILON)
+    }
+
+    fn max_value() -> Self {
+        Value::passive(f32::MAX)
+    }
+
+    fn is_nan(self) -> bool {
+        self.value.is_nan()
+    }
+
+    fn is_infinite(self) -> bool {
+        self.value.is_infinite()
+    }
+
+    fn is_finite(self) -> bool {
+        self.value.is_finite()
+    }
+
+    fn is_normal(self) -> bool {
+        self.value.is_normal()
+    }
+
+    fn classify(self) -> FpCategory {
+        self.value.classify()
+    }
+
+    fn floor(self) -> Self {
+        self.chain("floor", self.value.floor(), 0.0)
+    }
+
+    fn ceil(self) -> Self {
+        self.chain("ceil", self.value.ceil(), 0.0)
+    }
+
+    fn round(self) -> Self {
+        self.chain("round", self.value.round(), 0.0)
+    }
+
+    fn trunc(self) -> Self {
+        self.chain("trunc", self.value.trunc(), 0.0)
+    }
+
+    fn fract(self) -> Self {
+        self.chain("fract", self.value.fract(), 1.0)
+    }
+
+    fn abs(self) -> Self {
+        Value::abs(self)
+    }
+
+    fn signum(self) -> Self {
+        self.chain("signum", self.value.signum(), 0.0)
+    }
+
+    fn is_sign_positive(self) -> bool {
+        self.value.is_sign_positive()
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.value.is_sign_negative()
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        let value = self.value.mul_add(a.value, b.value);
+        let der = a.value * self.der + self.value * a.der + b.der;
+        Value::new(value, der).checked("mul_add", &[self, a, b])
+    }
+
+    fn recip(self) -> Self {
+        let recip = self.value.recip();
+        self.chain("recip", recip, -recip * recip)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        // x^0 is constant, and n x^(n - 1) would be 0 * inf at x = 0.
+        if n == 0 {
+            return Value::passive(1.0);
+        }
+        let dvalue_dself = n as f32 * self.value.powi(n - 1);
+        self.chain("powi", self.value.powi(n), dvalue_dself)
+    }
+
+    // x^n with both the base and the exponent active. The ln(x) term is
+    // skipped for a constant exponent so that negative bases stay finite.
+    fn powf(self, n: Self) -> Self {
+        let value = self.value.powf(n.value);
+        let mut der = n.value * self.value.powf(n.value - 1.0) * self.der;
+        if n.der != 0.0 {
+            der += value * self.value.ln() * n.der;
+        }
+        Value::new(value, der).checked("powf", &[self, n])
+    }
+
+    fn sqrt(self) -> Self {
+        Value::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        Value::exp(self)
+    }
+
+    fn exp2(self) -> Self {
+        let exp2 = self.value.exp2();
+        self.chain("exp2", exp2, exp2 * std::f32::consts::LN_2)
+    }
+
+    fn ln(self) -> Self {
+        Value::ln(self)
+    }
+
+    fn log(self, base: Self) -> Self {
+        self.ln() / base.ln()
+    }
+
+    fn log2(self) -> Self {
+        let dvalue_dself = (self.value * std::f32::consts::LN_2).recip();
+        self.chain("log2", self.value.log2(), dvalue_dself)
+    }
+
+    fn log10(self) -> Self {
+        let dvalue_dself = (self.value * std::f32::consts::LN_10).recip();
+        self.chain("log10", self.value.log10(), dvalue_dself)
+    }
+
+    fn to_degrees(self) -> Self {
+        self.chain("to_degrees", self.value.to_degrees(), 1.0_f32.to_degrees())
+    }
+
+    fn to_radians(self) -> Self {
+        self.chain("to_radians", self.value.to_radians(), 1.0_f32.to_radians())
+    }
+
+    fn max(self, other: Self) -> Self {
+        Value::max(self, other)
+    }
+
+    fn min(self, other: Self) -> Self {
+        Value::min(self, other)
+    }
+
+    fn abs_sub(self, other: Self) -> Self {
+        Value::max(self - other, Value::passive(0.0))
+    }
+
+    fn cbrt(self) -> Self {
+        let cbrt = self.value.cbrt();
+        self.chain("cbrt", cbrt, (3.0 * cbrt * cbrt).recip())
+    }
+
+    fn hypot(self, other: Self) -> Self {
+        let value = self.value.hypot(other.value);
+        let der = (self.value * self.der + other.value * other.der) / value;
+        Value::new(value, der).checked("hypot", &[self, other])
+    }
+
+    fn sin(self) -> Self {
+        self.chain("sin", self.value.sin(), self.value.cos())
+    }
+
+    fn cos(self) -> Self {
+        self.chain("cos", self.value.cos(), -self.value.sin())
+    }
+
+    fn tan(self) -> Self {
+        let tan = self.value.tan();
+        self.chain("tan", tan, 1.0 + tan * tan)
+    }
+
+    fn asin(self) -> Self {
+        let dvalue_dself = (1.0 - self.value * self.value).sqrt().recip();
+        self.chain("asin", self.value.asin(), dvalue_dself)
+    }
+
+    fn acos(self) -> Self {
+        let dvalue_dself = -(1.0 - self.value * self.value).sqrt().recip();
+        self.chain("acos", self.value.acos(), dvalue_dself)
+    }
+
+    fn atan(self) -> Self {
+        let dvalue_dself = (1.0 + self.value * self.value).recip();
+        self.chain("atan", self.value.atan(), dvalue_dself)
+    }
+
+    // atan2(y, x) with y = self.
+    fn atan2(self, other: Self) -> Self {
+        let value = self.value.atan2(other.value);
+        let norm2 = self.value * self.value + other.value * other.value;
+        let der = (other.value * self.der - self.value * other.der) / norm2;
+        Value::new(value, der).checked("atan2", &[self, other])
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        (Float::sin(self), Float::cos(self))
+    }
+
+    fn exp_m1(self) -> Self {
+        self.chain("exp_m1", self.value.exp_m1(), self.value.exp())
+    }
+
+    fn ln_1p(self) -> Self {
+        self.chain("ln_1p", self.value.ln_1p(), (1.0 + self.value).recip())
+    }
+
+    fn sinh(self) -> Self {
+        self.chain("sinh", self.value.sinh(), self.value.cosh())
+    }
+
+    fn cosh(self) -> Self {
+        self.chain("cosh", self.value.cosh(), self.value.sinh())
+    }
+
+    fn tanh(self) -> Self {
+        Value::tanh(self)
+    }
+
+    fn asinh(self) -> Self {
+        let dvalue_dself = (self.value * self.value + 1.0).sqrt().recip();
+        self.chain("asinh", self.value.asinh(), dvalue_dself)
+    }
+
+    fn acosh(self) -> Self {
+        let dvalue_dself = (self.value * self.value - 1.0).sqrt().recip();
+        self.chain("acosh", self.value.acosh(), dvalue_dself)
+    }
+
+    fn atanh(self) -> Self {
+        let dvalue_dself = (1.0 - self.value * self.value).recip();
+        self.chain("atanh", self.value.atanh(), dvalue_dself)
+    }
+
+    fn integer_decode(self) -> (u64, i16, i8) {
+        Float::integer_decode(self.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gradcheck::directional_difference;
+
+    // Checks the tangent of a unary expression against a central
+    // difference of the same expression evaluated in f64.
+    macro_rules! check_tangent {
+        ($x:expr, |$v:ident| $body:expr) => {{
+            fn dual($v: Value) -> Value {
+                $body
+            }
+            fn plain($v: f64) -> f64 {
+                $body
+            }
+            let x: f64 = $x;
+            let expected = directional_difference(|x| vec![plain(x[0])], &[x], &[1.0], 1e-6)[0];
+            let actual = dual(Value::new(x as f32, 1.0));
+            assert!(
+                (actual.value as f64 - plain(x)).abs() <= 1e-5 * (1.0 + plain(x).abs()),
+                "value of {}: {} != {}",
+                stringify!($body),
+                actual.value,
+                plain(x)
+            );
+            assert!(
+                (actual.der as f64 - expected).abs() <= 1e-4 * (1.0 + expected.abs()),
+                "tangent of {}: {} != {}",
+                stringify!($body),
+                actual.der,
+                expected
+            );
+        }};
+    }
+
+    #[test]
+    fn test_transcendental_tangents() {
+        check_tangent!(0.7, |x| x.exp());
+        check_tangent!(0.7, |x| x.exp2());
+        check_tangent!(0.7, |x| x.exp_m1());
+        check_tangent!(0.7, |x| x.ln());
+        check_tangent!(0.7, |x| x.ln_1p());
+        check_tangent!(0.7, |x| x.log2());
+        check_tangent!(0.7, |x| x.log10());
+        check_tangent!(0.7, |x| x.log(x + x));
+        check_tangent!(0.7, |x| x.recip());
+        check_tangent!(0.7, |x| x.powi(-3));
+        check_tangent!(0.7, |x| x.powi(0));
+        check_tangent!(0.0, |x| x.powi(0));
+        check_tangent!(0.0, |x| x.powi(1));
+        check_tangent!(0.7, |x| x.powf(x));
+        check_tangent!(0.7, |x| Float::sqrt(x));
+        check_tangent!(0.7, |x| x.cbrt());
+        check_tangent!(0.7, |x| x.hypot(x * x));
+        check_tangent!(0.7, |x| x.mul_add(x, x));
+        check_tangent!(0.7, |x| x.sin());
+        check_tangent!(0.7, |x| x.cos());
+        check_tangent!(0.7, |x| x.tan());
+        check_tangent!(0.7, |x| x.asin());
+        check_tangent!(0.7, |x| x.acos());
+        check_tangent!(0.7, |x| x.atan());
+        check_tangent!(0.7, |x| x.atan2(x * x - x));
+        check_tangent!(0.7, |x| x.sinh());
+        check_tangent!(0.7, |x| x.cosh());
+        check_tangent!(0.7, |x| x.tanh());
+        check_tangent!(0.7, |x| x.asinh());
+        check_tangent!(1.7, |x| x.acosh());
+        check_tangent!(0.7, |x| x.atanh());
+        check_tangent!(0.7, |x| x.to_degrees());
+        check_tangent!(0.7, |x| x.to_radians());
+        check_tangent!(2.7, |x| x.fract());
+        check_tangent!(2.7, |x| x.floor() + x.ceil() + x.round() + x.trunc());
+    }
+
+    #[test]
+    fn test_sin_cos() {
+        let (sin, cos) = Float::sin_cos(Value::new(0.5, 2.0));
+
+        assert_eq!((sin.value, sin.der), (0.5_f32.sin(), 2.0 * 0.5_f32.cos()));
+        assert_eq!((cos.value, cos.der), (0.5_f32.cos(), -2.0 * 0.5_f32.sin()));
+    }
+
+    #[test]
+    fn test_powf_constant_exponent_of_negative_base() {
+        let x = Value::new(-2.0, 1.0).powf(Value::passive(3.0));
+
+        assert_eq!((x.value, x.der), (-8.0, 12.0));
+    }
+
+    #[test]
+    fn test_constants_and_conversions() {
+        assert!(Value::nan().is_nan());
+        assert!(Value::infinity().is_infinite());
+        assert_eq!(Value::epsilon().value, f32::EPSILON);
+        assert_eq!(Value::max_value().der, 0.0);
+
+        let x: Value = NumCast::from(3_u8).unwrap();
+        assert_eq!((x.value, x.der), (3.0, 0.0));
+        assert_eq!(Value::new(2.5, 1.0).to_i64(), Some(2));
+        assert_eq!(Value::from_str_radix("1.5", 10).unwrap().value, 1.5);
+    }
+
+    // Written against `Float` only: Newton's method for the cube root.
+    fn newton_cbrt<F: Float>(a: F) -> F {
+        let three = F::from(3.0).unwrap();
+        let mut x = a;
+        for _ in 0..30 {
+            x = x - (x.powi(3) - a) / (three * x.powi(2));
+        }
+        x
+    }
+
+    // And a projectile's range under drag-free flight.
+    fn range<F: Float>(speed: F, angle: F) -> F {
+        let g = F::from(9.81).unwrap();
+        speed.powi(2) * (angle + angle).sin() / g
+    }
+
+    #[test]
+    fn test_generic_float_code() {
+        let y = newton_cbrt(Value::new(8.0, 1.0));
+        assert!((y.value - 2.0).abs() < 1e-6);
+        assert!((y.der - 1.0 / 12.0).abs() < 1e-6);
+
+        let dr_dangle = range(Value::passive(10.0), Value::new(0.3, 1.0)).der;
+        assert!((dr_dangle - 200.0 * 0.6_f32.cos() / 9.81).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::scalar::Function;
+use crate::subgradient;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/*
+ * A hyper-dual number value + eps1 e1 + eps2 e2 + eps12 e1 e2, with
+ * e1^2 = e2^2 = 0 and e1 e2 != 0.
+ *
+ * Seeding e1 along direction u and e2 along direction v makes eps1 and
+ * eps2 the directional derivatives along u and v, and eps12 the exact
+ * second derivative u^T H v, without any truncation or cancellation error.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HyperDual {
+    pub value: f32,
+    pub eps1: f32,
+    pub eps2: f32,
+    pub eps12: f32,
+}
+
+impl HyperDual {
+    pub fn new(value: f32, eps1: f32, eps2: f32, eps12: f32) -> Self {
+        HyperDual {
+            value,
+            eps1,
+            eps2,
+            eps12,
+        }
+    }
+
+    pub fn passive(value: f32) -> Self {
+        HyperDual::new(value, 0.0, 0.0, 0.0)
+    }
+
+    // Applies f given f(a), f'(a) and f''(a) at the primal value a.
+    fn chain(self, f: f32, df: f32, d2f: f32) -> Self {
+        HyperDual {
+            value: f,
+            eps1: df * self.eps1,
+            eps2: df * self.eps2,
+            eps12: df * self.eps12 + d2f * self.eps1 * self.eps2,
+        }
+    }
+
+    pub fn recip(self) -> Self {
+        let inv = 1.0 / self.value;
+        self.chain(inv, -inv * inv, 2.0 * inv * inv * inv)
+    }
+
+    pub fn pow(self, exp: f32) -> Self {
+        let x = self.value;
+        self.chain(
+            x.powf(exp),
+            exp * x.powf(exp - 1.0),
+            exp * (exp - 1.0) * x.powf(exp - 2.0),
+        )
+    }
+
+    pub fn sqrt(self) -> Self {
+        let sqrt = self.value.sqrt();
+        self.chain(sqrt, 0.5 / sqrt, -0.25 / (sqrt * self.value))
+    }
+
+    pub fn exp(self) -> Self {
+        let exp = self.value.exp();
+        self.chain(exp, exp, exp)
+    }
+
+    pub fn ln(self) -> Self {
+        let inv = 1.0 / self.value;
+        self.chain(self.value.ln(), inv, -inv * inv)
+    }
+
+    pub fn sin(self) -> Self {
+        let (sin, cos) = self.value.sin_cos();
+        self.chain(sin, cos, -sin)
+    }
+
+    pub fn cos(self) -> Self {
+        let (sin, cos) = self.value.sin_cos();
+        self.chain(cos, -sin, -cos)
+    }
+
+    pub fn tan(self) -> Self {
+        let tan = self.value.tan();
+        let sec2 = 1.0 + tan * tan;
+        self.chain(tan, sec2, 2.0 * tan * sec2)
+    }
+
+    pub fn sinh(self) -> Self {
+        let (sinh, cosh) = (self.value.sinh(), self.value.cosh());
+        self.chain(sinh, cosh, sinh)
+    }
+
+    pub fn cosh(self) -> Self {
+        let (sinh, cosh) = (self.value.sinh(), self.value.cosh());
+        self.chain(cosh, sinh, cosh)
+    }
+
+    pub fn tanh(self) -> Self {
+        let tanh = self.value.tanh();
+        let sech2 = 1.0 - tanh * tanh;
+        self.chain(tanh, sech2, -2.0 * tanh * sech2)
+    }
+
+    pub fn asin(self) -> Self {
+        let x = self.value;
+        let inv_sqrt = 1.0 / (1.0 - x * x).sqrt();
+        self.chain(x.asin(), inv_sqrt, x * inv_sqrt.powi(3))
+    }
+
+    pub fn acos(self) -> Self {
+        let x = self.value;
+        let inv_sqrt = 1.0 / (1.0 - x * x).sqrt();
+        self.chain(x.acos(), -inv_sqrt, -x * inv_sqrt.powi(3))
+    }
+
+    pub fn atan(self) -> Self {
+        let x = self.value;
+        let inv = 1.0 / (1.0 + x * x);
+        self.chain(x.atan(), inv, -2.0 * x * inv * inv)
+    }
+
+    /*
+     * The piecewise-linear functions below have a zero second derivative
+     * and take their slope at the kink from the active
+     * `subgradient::Subgradient` policy.
+     */
+
+    pub fn relu(self) -> Self {
+        let slope = subgradient::slope(self.value, 0.0, 1.0);
+        self.chain(self.value.max(0.0), slope, 0.0)
+    }
+
+    pub fn abs(self) -> Self {
+        let slope = subgradient::slope(self.value, -1.0, 1.0);
+        self.chain(self.value.abs(), slope, 0.0)
+    }
+
+    pub fn max(self, rhs: HyperDual) -> Self {
+        let dresult_dself = subgradient::slope(self.value - rhs.value, 0.0, 1.0);
+        let mut result = self.scale(dresult_dself) + rhs.scale(1.0 - dresult_dself);
+        result.value = self.value.max(rhs.value);
+        result
+    }
+
+    pub fn min(self, rhs: HyperDual) -> Self {
+        let dresult_dself = subgradient::slope(self.value - rhs.value, 1.0, 0.0);
+        let mut result = self.scale(dresult_dself) + rhs.scale(1.0 - dresult_dself);
+        result.value = self.value.min(rhs.value);
+        result
+    }
+
+    pub fn clamp(self, lo: f32, hi: f32) -> Self {
+        let slope = subgradient::clamp_slope(self.value, lo, hi);
+        self.chain(self.value.clamp(lo, hi), slope, 0.0)
+    }
+
+    // Heaviside step; flat everywhere, the policy only sets its value at 0.
+    pub fn step(self) -> Self {
+        HyperDual::passive(subgradient::slope(self.value, 0.0, 1.0))
+    }
+
+    fn scale(self, factor: f32) -> Self {
+        HyperDual {
+            value: factor * self.value,
+            eps1: factor * self.eps1,
+            eps2: factor * self.eps2,
+            eps12: factor * self.eps12,
+        }
+    }
+}
+
+impl Add for HyperDual {
+    type Output = HyperDual;
+
+    fn add(self, rhs: HyperDual) -> Self::Output {
+        HyperDual {
+            value: self.value + rhs.value,
+            eps1: self.eps1 + rhs.eps1,
+            eps2: self.eps2 + rhs.eps2,
+            eps12: self.eps12 + rhs.eps12,
+        }
+    }
+}
+
+impl Sub for HyperDual {
+    type Output = HyperDual;
+
+    fn sub(self, rhs: HyperDual) -> Self::Output {
+        HyperDual {
+            value: self.value - rhs.value,
+            eps1: self.eps1 - rhs.eps1,
+            eps2: self.eps2 - rhs.eps2,
+            eps12: self.eps12 - rhs.eps12,
+        }
+    }
+}
+
+impl Mul for HyperDual {
+    type Output = HyperDual;
+
+    fn mul(self, rhs: HyperDual) -> Self::Output {
+        HyperDual {
+            value: self.value * rhs.value,
+            eps1: self.eps1 * rhs.value + self.value * rhs.eps1,
+            eps2: self.eps2 * rhs.value + self.value * rhs.eps2,
+            eps12: self.eps12 * rhs.value
+                + self.eps1 * rhs.eps2
+                + self.eps2 * rhs.eps1
+                + self.value * rhs.eps12,
+        }
+    }
+}
+
+impl Div for HyperDual {
+    type Output = HyperDual;
+
+    // The quotient is the product with the reciprocal of `rhs`.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: HyperDual) -> Self::Output {
+        self * rhs.recip()
+    }
+}
+
+impl Neg for HyperDual {
+    type Output = HyperDual;
+
+    fn neg(self) -> Self::Output {
+        self.scale(-1.0)
+    }
+}
+
+// Hessian of `f` at `x`, one evaluation per entry of the upper triangle:
+// e1 is seeded along x_i and e2 along x_j, so eps12 is d^2 f / dx_i dx_j.
+// Both triangles are filled from the same evaluation.
+#[allow(clippy::needless_range_loop)]
+pub fn hessian<F: Function>(f: &F, x: &[f32]) -> Vec<Vec<f32>> {
+    let n = x.len();
+    let mut hessian = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in i..n {
+            let seeded: Vec<HyperDual> = x
+                .iter()
+                .enumerate()
+                .map(|(k, &value)| {
+                    let eps1 = if k == i { 1.0 } else { 0.0 };
+                    let eps2 = if k == j { 1.0 } else { 0.0 };
+                    HyperDual::new(value, eps1, eps2, 0.0)
+                })
+                .collect();
+            let entry = f.eval(&seeded).eps12;
+            hessian[i][j] = entry;
+            hessian[j][i] = entry;
+        }
+    }
+    hessian
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forward::taylor::Taylor;
+    use crate::scalar::Scalar;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= 1e-5 * (1.0 + expected.abs()),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    // f, f' and f'' of a univariate function, seeding both directions.
+    fn second_order(f: impl Fn(HyperDual) -> HyperDual, x: f32) -> [f32; 3] {
+        let y = f(HyperDual::new(x, 1.0, 1.0, 0.0));
+        assert_eq!(y.eps1, y.eps2);
+        [y.value, y.eps1, y.eps12]
+    }
+
+    fn check_against_taylor(
+        f: impl Fn(HyperDual) -> HyperDual,
+        g: impl Fn(Taylor<3>) -> Taylor<3>,
+        x: f32,
+    ) {
+        let actual = second_order(f, x);
+        let expected = g(Taylor::variable(x)).derivatives();
+        for (a, e) in actual.into_iter().zip(expected) {
+            assert_close(a, e);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // f(x) = (3x^2 - x) / (x + 1) at x = 2
+        let f =
+            |x: HyperDual| (HyperDual::passive(3.0) * x * x - x) / (x + HyperDual::passive(1.0));
+        let [value, der, der2] = second_order(f, 2.0);
+
+        assert_close(value, 10.0 / 3.0);
+        assert_close(der, 23.0 / 9.0);
+        assert_close(der2, 8.0 / 27.0);
+    }
+
+    #[test]
+    fn test_elementary_functions() {
+        check_against_taylor(|x| x.exp(), |x| x.exp(), 0.7);
+        check_against_taylor(|x| x.ln(), |x| x.ln(), 0.7);
+        check_against_taylor(|x| x.sqrt(), |x| x.sqrt(), 0.7);
+        check_against_taylor(|x| x.pow(-1.5), |x| x.pow(-1.5), 0.7);
+        check_against_taylor(|x| x.sin(), |x| x.sin(), 0.7);
+        check_against_taylor(|x| x.cos(), |x| x.cos(), 0.7);
+        check_against_taylor(|x| x.tan(), |x| x.tan(), 0.7);
+        check_against_taylor(|x| x.sinh(), |x| x.sinh(), 0.7);
+        check_against_taylor(|x| x.cosh(), |x| x.cosh(), 0.7);
+        check_against_taylor(|x| x.tanh(), |x| x.tanh(), 0.7);
+        check_against_taylor(|x| x.asin(), |x| x.asin(), 0.7);
+        check_against_taylor(|x| x.acos(), |x| x.acos(), 0.7);
+        check_against_taylor(|x| x.atan(), |x| x.atan(), 0.7);
+    }
+
+    #[test]
+    fn test_piecewise() {
+        let x = HyperDual::new(-2.0, 1.0, 3.0, 0.5);
+
+        assert_eq!(x.abs(), HyperDual::new(2.0, -1.0, -3.0, -0.5));
+        assert_eq!(x.relu(), HyperDual::new(0.0, 0.0, 0.0, 0.0));
+        assert_eq!(x.max(HyperDual::passive(1.0)), HyperDual::passive(1.0));
+        assert_eq!(x.min(HyperDual::passive(1.0)), x);
+        assert_eq!(x.clamp(-1.0, 1.0), HyperDual::passive(-1.0));
+        assert_eq!(x.step(), HyperDual::passive(0.0));
+    }
+
+    struct Rosenbrock;
+
+    impl Function for Rosenbrock {
+        fn eval<T: Scalar>(&self, x: &[T]) -> T {
+            let a = T::constant(1.0) - x[0].clone();
+            let b = x[1].clone() - x[0].clone().pow(2.0);
+            a.pow(2.0) + T::constant(100.0) * b.pow(2.0)
+        }
+    }
+
+    #[test]
+    fn test_hessian_rosenbrock() {
+        // H = [[1200 x^2 - 400 y + 2, -400 x], [-400 x, 200]]
+        let h = hessian(&Rosenbrock, &[1.5, 0.5]);
+
+        assert_close(h[0][0], 1200.0 * 2.25 - 400.0 * 0.5 + 2.0);
+        assert_close(h[0][1], -600.0);
+        assert_close(h[1][0], -600.0);
+        assert_close(h[1][1], 200.0);
+    }
+
+    struct Mixed;
+
+    impl Function for Mixed {
+        fn eval<T: Scalar>(&self, x: &[T]) -> T {
+            x[0].clone() * x[1].clone() * x[2].clone() + x[0].clone().pow(3.0) - x[2].clone().sqrt()
+        }
+    }
+
+    #[test]
+    fn test_hessian_three_inputs() {
+        // f = xyz + x^3 - sqrt(z)
+        let [x, y, z] = [2.0, -1.0, 4.0];
+        let h = hessian(&Mixed, &[x, y, z]);
+        let expected = [[6.0 * x, z, y], [z, 0.0, x], [y, x, 0.25 * z.powf(-1.5)]];
+
+        for (row, expected_row) in h.iter().zip(expected) {
+            for (&entry, expected_entry) in row.iter().zip(expected_row) {
+                assert_close(entry, expected_entry);
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use super::value::Value;
+use crate::linalg::{self, matmul, Lu};
+
+/*
+ * Linear algebra on matrices of `Value`s, stored row-major as flat slices.
+ * The primal goes through the `f32` kernels once, and the tangents follow
+ * from the differentiated identities (Giles, 2008) instead of tracing the
+ * factorization element by element.
+ */
+
+fn split(a: &[Value]) -> (Vec<f32>, Vec<f32>) {
+    a.iter().map(|x| (x.value, x.der)).unzip()
+}
+
+fn join(values: &[f32], ders: &[f32]) -> Vec<Value> {
+    values
+        .iter()
+        .zip(ders)
+        .map(|(&value, &der)| Value::new(value, der))
+        .collect()
+}
+
+// Only for the ops that need A^-1; `det` and `logdet` factor any A.
+fn lu(a: &[Value], n: usize, op: &str) -> Lu {
+    let (a, _) = split(a);
+    Lu::new(&a, n).unwrap_or_else(|| panic!("{} of a singular matrix", op))
+}
+
+// A^-1 dA, the building block of every rule below.
+fn inv_times_tangent(lu: &Lu, a: &[Value]) -> Vec<f32> {
+    let (_, da) = split(a);
+    lu.solve(&da, lu.n)
+}
+
+fn trace(a: &[f32], n: usize) -> f32 {
+    (0..n).map(|i| a[i * n + i]).sum()
+}
+
+// X with A X = B for A of shape [n, n] and B of shape [n, m]; dX = A^-1 (dB - dA X).
+pub fn solve(a: &[Value], b: &[Value], n: usize) -> Vec<Value> {
+    let lu = lu(a, n, "solve");
+    assert_eq!(
+        b.len() % n,
+        0,
+        "cannot solve a system of order {} for {} right-hand side values",
+        n,
+        b.len()
+    );
+    let m = b.len() / n;
+    let (b, db) = split(b);
+    let x = lu.solve(&b, m);
+
+    let (_, da) = split(a);
+    let da_x = matmul(&da, &x, n, n, m);
+    let rhs: Vec<f32> = db.iter().zip(&da_x).map(|(d, e)| d - e).collect();
+    join(&x, &lu.solve(&rhs, m))
+}
+
+// Y = A^-1, with dY = -Y dA Y.
+pub fn inv(a: &[Value], n: usize) -> Vec<Value> {
+    let lu = lu(a, n, "inv");
+    let y = lu.inverse();
+    let dy = matmul(&inv_times_tangent(&lu, a), &y, n, n, n);
+    join(&y, &dy.iter().map(|d| -d).collect::<Vec<_>>())
+}
+
+// tr(adj(A) dA), the tangent of det A, also for singular A.
+fn adjugate_tangent(a: &[Value], n: usize) -> f32 {
+    let (a, da) = split(a);
+    trace(&matmul(&linalg::adjugate(&a, n), &da, n, n, n), n)
+}
+
+// d det A = tr(adj(A) dA), which is det A tr(A^-1 dA) for invertible A.
+pub fn det(a: &[Value], n: usize) -> Value {
+    let (values, _) = split(a);
+    Value::new(Lu::factor(&values, n).det(), adjugate_tangent(a, n))
+}
+
+/*
+ * ln |det A|, with d logdet A = tr(A^-1 dA). A singular A gives -inf, and
+ * a tangent tr(adj(A) dA) / 0 that is not finite.
+ */
+pub fn logdet(a: &[Value], n: usize) -> Value {
+    let (values, _) = split(a);
+    let lu = Lu::factor(&values, n);
+    let der = if lu.is_singular() {
+        adjugate_tangent(a, n) / 0.0
+    } else {
+        trace(&inv_times_tangent(&lu, a), n)
+    };
+    Value::new(lu.ln_abs_det(), der)
+}
+
+/*
+ * Lower triangular L with A = L L^T, reading only the lower triangle of A
+ * (and of its tangent, which is taken as symmetric). With Phi taking the
+ * lower triangle and halving the diagonal, dL = L Phi(L^-1 dA L^-T).
+ */
+pub fn cholesky(a: &[Value], n: usize) -> Vec<Value> {
+    let (a, mut da) = split(a);
+    let l = linalg::cholesky(&a, n)
+        .unwrap_or_else(|| panic!("cholesky of a matrix that is not positive definite"));
+    for i in 0..n {
+        for j in i + 1..n {
+            da[i * n + j] = da[j * n + i];
+        }
+    }
+
+    // L^-1 dA L^-T = L^-1 (L^-1 dA)^T, as dA is symmetric.
+    let left = linalg::solve_lower(&l, &da, n, n, false);
+    let mut phi = linalg::solve_lower(&l, &linalg::transpose(&left, n, n), n, n, false);
+    for i in 0..n {
+        phi[i * n + i] *= 0.5;
+        for j in i + 1..n {
+            phi[i * n + j] = 0.0;
+        }
+    }
+    join(&l, &matmul(&l, &phi, n, n, n))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gradcheck::{directional_difference, Error};
+    use crate::linalg::testing::{random, random_spd};
+
+    // A + t dA along a random symmetric direction dA.
+    fn along(a: &[f32], n: usize, seed: u64) -> Vec<Value> {
+        let d = random(n * n, seed);
+        let d = linalg::transpose(&d, n, n)
+            .iter()
+            .zip(&d)
+            .map(|(x, y)| 0.5 * (x + y))
+            .collect::<Vec<_>>();
+        join(a, &d)
+    }
+
+    // Central difference of `f` along the tangents of `a`.
+    fn numerical(a: &[Value], f: impl Fn(&[f32]) -> Vec<f32>) -> Vec<f32> {
+        let (x, v): (Vec<f32>, Vec<f32>) = a.iter().map(|a| (a.value, a.der)).unzip();
+        directional_difference(f, &x, &v, 1e-2)
+    }
+
+    fn assert_tangents(computed: &[Value], expected: &[f32]) {
+        for (c, e) in computed.iter().zip(expected) {
+            assert!(Error::new(c.der, *e).within(1e-2), "{} != {}", c.der, e);
+        }
+    }
+
+    #[test]
+    fn test_solve_and_inv() {
+        let n = 4;
+        let a = along(&random_spd(n, 1), n, 2);
+        let b = join(&random(n * 2, 3), &random(n * 2, 4));
+
+        // Both A and b move along their tangents.
+        let x = solve(&a, &b, n);
+        let moved = [a.clone(), b.clone()].concat();
+        let expected = numerical(&moved, |ab| {
+            Lu::new(&ab[..n * n], n).unwrap().solve(&ab[n * n..], 2)
+        });
+        assert_tangents(&x, &expected);
+
+        let y = inv(&a, n);
+        assert_tangents(&y, &numerical(&a, |a| Lu::new(a, n).unwrap().inverse()));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot solve a system of order 2 for 3")]
+    fn test_solve_shape_mismatch() {
+        let a = join(&[2.0, 0.0, 0.0, 2.0], &[0.0; 4]);
+        solve(&a, &join(&[1.0; 3], &[0.0; 3]), 2);
+    }
+
+    #[test]
+    fn test_det_and_logdet() {
+        let n = 3;
+        let a = along(&random_spd(n, 5), n, 6);
+
+        let d = det(&a, n);
+        assert_tangents(&[d], &numerical(&a, |a| vec![Lu::new(a, n).unwrap().det()]));
+        let d = logdet(&a, n);
+        assert_tangents(
+            &[d],
+            &numerical(&a, |a| vec![Lu::new(a, n).unwrap().ln_abs_det()]),
+        );
+    }
+
+    #[test]
+    fn test_singular_det() {
+        // Rank 2, moving along a direction that changes the rank.
+        let n = 3;
+        let a = join(
+            &[1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 1.0, 0.0, 1.0],
+            &random(n * n, 9),
+        );
+        let d = det(&a, n);
+
+        assert_eq!(d.value, 0.0);
+        assert_tangents(&[d], &numerical(&a, |a| vec![Lu::factor(a, n).det()]));
+        assert_eq!(logdet(&a, n).value, f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_cholesky() {
+        let n = 4;
+        let a = along(&random_spd(n, 7), n, 8);
+        let l = cholesky(&a, n);
+
+        assert_tangents(&l, &numerical(&a, |a| linalg::cholesky(a, n).unwrap()));
+        for i in 0..n {
+            for j in i + 1..n {
+                assert_eq!(l[i * n + j], Value::new(0.0, 0.0));
+            }
+        }
+    }
+}
@@ -1 +1,10 @@
+pub mod complex;
+#[cfg(feature = "float")]
+pub mod float;
+pub mod hyperdual;
+pub mod linalg;
+#[cfg(feature = "nalgebra")]
+pub mod realfield;
+pub mod softmax;
+pub mod taylor;
 pub mod value;
@@ -0,0 +1,579 @@
+use super::value::Value;
+use approx::{AbsDiffEq, RelativeEq, UlpsEq};
+use num_traits::{Float, FromPrimitive, Signed, Zero};
+use simba::scalar::{ComplexField, Field, RealField, SubsetOf};
+use simba::simd::SimdValue;
+
+/*
+ * nalgebra's `RealField` (and so `ComplexField`) for `Value`, so that
+ * `Matrix3<Value>`, `UnitQuaternion<Value>`, `Isometry3<Value>`, ... can be
+ * differentiated in the tangent mode as is.
+ *
+ * The math delegates to the `num_traits::Float` impl, so the same rules
+ * and subgradient policy apply. Comparisons (`approx`, `PartialOrd`) look
+ * at the primal values only, and constants are passive.
+ */
+
+impl SimdValue for Value {
+    const LANES: usize = 1;
+    type Element = Value;
+    type SimdBool = bool;
+
+    fn splat(val: Self::Element) -> Self {
+        val
+    }
+
+    fn extract(&self, _: usize) -> Self::Element {
+        *self
+    }
+
+    unsafe fn extract_unchecked(&self, _: usize) -> Self::Element {
+        *self
+    }
+
+    fn replace(&mut self, _: usize, val: Self::Element) {
+        *self = val
+    }
+
+    unsafe fn replace_unchecked(&mut self, _: usize, val: Self::Element) {
+        *self = val
+    }
+
+    fn select(self, cond: Self::SimdBool, other: Self) -> Self {
+        if cond {
+            self
+        } else {
+            other
+        }
+    }
+}
+
+impl Field for Value {}
+
+impl SubsetOf<Value> for Value {
+    fn to_superset(&self) -> Value {
+        *self
+    }
+
+    fn from_superset_unchecked(element: &Value) -> Self {
+        *element
+    }
+
+    fn is_in_subset(_: &Value) -> bool {
+        true
+    }
+}
+
+// Plain numbers embed as passive values; going back drops the tangent.
+macro_rules! impl_subset {
+    ($($t:ty),*) => {$(
+        impl SubsetOf<Value> for $t {
+            fn to_superset(&self) -> Value {
+                Value::passive(*self as f32)
+            }
+
+            fn from_superset_unchecked(element: &Value) -> Self {
+                element.value as $t
+            }
+
+            fn is_in_subset(_: &Value) -> bool {
+                true
+            }
+        }
+    )*};
+}
+
+impl_subset!(f32, f64);
+
+impl FromPrimitive for Value {
+    fn from_i64(n: i64) -> Option<Self> {
+        Some(Value::passive(n as f32))
+    }
+
+    fn from_u64(n: u64) -> Option<Self> {
+        Some(Value::passive(n as f32))
+    }
+
+    fn from_f64(n: f64) -> Option<Self> {
+        Some(Value::passive(n as f32))
+    }
+}
+
+impl Signed for Value {
+    fn abs(&self) -> Self {
+        Value::abs(*self)
+    }
+
+    fn abs_sub(&self, other: &Self) -> Self {
+        Float::abs_sub(*self, *other)
+    }
+
+    fn signum(&self) -> Self {
+        Float::signum(*self)
+    }
+
+    fn is_positive(&self) -> bool {
+        self.value > 0.0
+    }
+
+    fn is_negative(&self) -> bool {
+        self.value < 0.0
+    }
+}
+
+impl AbsDiffEq for Value {
+    type Epsilon = Value;
+
+    fn default_epsilon() -> Self::Epsilon {
+        Value::passive(f32::default_epsilon())
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: Self::Epsilon) -> bool {
+        self.value.abs_diff_eq(&other.value, epsilon.value)
+    }
+}
+
+impl RelativeEq for Value {
+    fn default_max_relative() -> Self::Epsilon {
+        Value::passive(f32::default_max_relative())
+    }
+
+    fn relative_eq(
+        &self,
+        other: &Self,
+        epsilon: Self::Epsilon,
+        max_relative: Self::Epsilon,
+    ) -> bool {
+        self.value
+            .relative_eq(&other.value, epsilon.value, max_relative.value)
+    }
+}
+
+impl UlpsEq for Value {
+    fn default_max_ulps() -> u32 {
+        f32::default_max_ulps()
+    }
+
+    fn ulps_eq(&self, other: &Self, epsilon: Self::Epsilon, max_ulps: u32) -> bool {
+        self.value.ulps_eq(&other.value, epsilon.value, max_ulps)
+    }
+}
+
+impl ComplexField for Value {
+    type RealField = Value;
+
+    fn from_real(re: Self::RealField) -> Self {
+        re
+    }
+
+    fn real(self) -> Self::RealField {
+        self
+    }
+
+    fn imaginary(self) -> Self::RealField {
+        Value::zero()
+    }
+
+    fn modulus(self) -> Self::RealField {
+        Value::abs(self)
+    }
+
+    fn modulus_squared(self) -> Self::RealField {
+        self * self
+    }
+
+    // 0 or pi, which is flat in x.
+    fn argument(self) -> Self::RealField {
+        if self.value >= 0.0 {
+            Value::zero()
+        } else {
+            Value::pi()
+        }
+    }
+
+    fn norm1(self) -> Self::RealField {
+        Value::abs(self)
+    }
+
+    fn scale(self, factor: Self::RealField) -> Self {
+        self * factor
+    }
+
+    fn unscale(self, factor: Self::RealField) -> Self {
+        self / factor
+    }
+
+    fn floor(self) -> Self {
+        Float::floor(self)
+    }
+
+    fn ceil(self) -> Self {
+        Float::ceil(self)
+    }
+
+    fn round(self) -> Self {
+        Float::round(self)
+    }
+
+    fn trunc(self) -> Self {
+        Float::trunc(self)
+    }
+
+    fn fract(self) -> Self {
+        Float::fract(self)
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        Float::mul_add(self, a, b)
+    }
+
+    fn abs(self) -> Self::RealField {
+        Value::abs(self)
+    }
+
+    fn hypot(self, other: Self) -> Self::RealField {
+        Float::hypot(self, other)
+    }
+
+    fn recip(self) -> Self {
+        Float::recip(self)
+    }
+
+    fn conjugate(self) -> Self {
+        self
+    }
+
+    fn sin(self) -> Self {
+        Float::sin(self)
+    }
+
+    fn cos(self) -> Self {
+        Float::cos(self)
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        Float::sin_cos(self)
+    }
+
+    fn tan(self) -> Self {
+        Float::tan(self)
+    }
+
+    fn asin(self) -> Self {
+        Float::asin(self)
+    }
+
+    fn acos(self) -> Self {
+        Float::acos(self)
+    }
+
+    fn atan(self) -> Self {
+        Float::atan(self)
+    }
+
+    fn sinh(self) -> Self {
+        Float::sinh(self)
+    }
+
+    fn cosh(self) -> Self {
+        Float::cosh(self)
+    }
+
+    fn tanh(self) -> Self {
+        Float::tanh(self)
+    }
+
+    fn asinh(self) -> Self {
+        Float::asinh(self)
+    }
+
+    fn acosh(self) -> Self {
+        Float::acosh(self)
+    }
+
+    fn atanh(self) -> Self {
+        Float::atanh(self)
+    }
+
+    fn log(self, base: Self::RealField) -> Self {
+        Float::log(self, base)
+    }
+
+    fn log2(self) -> Self {
+        Float::log2(self)
+    }
+
+    fn log10(self) -> Self {
+        Float::log10(self)
+    }
+
+    fn ln(self) -> Self {
+        Float::ln(self)
+    }
+
+    fn ln_1p(self) -> Self {
+        Float::ln_1p(self)
+    }
+
+    fn sqrt(self) -> Self {
+        Value::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        Float::exp(self)
+    }
+
+    fn exp2(self) -> Self {
+        Float::exp2(self)
+    }
+
+    fn exp_m1(self) -> Self {
+        Float::exp_m1(self)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        Float::powi(self, n)
+    }
+
+    fn powf(self, n: Self::RealField) -> Self {
+        Float::powf(self, n)
+    }
+
+    fn powc(self, n: Self) -> Self {
+        Float::powf(self, n)
+    }
+
+    fn cbrt(self) -> Self {
+        Float::cbrt(self)
+    }
+
+    fn is_finite(&self) -> bool {
+        Float::is_finite(*self)
+    }
+
+    fn try_sqrt(self) -> Option<Self> {
+        (self.value >= 0.0).then(|| Value::sqrt(self))
+    }
+}
+
+impl RealField for Value {
+    fn is_sign_positive(&self) -> bool {
+        Float::is_sign_positive(*self)
+    }
+
+    fn is_sign_negative(&self) -> bool {
+        Float::is_sign_negative(*self)
+    }
+
+    fn copysign(self, sign: Self) -> Self {
+        Float::copysign(self, sign)
+    }
+
+    fn max(self, other: Self) -> Self {
+        Value::max(self, other)
+    }
+
+    fn min(self, other: Self) -> Self {
+        Value::min(self, other)
+    }
+
+    fn clamp(self, min: Self, max: Self) -> Self {
+        Value::max(Value::min(self, max), min)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        Float::atan2(self, other)
+    }
+
+    fn min_value() -> Option<Self> {
+        Some(Value::passive(f32::MIN))
+    }
+
+    fn max_value() -> Option<Self> {
+        Some(Value::passive(f32::MAX))
+    }
+
+    fn pi() -> Self {
+        Value::passive(std::f32::consts::PI)
+    }
+
+    fn two_pi() -> Self {
+        Value::passive(std::f32::consts::TAU)
+    }
+
+    fn frac_pi_2() -> Self {
+        Value::passive(std::f32::consts::FRAC_PI_2)
+    }
+
+    fn frac_pi_3() -> Self {
+        Value::passive(std::f32::consts::FRAC_PI_3)
+    }
+
+    fn frac_pi_4() -> Self {
+        Value::passive(std::f32::consts::FRAC_PI_4)
+    }
+
+    fn frac_pi_6() -> Self {
+        Value::passive(std::f32::consts::FRAC_PI_6)
+    }
+
+    fn frac_pi_8() -> Self {
+        Value::passive(std::f32::consts::FRAC_PI_8)
+    }
+
+    fn frac_1_pi() -> Self {
+        Value::passive(std::f32::consts::FRAC_1_PI)
+    }
+
+    fn frac_2_pi() -> Self {
+        Value::passive(std::f32::consts::FRAC_2_PI)
+    }
+
+    fn frac_2_sqrt_pi() -> Self {
+        Value::passive(std::f32::consts::FRAC_2_SQRT_PI)
+    }
+
+    fn e() -> Self {
+        Value::passive(std::f32::consts::E)
+    }
+
+    fn log2_e() -> Self {
+        Value::passive(std::f32::consts::LOG2_E)
+    }
+
+    fn log10_e() -> Self {
+        Value::passive(std::f32::consts::LOG10_E)
+    }
+
+    fn ln_2() -> Self {
+        Value::passive(std::f32::consts::LN_2)
+    }
+
+    fn ln_10() -> Self {
+        Value::passive(std::f32::consts::LN_10)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gradcheck::{directional_difference, unit};
+    use nalgebra::{Isometry3, Matrix3, Point3, Rotation3, Translation3, UnitQuaternion, Vector3};
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn rotation(roll: Value, pitch: Value, yaw: Value) -> Matrix3<Value> {
+        let z = Rotation3::from_axis_angle(&Vector3::z_axis(), yaw);
+        let y = Rotation3::from_axis_angle(&Vector3::y_axis(), pitch);
+        let x = Rotation3::from_axis_angle(&Vector3::x_axis(), roll);
+        (z * y * x).into_inner()
+    }
+
+    #[test]
+    fn test_rotation_composition() {
+        let (roll, pitch, yaw) = (0.3, -0.4, 1.1);
+        let r = rotation(
+            Value::passive(roll),
+            Value::passive(pitch),
+            Value::new(yaw, 1.0),
+        );
+
+        // dR/dyaw = K R with K the cross-product matrix of the z axis.
+        let plain = rotation(
+            Value::passive(roll),
+            Value::passive(pitch),
+            Value::passive(yaw),
+        )
+        .map(|x| x.value);
+        let k = Matrix3::new(0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        let expected = k * plain;
+        for (d, e) in r.iter().zip(expected.iter()) {
+            assert_close(d.der, *e);
+        }
+
+        // Orthogonality holds to first order: d(R^T R) = 0.
+        let identity = r.transpose() * r;
+        for x in identity.iter() {
+            assert_close(x.der, 0.0);
+        }
+        assert_close(r.determinant().der, 0.0);
+    }
+
+    #[test]
+    fn test_point_transform() {
+        // A pose from a translation and roll, pitch, yaw; differentiate the
+        // transformed point with respect to each pose parameter.
+        let pose = [0.5, -1.0, 2.0, 0.2, 0.7, -0.3];
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let transform = |params: &[Value]| {
+            let translation = Translation3::new(params[0], params[1], params[2]);
+            let rotation = UnitQuaternion::from_euler_angles(params[3], params[4], params[5]);
+            Isometry3::from_parts(translation, rotation) * p.map(Value::passive)
+        };
+
+        for k in 0..6 {
+            let seeded: Vec<Value> = pose
+                .iter()
+                .enumerate()
+                .map(|(i, &x)| Value::new(x, if i == k { 1.0 } else { 0.0 }))
+                .collect();
+            let q = transform(&seeded);
+
+            let numerical = directional_difference(
+                |params| {
+                    let params: Vec<Value> = params.iter().map(|&x| Value::passive(x)).collect();
+                    let q = transform(&params);
+                    vec![q.x.value, q.y.value, q.z.value]
+                },
+                &pose,
+                &unit(6, k),
+                1e-3,
+            );
+            for i in 0..3 {
+                assert!(
+                    (q[i].der - numerical[i]).abs() < 1e-2,
+                    "parameter {} coordinate {}: {} != {}",
+                    k,
+                    i,
+                    q[i].der,
+                    numerical[i]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_translation_derivative_is_identity() {
+        let p = Point3::new(1.0, 2.0, 3.0).map(Value::passive);
+        let rotation = UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3).cast::<Value>();
+        let translation = Translation3::new(
+            Value::new(0.0, 1.0),
+            Value::passive(0.0),
+            Value::passive(0.0),
+        );
+        let q = Isometry3::from_parts(translation, rotation) * p;
+
+        assert_eq!([q.x.der, q.y.der, q.z.der], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_matrix_inverse() {
+        // d(A^-1) = -A^-1 dA A^-1 along dA = E_00.
+        let a = Matrix3::new(4.0, 1.0, 0.0, 1.0, 3.0, 1.0, 0.0, 1.0, 2.0);
+        let mut seeded = a.map(Value::passive);
+        seeded[(0, 0)] = Value::new(4.0, 1.0);
+
+        let inv = seeded.try_inverse().unwrap();
+        let plain = a.try_inverse().unwrap();
+        let expected = -plain * Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0) * plain;
+        for (d, e) in inv.iter().zip(expected.iter()) {
+            assert_close(d.der, *e);
+        }
+        assert!(approx::relative_eq!(
+            seeded.determinant(),
+            Value::passive(a.determinant())
+        ));
+    }
+}
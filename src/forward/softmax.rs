@@ -0,0 +1,102 @@
+use super::value::Value;
+use crate::softmax as kernels;
+
+/*
+ * Softmax and friends on slices of `Value`s. The primal goes through the
+ * max-shifted `f32` kernels, and with s = softmax(x) every tangent is a
+ * correction by the s-weighted mean of dx:
+ *   d logsumexp = <s, dx>,  d log_softmax = dx - <s, dx>,
+ *   d softmax = s (dx - <s, dx>).
+ */
+
+fn primal(x: &[Value]) -> Vec<f32> {
+    x.iter().map(|x| x.value).collect()
+}
+
+// <s, dx>.
+fn mean_tangent(s: &[f32], x: &[Value]) -> f32 {
+    s.iter().zip(x).map(|(s, x)| s * x.der).sum()
+}
+
+pub fn logsumexp(x: &[Value]) -> Value {
+    let values = primal(x);
+    let s = kernels::softmax(&values);
+    Value::new(kernels::logsumexp(&values), mean_tangent(&s, x))
+}
+
+pub fn softmax(x: &[Value]) -> Vec<Value> {
+    let s = kernels::softmax(&primal(x));
+    let mean = mean_tangent(&s, x);
+    s.iter()
+        .zip(x)
+        .map(|(&s, x)| Value::new(s, s * (x.der - mean)))
+        .collect()
+}
+
+pub fn log_softmax(x: &[Value]) -> Vec<Value> {
+    let l = kernels::log_softmax(&primal(x));
+    let s: Vec<f32> = l.iter().map(|l| l.exp()).collect();
+    let mean = mean_tangent(&s, x);
+    l.iter()
+        .zip(x)
+        .map(|(&l, x)| Value::new(l, x.der - mean))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::gradcheck::directional_difference;
+
+    // x + t v, seeded along v.
+    fn along(x: &[f32], v: &[f32]) -> Vec<Value> {
+        x.iter().zip(v).map(|(&x, &v)| Value::new(x, v)).collect()
+    }
+
+    // The same functions composed naively, in f64 so they stay exact enough.
+    fn naive_log_softmax(x: &[f64]) -> Vec<f64> {
+        let lse = x.iter().map(|x| x.exp()).sum::<f64>().ln();
+        x.iter().map(|x| x - lse).collect()
+    }
+
+    #[test]
+    fn test_tangents() {
+        let x = [0.3f32, -1.2, 2.0, 0.7];
+        let v = [1.0f32, 0.5, -2.0, 0.25];
+        let (x64, v64): (Vec<f64>, Vec<f64>) = x
+            .iter()
+            .zip(&v)
+            .map(|(&x, &v)| (x as f64, v as f64))
+            .unzip();
+        let dl = directional_difference(naive_log_softmax, &x64, &v64, 1e-4);
+        let ds = directional_difference(
+            |x| naive_log_softmax(x).iter().map(|l| l.exp()).collect(),
+            &x64,
+            &v64,
+            1e-4,
+        );
+
+        let l = log_softmax(&along(&x, &v));
+        let s = softmax(&along(&x, &v));
+        for i in 0..x.len() {
+            assert!((l[i].der as f64 - dl[i]).abs() < 1e-4);
+            assert!((s[i].der as f64 - ds[i]).abs() < 1e-4);
+            assert!((l[i].value.exp() - s[i].value).abs() < 1e-6);
+        }
+
+        // logsumexp(x) = x_i - log_softmax(x)_i, for any i.
+        let lse = logsumexp(&along(&x, &v));
+        assert!((lse.value - (x[0] - l[0].value)).abs() < 1e-6);
+        assert!((lse.der - (v[0] - l[0].der)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_large_logits() {
+        let x = along(&[1000.0, 1000.0, 990.0], &[1.0, 0.0, 0.0]);
+        let s = softmax(&x);
+
+        assert!(s.iter().all(|s| s.value.is_finite() && s.der.is_finite()));
+        assert!((s[0].value - s[1].value).abs() < 1e-6);
+        assert!((logsumexp(&x).value - (1000.0 + 2f32.ln())).abs() < 1e-3);
+    }
+}
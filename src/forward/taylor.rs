@@ -0,0 +1,497 @@
+use crate::subgradient;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/*
+ * A truncated Taylor polynomial, which carries the first N - 1
+ * derivatives through a computation in a single forward pass.
+ *
+ * coeffs[k] holds f^(k)(x) / k!, the normalized k-th derivative, so that
+ * arithmetic reduces to polynomial arithmetic truncated after N terms and
+ * elementary functions to the usual recurrences on the coefficients.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Taylor<const N: usize> {
+    pub coeffs: [f32; N],
+}
+
+impl<const N: usize> Taylor<N> {
+    pub fn constant(value: f32) -> Self {
+        let mut coeffs = [0.0; N];
+        coeffs[0] = value;
+        Taylor { coeffs }
+    }
+
+    // The independent variable, expanded around `value`.
+    pub fn variable(value: f32) -> Self {
+        let mut coeffs = [0.0; N];
+        coeffs[0] = value;
+        if N > 1 {
+            coeffs[1] = 1.0;
+        }
+        Taylor { coeffs }
+    }
+
+    pub fn new(coeffs: [f32; N]) -> Self {
+        Taylor { coeffs }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.coeffs[0]
+    }
+
+    // The k-th derivative, i.e. coeffs[k] * k!.
+    pub fn derivative(&self, k: usize) -> f32 {
+        let factorial: f32 = (1..=k).map(|i| i as f32).product();
+        self.coeffs[k] * factorial
+    }
+
+    pub fn derivatives(&self) -> [f32; N] {
+        let mut derivatives = [0.0; N];
+        for (k, derivative) in derivatives.iter_mut().enumerate() {
+            *derivative = self.derivative(k);
+        }
+        derivatives
+    }
+
+    // Polynomial evaluated at a displacement `h` from the expansion point.
+    pub fn eval(&self, h: f32) -> f32 {
+        self.coeffs.iter().rev().fold(0.0, |acc, c| acc * h + c)
+    }
+
+    // Series of u with u' = self' * h and u(x) = value.
+    fn integrate(&self, value: f32, h: &Self) -> Self {
+        let mut coeffs = [0.0; N];
+        coeffs[0] = value;
+        for (k, c) in coeffs.iter_mut().enumerate().skip(1) {
+            let sum: f32 = (1..=k)
+                .map(|j| j as f32 * self.coeffs[j] * h.coeffs[k - j])
+                .sum();
+            *c = sum / k as f32;
+        }
+        Taylor { coeffs }
+    }
+
+    fn scale(self, factor: f32) -> Self {
+        Taylor {
+            coeffs: self.coeffs.map(|c| c * factor),
+        }
+    }
+
+    pub fn exp(self) -> Self {
+        let a = &self.coeffs;
+        let mut coeffs = [0.0; N];
+        coeffs[0] = a[0].exp();
+        for k in 1..N {
+            let sum: f32 = (1..=k).map(|j| j as f32 * a[j] * coeffs[k - j]).sum();
+            coeffs[k] = sum / k as f32;
+        }
+        Taylor { coeffs }
+    }
+
+    pub fn ln(self) -> Self {
+        self.integrate(self.coeffs[0].ln(), &(Taylor::constant(1.0) / self))
+    }
+
+    pub fn pow(self, exp: f32) -> Self {
+        // From a * p' = exp * a' * p.
+        let a = &self.coeffs;
+        let mut coeffs = [0.0; N];
+        coeffs[0] = a[0].powf(exp);
+        for k in 1..N {
+            let sum: f32 = (1..=k)
+                .map(|j| ((exp + 1.0) * j as f32 - k as f32) * a[j] * coeffs[k - j])
+                .sum();
+            coeffs[k] = sum / (k as f32 * a[0]);
+        }
+        Taylor { coeffs }
+    }
+
+    // Integer powers by repeated squaring, well defined at zero.
+    pub fn powi(self, exp: i32) -> Self {
+        let mut result = Taylor::constant(1.0);
+        let mut base = self;
+        let mut n = exp.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            n >>= 1;
+        }
+        if exp < 0 {
+            Taylor::constant(1.0) / result
+        } else {
+            result
+        }
+    }
+
+    pub fn sqrt(self) -> Self {
+        // From s * s = a.
+        let a = &self.coeffs;
+        let mut coeffs = [0.0; N];
+        coeffs[0] = a[0].sqrt();
+        for k in 1..N {
+            let sum: f32 = (1..k).map(|j| coeffs[j] * coeffs[k - j]).sum();
+            coeffs[k] = (a[k] - sum) / (2.0 * coeffs[0]);
+        }
+        Taylor { coeffs }
+    }
+
+    pub fn sin_cos(self) -> (Self, Self) {
+        let a = &self.coeffs;
+        let mut sin = [0.0; N];
+        let mut cos = [0.0; N];
+        (sin[0], cos[0]) = a[0].sin_cos();
+        for k in 1..N {
+            let mut sin_sum = 0.0;
+            let mut cos_sum = 0.0;
+            for j in 1..=k {
+                sin_sum += j as f32 * a[j] * cos[k - j];
+                cos_sum += j as f32 * a[j] * sin[k - j];
+            }
+            sin[k] = sin_sum / k as f32;
+            cos[k] = -cos_sum / k as f32;
+        }
+        (Taylor { coeffs: sin }, Taylor { coeffs: cos })
+    }
+
+    pub fn sin(self) -> Self {
+        self.sin_cos().0
+    }
+
+    pub fn cos(self) -> Self {
+        self.sin_cos().1
+    }
+
+    pub fn tan(self) -> Self {
+        let (sin, cos) = self.sin_cos();
+        sin / cos
+    }
+
+    pub fn sinh_cosh(self) -> (Self, Self) {
+        let a = &self.coeffs;
+        let mut sinh = [0.0; N];
+        let mut cosh = [0.0; N];
+        sinh[0] = a[0].sinh();
+        cosh[0] = a[0].cosh();
+        for k in 1..N {
+            let mut sinh_sum = 0.0;
+            let mut cosh_sum = 0.0;
+            for j in 1..=k {
+                sinh_sum += j as f32 * a[j] * cosh[k - j];
+                cosh_sum += j as f32 * a[j] * sinh[k - j];
+            }
+            sinh[k] = sinh_sum / k as f32;
+            cosh[k] = cosh_sum / k as f32;
+        }
+        (Taylor { coeffs: sinh }, Taylor { coeffs: cosh })
+    }
+
+    pub fn sinh(self) -> Self {
+        self.sinh_cosh().0
+    }
+
+    pub fn cosh(self) -> Self {
+        self.sinh_cosh().1
+    }
+
+    pub fn tanh(self) -> Self {
+        let (sinh, cosh) = self.sinh_cosh();
+        sinh / cosh
+    }
+
+    pub fn asin(self) -> Self {
+        let one = Taylor::constant(1.0);
+        let h = one / (one - self * self).sqrt();
+        self.integrate(self.coeffs[0].asin(), &h)
+    }
+
+    pub fn acos(self) -> Self {
+        let one = Taylor::constant(1.0);
+        let h = -(one / (one - self * self).sqrt());
+        self.integrate(self.coeffs[0].acos(), &h)
+    }
+
+    pub fn atan(self) -> Self {
+        let one = Taylor::constant(1.0);
+        let h = one / (one + self * self);
+        self.integrate(self.coeffs[0].atan(), &h)
+    }
+
+    /*
+     * Piecewise-linear functions are linear on either side of their kink,
+     * so they scale the whole series by the slope picked by the active
+     * `subgradient::Subgradient` policy.
+     */
+
+    pub fn abs(self) -> Self {
+        self.scale(subgradient::slope(self.coeffs[0], -1.0, 1.0))
+    }
+
+    pub fn relu(self) -> Self {
+        self.scale(subgradient::slope(self.coeffs[0], 0.0, 1.0))
+    }
+
+    pub fn max(self, rhs: Self) -> Self {
+        let dresult_dself = subgradient::slope(self.coeffs[0] - rhs.coeffs[0], 0.0, 1.0);
+        self.scale(dresult_dself) + rhs.scale(1.0 - dresult_dself)
+    }
+
+    pub fn min(self, rhs: Self) -> Self {
+        let dresult_dself = subgradient::slope(self.coeffs[0] - rhs.coeffs[0], 1.0, 0.0);
+        self.scale(dresult_dself) + rhs.scale(1.0 - dresult_dself)
+    }
+
+    pub fn clamp(self, lo: f32, hi: f32) -> Self {
+        let slope = subgradient::clamp_slope(self.coeffs[0], lo, hi);
+        let mut result = self.scale(slope);
+        result.coeffs[0] = self.coeffs[0].clamp(lo, hi);
+        result
+    }
+
+    pub fn step(self) -> Self {
+        Taylor::constant(subgradient::slope(self.coeffs[0], 0.0, 1.0))
+    }
+}
+
+impl<const N: usize> Add for Taylor<N> {
+    type Output = Taylor<N>;
+
+    fn add(self, rhs: Taylor<N>) -> Self::Output {
+        let mut coeffs = self.coeffs;
+        for (c, r) in coeffs.iter_mut().zip(rhs.coeffs) {
+            *c += r;
+        }
+        Taylor { coeffs }
+    }
+}
+
+impl<const N: usize> Sub for Taylor<N> {
+    type Output = Taylor<N>;
+
+    fn sub(self, rhs: Taylor<N>) -> Self::Output {
+        let mut coeffs = self.coeffs;
+        for (c, r) in coeffs.iter_mut().zip(rhs.coeffs) {
+            *c -= r;
+        }
+        Taylor { coeffs }
+    }
+}
+
+impl<const N: usize> Mul for Taylor<N> {
+    type Output = Taylor<N>;
+
+    // Cauchy product, truncated.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn mul(self, rhs: Taylor<N>) -> Self::Output {
+        let mut coeffs = [0.0; N];
+        for (k, c) in coeffs.iter_mut().enumerate() {
+            *c = (0..=k).map(|j| self.coeffs[j] * rhs.coeffs[k - j]).sum();
+        }
+        Taylor { coeffs }
+    }
+}
+
+impl<const N: usize> Div for Taylor<N> {
+    type Output = Taylor<N>;
+
+    // Solves rhs * result = self term by term.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Taylor<N>) -> Self::Output {
+        let mut coeffs = [0.0; N];
+        for k in 0..N {
+            let sum: f32 = (1..=k).map(|j| rhs.coeffs[j] * coeffs[k - j]).sum();
+            coeffs[k] = (self.coeffs[k] - sum) / rhs.coeffs[0];
+        }
+        Taylor { coeffs }
+    }
+}
+
+impl<const N: usize> Neg for Taylor<N> {
+    type Output = Taylor<N>;
+
+    fn neg(self) -> Self::Output {
+        Taylor {
+            coeffs: self.coeffs.map(|c| -c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forward::value::Value;
+    use crate::scalar::{Function, Scalar};
+
+    fn assert_close<const N: usize>(actual: [f32; N], expected: [f32; N]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a - e).abs() <= 1e-5 * (1.0 + e.abs()),
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_constant_and_variable() {
+        let c = Taylor::<3>::constant(2.0);
+        let x = Taylor::<3>::variable(2.0);
+
+        assert_eq!(c.coeffs, [2.0, 0.0, 0.0]);
+        assert_eq!(x.coeffs, [2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_polynomial_derivatives() {
+        // f(x) = 3x^3 - x at x = 2: f = 22, f' = 35, f'' = 36, f''' = 18
+        let x = Taylor::<5>::variable(2.0);
+        let y = Taylor::constant(3.0) * x * x * x - x;
+
+        assert_close(y.derivatives(), [22.0, 35.0, 36.0, 18.0, 0.0]);
+    }
+
+    #[test]
+    fn test_division_geometric_series() {
+        // 1 / (1 - x) = 1 + x + x^2 + ...
+        let x = Taylor::<6>::variable(0.0);
+        let y = Taylor::constant(1.0) / (Taylor::constant(1.0) - x);
+
+        assert_close(y.coeffs, [1.0; 6]);
+    }
+
+    #[test]
+    fn test_exp_ln() {
+        let x = Taylor::<6>::variable(0.0);
+
+        assert_close(
+            x.exp().coeffs,
+            [1.0, 1.0, 0.5, 1.0 / 6.0, 1.0 / 24.0, 1.0 / 120.0],
+        );
+        assert_close(
+            (Taylor::constant(1.0) + x).ln().coeffs,
+            [0.0, 1.0, -0.5, 1.0 / 3.0, -0.25, 0.2],
+        );
+        assert_close(
+            Taylor::<4>::variable(1.5).exp().ln().coeffs,
+            [1.5, 1.0, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn test_pow_sqrt() {
+        // (1 + x)^r = 1 + r x + r(r-1)/2 x^2 + r(r-1)(r-2)/6 x^3
+        let x = Taylor::<4>::variable(0.0);
+        let one = Taylor::constant(1.0);
+
+        assert_close((one + x).pow(-1.5).coeffs, [1.0, -1.5, 1.875, -2.1875]);
+        assert_close((one + x).sqrt().coeffs, [1.0, 0.5, -0.125, 0.0625]);
+        assert_close((one + x).pow(0.5).coeffs, (one + x).sqrt().coeffs);
+    }
+
+    #[test]
+    fn test_powi() {
+        let x = Taylor::<5>::variable(0.0);
+
+        assert_close(x.powi(3).coeffs, [0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_close(
+            (Taylor::constant(2.0) + x).powi(-1).coeffs,
+            [0.5, -0.25, 0.125, -0.0625, 0.03125],
+        );
+    }
+
+    #[test]
+    fn test_trigonometric() {
+        let x = Taylor::<6>::variable(0.0);
+
+        assert_close(x.sin().derivatives(), [0.0, 1.0, 0.0, -1.0, 0.0, 1.0]);
+        assert_close(x.cos().derivatives(), [1.0, 0.0, -1.0, 0.0, 1.0, 0.0]);
+        assert_close(x.tan().derivatives(), [0.0, 1.0, 0.0, 2.0, 0.0, 16.0]);
+    }
+
+    #[test]
+    fn test_hyperbolic() {
+        let x = Taylor::<6>::variable(0.0);
+
+        assert_close(x.sinh().derivatives(), [0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+        assert_close(x.cosh().derivatives(), [1.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+        assert_close(
+            x.tanh().coeffs,
+            [0.0, 1.0, 0.0, -1.0 / 3.0, 0.0, 2.0 / 15.0],
+        );
+    }
+
+    #[test]
+    fn test_inverse_trigonometric() {
+        let x = Taylor::<6>::variable(0.0);
+
+        assert_close(x.asin().coeffs, [0.0, 1.0, 0.0, 1.0 / 6.0, 0.0, 3.0 / 40.0]);
+        assert_close(
+            x.acos().coeffs,
+            [
+                std::f32::consts::FRAC_PI_2,
+                -1.0,
+                0.0,
+                -1.0 / 6.0,
+                0.0,
+                -3.0 / 40.0,
+            ],
+        );
+        assert_close(x.atan().coeffs, [0.0, 1.0, 0.0, -1.0 / 3.0, 0.0, 0.2]);
+    }
+
+    #[test]
+    fn test_piecewise() {
+        let x = Taylor::<3>::new([-2.0, 1.0, 0.5]);
+
+        assert_eq!(x.abs().coeffs, [2.0, -1.0, -0.5]);
+        assert_eq!(x.relu().coeffs, [0.0, 0.0, 0.0]);
+        assert_eq!(x.max(Taylor::constant(1.0)).coeffs, [1.0, 0.0, 0.0]);
+        assert_eq!(x.min(Taylor::constant(1.0)).coeffs, x.coeffs);
+        assert_eq!(x.clamp(-3.0, 0.0).coeffs, x.coeffs);
+        assert_eq!(x.clamp(-1.0, 0.0).coeffs, [-1.0, 0.0, 0.0]);
+        assert_eq!(x.step().coeffs, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_first_order_matches_value() {
+        // f(x) = exp(sin(x)) / sqrt(x)
+        let x = Taylor::<2>::variable(1.3);
+        let y = x.sin().exp() / x.sqrt();
+
+        let v = Value::new(1.3, 1.0);
+        let (sin, cos) = v.value.sin_cos();
+        let expected = Value::new(sin.exp(), cos * sin.exp() * v.der) / v.sqrt();
+
+        assert!((y.value() - expected.value).abs() < 1e-6);
+        assert!((y.derivative(1) - expected.der).abs() < 1e-5);
+    }
+
+    struct Cubic;
+
+    impl Function for Cubic {
+        fn eval<T: Scalar>(&self, x: &[T]) -> T {
+            x[0].clone().pow(3.0) - T::constant(2.0) * x[0].clone()
+        }
+    }
+
+    #[test]
+    fn test_generic_function() {
+        // f(x) = x^3 - 2x at x = 2: f = 4, f' = 10, f'' = 12, f''' = 6
+        let y = Cubic.eval(&[Taylor::<4>::variable(2.0)]);
+
+        assert_close(y.derivatives(), [4.0, 10.0, 12.0, 6.0]);
+    }
+
+    #[test]
+    fn test_taylor_ode_step() {
+        // y' = y, y(0) = 1: the Taylor coefficients of exp(t) give one
+        // high-order step of the solution.
+        let t = Taylor::<8>::variable(0.0);
+        let y = t.exp();
+
+        assert!((y.eval(0.5) - 0.5_f32.exp()).abs() < 1e-5);
+    }
+}
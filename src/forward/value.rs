@@ -1,11 +1,19 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use crate::anomaly;
+use crate::subgradient;
+use num_traits::{One, Zero};
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::{Product, Sum};
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
+};
 
 /*
  * A wrapper around a numerical value, which
  * performs a derivative computation in the tangent mode.
  */
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Value {
     pub value: f32,
     pub der: f32,
@@ -23,21 +31,86 @@ impl Value {
     pub fn pow(self, exp: f32) -> Self {
         let value = self.value.powf(exp);
         let der = exp * self.value.powf(exp - 1.0) * self.der;
-        Value { value, der }
+        Value { value, der }.checked("pow", &[self])
     }
 
     pub fn sqrt(self) -> Self {
         let value = self.value.sqrt();
         let der = 0.5 * self.value.powf(-0.5) * self.der;
-        Value { value, der }
+        Value { value, der }.checked("sqrt", &[self])
+    }
+
+    pub fn exp(self) -> Self {
+        let value = self.value.exp();
+        let der = value * self.der;
+        Value { value, der }.checked("exp", &[self])
+    }
+
+    pub fn ln(self) -> Self {
+        let value = self.value.ln();
+        let der = self.der / self.value;
+        Value { value, der }.checked("ln", &[self])
+    }
+
+    pub fn tanh(self) -> Self {
+        let value = self.value.tanh();
+        let der = (1.0 - value * value) * self.der;
+        Value { value, der }.checked("tanh", &[self])
     }
 
+    pub fn sigmoid(self) -> Self {
+        let value = 1.0 / (1.0 + (-self.value).exp());
+        let der = value * (1.0 - value) * self.der;
+        Value { value, der }.checked("sigmoid", &[self])
+    }
+
+    /*
+     * The piecewise-linear functions below take their derivative at the
+     * kink from the active `subgradient::Subgradient` policy.
+     */
+
     pub fn relu(self) -> Self {
-        if self.value > 0.0 {
-            self
-        } else {
-            Value::new(0.0, 0.0)
-        }
+        let value = self.value.max(0.0);
+        let der = subgradient::slope(self.value, 0.0, 1.0) * self.der;
+        Value { value, der }.checked("relu", &[self])
+    }
+
+    pub fn abs(self) -> Self {
+        let value = self.value.abs();
+        let der = subgradient::slope(self.value, -1.0, 1.0) * self.der;
+        Value { value, der }.checked("abs", &[self])
+    }
+
+    pub fn max(self, rhs: Value) -> Self {
+        let dresult_dself = subgradient::slope(self.value - rhs.value, 0.0, 1.0);
+        let value = self.value.max(rhs.value);
+        let der = dresult_dself * self.der + (1.0 - dresult_dself) * rhs.der;
+        Value { value, der }.checked("max", &[self, rhs])
+    }
+
+    pub fn min(self, rhs: Value) -> Self {
+        let dresult_dself = subgradient::slope(self.value - rhs.value, 1.0, 0.0);
+        let value = self.value.min(rhs.value);
+        let der = dresult_dself * self.der + (1.0 - dresult_dself) * rhs.der;
+        Value { value, der }.checked("min", &[self, rhs])
+    }
+
+    pub fn clamp(self, lo: f32, hi: f32) -> Self {
+        let value = self.value.clamp(lo, hi);
+        let der = subgradient::clamp_slope(self.value, lo, hi) * self.der;
+        Value { value, der }.checked("clamp", &[self])
+    }
+
+    // Heaviside step; flat everywhere, the policy only sets its value at 0.
+    pub fn step(self) -> Self {
+        let value = subgradient::slope(self.value, 0.0, 1.0);
+        Value { value, der: 0.0 }.checked("step", &[self])
+    }
+
+    // Reports a NaN or Inf result when anomaly detection is enabled.
+    pub(crate) fn checked(self, op: &str, operands: &[Value]) -> Self {
+        anomaly::check_value(op, operands, self);
+        self
     }
 }
 
@@ -47,7 +120,7 @@ impl Add for Value {
     fn add(self, rhs: Value) -> Self::Output {
         let value = self.value + rhs.value;
         let der = self.der + rhs.der;
-        Value { value, der }
+        Value { value, der }.checked("add", &[self, rhs])
     }
 }
 
@@ -57,7 +130,7 @@ impl Sub for Value {
     fn sub(self, rhs: Value) -> Self::Output {
         let value = self.value - rhs.value;
         let der = self.der - rhs.der;
-        Value { value, der }
+        Value { value, der }.checked("sub", &[self, rhs])
     }
 }
 
@@ -67,17 +140,35 @@ impl Mul for Value {
     fn mul(self, rhs: Value) -> Self::Output {
         let value = self.value * rhs.value;
         let der = rhs.value * self.der + self.value * rhs.der;
-        Value { value, der }
+        Value { value, der }.checked("mul", &[self, rhs])
     }
 }
 
 impl Div for Value {
     type Output = Value;
 
-    fn div(self, mut rhs: Value) -> Self::Output {
-        rhs.der = -rhs.der / rhs.value / rhs.value;
-        rhs.value = 1.0 / rhs.value;
-        self * rhs
+    // The quotient is the product with the reciprocal of `rhs`.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Value) -> Self::Output {
+        let inv_der = -rhs.der / rhs.value / rhs.value;
+        let inv_value = 1.0 / rhs.value;
+        let value = self.value * inv_value;
+        let der = inv_value * self.der + self.value * inv_der;
+        Value { value, der }.checked("div", &[self, rhs])
+    }
+}
+
+impl Rem for Value {
+    type Output = Value;
+
+    // Truncated remainder, self - rhs * trunc(self / rhs). The quotient is
+    // piecewise constant and does not contribute to the derivative.
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn rem(self, rhs: Value) -> Self::Output {
+        let quotient = (self.value / rhs.value).trunc();
+        let value = self.value % rhs.value;
+        let der = self.der - quotient * rhs.der;
+        Value { value, der }.checked("rem", &[self, rhs])
     }
 }
 
@@ -87,7 +178,108 @@ impl Neg for Value {
     fn neg(self) -> Self::Output {
         let value = -self.value;
         let der = -self.der;
-        Value { value, der }
+        Value { value, der }.checked("neg", &[self])
+    }
+}
+
+/*
+ * Comparisons look at the primal value only, so that branches such as
+ * `if x > y` behave as they would on plain numbers. The derivative
+ * is ignored: two values are equal whenever their values are.
+ */
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.value == other.value
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (der {})", self.value, self.der)
+    }
+}
+
+// A plain number is a constant.
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::passive(value)
+    }
+}
+
+impl Zero for Value {
+    fn zero() -> Self {
+        Value::passive(0.0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value == 0.0
+    }
+}
+
+impl One for Value {
+    fn one() -> Self {
+        Value::passive(1.0)
+    }
+}
+
+impl Sum for Value {
+    fn sum<I: Iterator<Item = Value>>(iter: I) -> Self {
+        iter.fold(Value::zero(), |acc, x| acc + x)
+    }
+}
+
+impl<'a> Sum<&'a Value> for Value {
+    fn sum<I: Iterator<Item = &'a Value>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+impl Product for Value {
+    fn product<I: Iterator<Item = Value>>(iter: I) -> Self {
+        iter.fold(Value::one(), |acc, x| acc * x)
+    }
+}
+
+impl<'a> Product<&'a Value> for Value {
+    fn product<I: Iterator<Item = &'a Value>>(iter: I) -> Self {
+        iter.copied().product()
+    }
+}
+
+impl AddAssign for Value {
+    fn add_assign(&mut self, rhs: Value) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Value {
+    fn sub_assign(&mut self, rhs: Value) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Value {
+    fn mul_assign(&mut self, rhs: Value) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign for Value {
+    fn div_assign(&mut self, rhs: Value) {
+        *self = *self / rhs;
+    }
+}
+
+impl RemAssign for Value {
+    fn rem_assign(&mut self, rhs: Value) {
+        *self = *self % rhs;
     }
 }
 
@@ -158,6 +350,80 @@ mod test {
         assert_eq!(y.der, 0.0);
     }
 
+    #[test]
+    fn test_abs_operator() {
+        let x = Value::new(-1.5, 2.5).abs();
+        let y = Value::new(1.5, 2.5).abs();
+
+        assert_eq!(x.value, 1.5);
+        assert_eq!(x.der, -2.5);
+        assert_eq!(y.value, 1.5);
+        assert_eq!(y.der, 2.5);
+    }
+
+    #[test]
+    fn test_max_min_operators() {
+        let a = Value::new(1.0, 2.0);
+        let b = Value::new(3.0, 4.0);
+
+        assert_eq!(a.max(b).value, 3.0);
+        assert_eq!(a.max(b).der, 4.0);
+        assert_eq!(a.min(b).value, 1.0);
+        assert_eq!(a.min(b).der, 2.0);
+    }
+
+    #[test]
+    fn test_clamp_operator() {
+        let x = Value::new(0.5, 2.0);
+
+        assert_eq!(x.clamp(0.0, 1.0).der, 2.0);
+        assert_eq!(x.clamp(1.0, 2.0).value, 1.0);
+        assert_eq!(x.clamp(1.0, 2.0).der, 0.0);
+    }
+
+    #[test]
+    fn test_step_operator() {
+        assert_eq!(Value::new(-0.5, 1.0).step().value, 0.0);
+        assert_eq!(Value::new(0.5, 1.0).step().value, 1.0);
+        assert_eq!(Value::new(0.5, 1.0).step().der, 0.0);
+    }
+
+    #[test]
+    fn test_kinks_follow_policy() {
+        use crate::subgradient::{with_subgradient, Subgradient};
+
+        let zero = Value::new(0.0, 1.0);
+        let a = Value::new(1.0, 1.0);
+        let b = Value::new(1.0, 0.0);
+
+        let kinks = |policy| {
+            with_subgradient(policy, || {
+                [
+                    zero.relu().der,
+                    zero.abs().der,
+                    a.max(b).der,
+                    a.min(b).der,
+                    a.clamp(1.0, 2.0).der,
+                    a.clamp(0.0, 1.0).der,
+                    zero.step().value,
+                ]
+            })
+        };
+
+        assert_eq!(
+            kinks(Subgradient::Left),
+            [0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 0.0]
+        );
+        assert_eq!(
+            kinks(Subgradient::Right),
+            [1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0]
+        );
+        assert_eq!(
+            kinks(Subgradient::Midpoint),
+            [0.5, 0.0, 0.5, 0.5, 0.5, 0.5, 0.5]
+        );
+    }
+
     fn f1(x: Value) -> Value {
         // f1(x)    = 2 * x^3
         // f1'(x)   = 6 * x^2
@@ -222,6 +488,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn test_differentiate_chain() {
         // f4(x)    = f1(f2(x)) = 2 * ( 2 / x^-0.5 )^3
         //          = 16 / x^1.5
@@ -232,4 +499,85 @@ mod test {
         assert_eq!(y.value, 5.656854249);
         assert_eq!(y.der, -4.242640687);
     }
+
+    #[test]
+    fn test_rem_operator() {
+        let x = Value::new(7.5, 1.0) % Value::new(2.0, 1.0);
+        let y = Value::new(-7.5, 1.0) % Value::new(2.0, 0.0);
+
+        assert_eq!(x.value, 1.5);
+        assert_eq!(x.der, -2.0);
+        assert_eq!(y.value, -1.5);
+        assert_eq!(y.der, 1.0);
+    }
+
+    #[test]
+    fn test_comparisons_use_primal() {
+        let a = Value::new(1.0, 5.0);
+        let b = Value::new(1.0, -5.0);
+        let c = Value::new(2.0, 0.0);
+
+        assert_eq!(a, b);
+        assert!(a < c);
+        assert!(c >= b);
+        assert_eq!(a.partial_cmp(&Value::passive(f32::NAN)), None);
+
+        let mut xs = [c, a, Value::new(-3.0, 1.0)];
+        xs.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(xs.map(|x| x.value), [-3.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::new(1.5, -2.0).to_string(), "1.5 (der -2)");
+    }
+
+    #[test]
+    fn test_default_and_from() {
+        let x = Value::default();
+        let y = Value::from(3.0);
+
+        assert_eq!((x.value, x.der), (0.0, 0.0));
+        assert_eq!((y.value, y.der), (3.0, 0.0));
+    }
+
+    #[test]
+    fn test_zero_one() {
+        assert!(Value::zero().is_zero());
+        assert!(Value::new(0.0, 1.0).is_zero());
+        assert!(Value::one().is_one());
+        assert_eq!(Value::one().der, 0.0);
+    }
+
+    #[test]
+    fn test_sum_product() {
+        let xs = [
+            Value::new(1.0, 1.0),
+            Value::new(2.0, 0.0),
+            Value::new(3.0, 2.0),
+        ];
+
+        let sum: Value = xs.iter().sum();
+        assert_eq!((sum.value, sum.der), (6.0, 3.0));
+
+        // d(abc) = b c da + a c db + a b dc
+        let product: Value = xs.into_iter().product();
+        assert_eq!((product.value, product.der), (6.0, 6.0 + 0.0 + 4.0));
+
+        let empty: Value = std::iter::empty::<Value>().product();
+        assert_eq!((empty.value, empty.der), (1.0, 0.0));
+    }
+
+    #[test]
+    fn test_assign_operators() {
+        let x = Value::new(2.0, 1.0);
+        let mut y = x;
+        y += x;
+        y *= x;
+        y -= Value::passive(1.0);
+        y /= x;
+
+        let z = ((x + x) * x - Value::passive(1.0)) / x;
+        assert_eq!((y.value, y.der), (z.value, z.der));
+    }
 }
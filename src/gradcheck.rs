@@ -0,0 +1,537 @@
+use crate::backprop::grad::grad;
+use crate::backprop::tape::scoped;
+use crate::backprop::variable::Variable;
+use crate::forward::value::Value;
+use crate::scalar::{Function, Scalar};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/*
+ * Finite-difference gradient checking.
+ *
+ * The derivatives of `f` with respect to each input are computed in
+ * tangent mode (`Value`), on the tape (`Variable`) and numerically, and
+ * both AD results are compared against the numerical one. The tests of
+ * tensors and of the other tangent types share `directional_difference`
+ * and `Error` for the same comparison.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Difference {
+    // (f(x + eps) - f(x - eps)) / 2eps, accurate to O(eps^2).
+    Central,
+    // Im(f(x + i eps)) / eps, free of cancellation so eps can be tiny.
+    ComplexStep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Error {
+    pub abs: f32,
+    pub rel: f32,
+}
+
+impl Error {
+    pub fn new(computed: f32, expected: f32) -> Self {
+        let abs = (computed - expected).abs();
+        let scale = computed.abs().max(expected.abs());
+        let rel = if scale > 0.0 { abs / scale } else { 0.0 };
+        Error { abs, rel }
+    }
+
+    pub fn within(&self, tol: f32) -> bool {
+        self.abs <= tol || self.rel <= tol
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputReport {
+    pub numerical: f32,
+    pub forward: f32,
+    pub reverse: f32,
+    pub forward_error: Error,
+    pub reverse_error: Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradcheckReport {
+    pub tol: f32,
+    pub inputs: Vec<InputReport>,
+}
+
+impl GradcheckReport {
+    pub fn passed(&self) -> bool {
+        self.inputs.iter().all(|input| {
+            input.forward_error.within(self.tol) && input.reverse_error.within(self.tol)
+        })
+    }
+}
+
+pub fn gradcheck<F: Function>(f: &F, x: &[f32], eps: f32, tol: f32) -> GradcheckReport {
+    gradcheck_with(f, x, eps, tol, Difference::Central)
+}
+
+pub fn gradcheck_with<F: Function>(
+    f: &F,
+    x: &[f32],
+    eps: f32,
+    tol: f32,
+    difference: Difference,
+) -> GradcheckReport {
+    let reverse = reverse_gradient(f, x);
+    let inputs = (0..x.len())
+        .map(|i| {
+            let numerical = match difference {
+                Difference::Central => central_difference(f, x, i, eps),
+                Difference::ComplexStep => complex_step(f, x, i, eps),
+            };
+            let forward = forward_derivative(f, x, i);
+            InputReport {
+                numerical,
+                forward,
+                reverse: reverse[i],
+                forward_error: Error::new(forward, numerical),
+                reverse_error: Error::new(reverse[i], numerical),
+            }
+        })
+        .collect();
+
+    GradcheckReport { tol, inputs }
+}
+
+/*
+ * The derivative of every output of `f` at `x` along `v`:
+ *   (f(x + eps v) - f(x - eps v)) / 2eps.
+ * Generic so that tests can difference in `f64` where `f32` cancels.
+ */
+pub fn directional_difference<T>(f: impl Fn(&[T]) -> Vec<T>, x: &[T], v: &[T], eps: T) -> Vec<T>
+where
+    T: Copy
+        + Add<Output = T>
+        + Sub<Output = T>
+        + Mul<Output = T>
+        + Div<Output = T>
+        + Neg<Output = T>,
+{
+    let shifted = |t: T| {
+        f(&x.iter()
+            .zip(v)
+            .map(|(&x, &v)| x + t * v)
+            .collect::<Vec<_>>())
+    };
+    let (up, down) = (shifted(eps), shifted(-eps));
+    up.iter()
+        .zip(&down)
+        .map(|(&u, &d)| (u - d) / (eps + eps))
+        .collect()
+}
+
+// The unit vector along input `i` of `n`.
+pub fn unit(n: usize, i: usize) -> Vec<f32> {
+    (0..n).map(|j| if j == i { 1.0 } else { 0.0 }).collect()
+}
+
+fn central_difference<F: Function>(f: &F, x: &[f32], i: usize, eps: f32) -> f32 {
+    directional_difference(|x| vec![f.eval(x)], x, &unit(x.len(), i), eps)[0]
+}
+
+fn complex_step<F: Function>(f: &F, x: &[f32], i: usize, eps: f32) -> f32 {
+    let z: Vec<ComplexStep> = x
+        .iter()
+        .enumerate()
+        .map(|(j, &re)| ComplexStep {
+            re,
+            im: if j == i { eps } else { 0.0 },
+        })
+        .collect();
+    f.eval(&z).im / eps
+}
+
+fn forward_derivative<F: Function>(f: &F, x: &[f32], i: usize) -> f32 {
+    let mut x: Vec<Value> = x.iter().map(|&v| Value::passive(v)).collect();
+    x[i].der = 1.0; // seed the desired partial
+    f.eval(&x).der
+}
+
+fn reverse_gradient<F: Function>(f: &F, x: &[f32]) -> Vec<f32> {
+    // Keep the check off the caller's tape.
+    let (dy_dx, _) = scoped(|| {
+        let x: Vec<Variable> = x.iter().map(|&v| Variable::new(v, None)).collect();
+        let y = f.eval(&x);
+        grad(&y, &x)
+    });
+
+    dy_dx
+        .iter()
+        .map(|d| d.as_ref().map_or(0.0, |d| d.value))
+        .collect()
+}
+
+impl fmt::Display for GradcheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "gradcheck (tol = {}):", self.tol)?;
+        for (i, input) in self.inputs.iter().enumerate() {
+            writeln!(
+                f,
+                "  x{}: numerical = {}, forward = {} (abs {}, rel {}), reverse = {} (abs {}, rel {})",
+                i,
+                input.numerical,
+                input.forward,
+                input.forward_error.abs,
+                input.forward_error.rel,
+                input.reverse,
+                input.reverse_error.abs,
+                input.reverse_error.rel
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * Complex number used for the complex-step difference. The imaginary part
+ * carries eps times the derivative, the same way `Value::der` does.
+ */
+#[derive(Debug, Clone, Copy)]
+struct ComplexStep {
+    re: f32,
+    im: f32,
+}
+
+impl Add for ComplexStep {
+    type Output = ComplexStep;
+
+    fn add(self, rhs: ComplexStep) -> Self::Output {
+        ComplexStep {
+            re: self.re + rhs.re,
+            im: self.im + rhs.im,
+        }
+    }
+}
+
+impl Sub for ComplexStep {
+    type Output = ComplexStep;
+
+    fn sub(self, rhs: ComplexStep) -> Self::Output {
+        ComplexStep {
+            re: self.re - rhs.re,
+            im: self.im - rhs.im,
+        }
+    }
+}
+
+impl Mul for ComplexStep {
+    type Output = ComplexStep;
+
+    fn mul(self, rhs: ComplexStep) -> Self::Output {
+        ComplexStep {
+            re: self.re * rhs.re - self.im * rhs.im,
+            im: self.re * rhs.im + self.im * rhs.re,
+        }
+    }
+}
+
+impl Div for ComplexStep {
+    type Output = ComplexStep;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: ComplexStep) -> Self::Output {
+        let norm = rhs.re * rhs.re + rhs.im * rhs.im;
+        ComplexStep {
+            re: (self.re * rhs.re + self.im * rhs.im) / norm,
+            im: (self.im * rhs.re - self.re * rhs.im) / norm,
+        }
+    }
+}
+
+impl Neg for ComplexStep {
+    type Output = ComplexStep;
+
+    fn neg(self) -> Self::Output {
+        ComplexStep {
+            re: -self.re,
+            im: -self.im,
+        }
+    }
+}
+
+impl Scalar for ComplexStep {
+    fn constant(value: f32) -> Self {
+        ComplexStep { re: value, im: 0.0 }
+    }
+
+    fn primal(&self) -> f32 {
+        self.re
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        // Integer powers by repeated multiplication, which stays exact for
+        // negative bases where the polar form picks up rounding in the angle.
+        if exp.fract() == 0.0 && exp.abs() <= 64.0 {
+            let mut result = ComplexStep::constant(1.0);
+            for _ in 0..exp.abs() as usize {
+                result = result * self;
+            }
+            return if exp < 0.0 {
+                ComplexStep::constant(1.0) / result
+            } else {
+                result
+            };
+        }
+
+        // Principal branch, in polar form.
+        let modulus = self.re.hypot(self.im).powf(exp);
+        let arg = self.im.atan2(self.re) * exp;
+        ComplexStep {
+            re: modulus * arg.cos(),
+            im: modulus * arg.sin(),
+        }
+    }
+
+    fn sqrt(self) -> Self {
+        self.pow(0.5)
+    }
+
+    fn exp(self) -> Self {
+        let modulus = self.re.exp();
+        ComplexStep {
+            re: modulus * self.im.cos(),
+            im: modulus * self.im.sin(),
+        }
+    }
+
+    fn ln(self) -> Self {
+        ComplexStep {
+            re: self.re.hypot(self.im).ln(),
+            im: self.im.atan2(self.re),
+        }
+    }
+
+    // (sinh 2a + i sin 2b) / (cosh 2a + cos 2b), without cancellation.
+    fn tanh(self) -> Self {
+        let norm = (2.0 * self.re).cosh() + (2.0 * self.im).cos();
+        ComplexStep {
+            re: (2.0 * self.re).sinh() / norm,
+            im: (2.0 * self.im).sin() / norm,
+        }
+    }
+
+    fn relu(self) -> Self {
+        if self.re > 0.0 {
+            self
+        } else {
+            ComplexStep::constant(0.0)
+        }
+    }
+
+    fn abs(self) -> Self {
+        if self.re < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        if self.re > rhs.re {
+            self
+        } else {
+            rhs
+        }
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        if self.re < rhs.re {
+            self
+        } else {
+            rhs
+        }
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        if self.re < lo {
+            ComplexStep::constant(lo)
+        } else if self.re > hi {
+            ComplexStep::constant(hi)
+        } else {
+            self
+        }
+    }
+
+    fn step(self) -> Self {
+        ComplexStep::constant(self.re.step())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! function {
+        ($name:ident, |$x:ident| $body:expr) => {
+            struct $name;
+
+            impl Function for $name {
+                fn eval<T: Scalar>(&self, $x: &[T]) -> T {
+                    $body
+                }
+            }
+        };
+    }
+
+    function!(AddOp, |x| x[0].clone() + x[1].clone());
+    function!(SubOp, |x| x[0].clone() - x[1].clone());
+    function!(MulOp, |x| x[0].clone() * x[1].clone());
+    function!(DivOp, |x| x[0].clone() / x[1].clone());
+    function!(NegOp, |x| -x[0].clone());
+    function!(PowOp, |x| x[0].clone().pow(3.0));
+    function!(SqrtOp, |x| x[0].clone().sqrt());
+    function!(ExpOp, |x| x[0].clone().exp());
+    function!(LnOp, |x| x[0].clone().ln());
+    function!(TanhOp, |x| x[0].clone().tanh());
+    function!(SigmoidOp, |x| x[0].clone().sigmoid());
+    function!(ReluOp, |x| x[0].clone().relu());
+    function!(AbsOp, |x| x[0].clone().abs());
+    function!(MaxOp, |x| x[0].clone().max(x[1].clone()));
+    function!(MinOp, |x| x[0].clone().min(x[1].clone()));
+    function!(ClampOp, |x| x[0].clone().clamp(-1.0, 1.0));
+    function!(StepOp, |x| x[0].clone().step() * x[1].clone());
+    function!(Chain, |x| {
+        // 2 * (2 / x^0.5)^3 * y - x / y
+        let a = T::constant(2.0);
+        let inner = a.clone() / x[0].clone().sqrt();
+        a * inner.pow(3.0) * x[1].clone() - x[0].clone() / x[1].clone()
+    });
+
+    fn assert_passes<F: Function>(f: &F, x: &[f32]) {
+        let report = gradcheck(f, x, 1e-2, 1e-3);
+        assert!(report.passed(), "{}", report);
+
+        let report = gradcheck_with(f, x, 1e-6, 1e-5, Difference::ComplexStep);
+        assert!(report.passed(), "{}", report);
+    }
+
+    #[test]
+    fn test_gradcheck_add() {
+        assert_passes(&AddOp, &[1.5, -2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_sub() {
+        assert_passes(&SubOp, &[1.5, -2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_mul() {
+        assert_passes(&MulOp, &[1.5, -2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_div() {
+        assert_passes(&DivOp, &[1.5, -2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_neg() {
+        assert_passes(&NegOp, &[1.5]);
+    }
+
+    #[test]
+    fn test_gradcheck_pow() {
+        assert_passes(&PowOp, &[1.5]);
+        assert_passes(&PowOp, &[-0.5]);
+    }
+
+    #[test]
+    fn test_gradcheck_sqrt() {
+        assert_passes(&SqrtOp, &[2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_exp() {
+        assert_passes(&ExpOp, &[1.5]);
+        assert_passes(&ExpOp, &[-0.5]);
+    }
+
+    #[test]
+    fn test_gradcheck_ln() {
+        assert_passes(&LnOp, &[1.5]);
+        assert_passes(&LnOp, &[0.25]);
+    }
+
+    #[test]
+    fn test_gradcheck_tanh() {
+        assert_passes(&TanhOp, &[0.5]);
+        assert_passes(&TanhOp, &[-2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_sigmoid() {
+        assert_passes(&SigmoidOp, &[0.5]);
+        assert_passes(&SigmoidOp, &[-3.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_relu() {
+        assert_passes(&ReluOp, &[1.5]);
+        assert_passes(&ReluOp, &[-1.5]);
+    }
+
+    #[test]
+    fn test_gradcheck_abs() {
+        assert_passes(&AbsOp, &[1.5]);
+        assert_passes(&AbsOp, &[-1.5]);
+    }
+
+    #[test]
+    fn test_gradcheck_max() {
+        assert_passes(&MaxOp, &[1.5, -2.0]);
+        assert_passes(&MaxOp, &[-1.5, 2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_min() {
+        assert_passes(&MinOp, &[1.5, -2.0]);
+        assert_passes(&MinOp, &[-1.5, 2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_clamp() {
+        assert_passes(&ClampOp, &[-1.5]);
+        assert_passes(&ClampOp, &[0.5]);
+        assert_passes(&ClampOp, &[1.5]);
+    }
+
+    #[test]
+    fn test_gradcheck_step() {
+        assert_passes(&StepOp, &[-1.5, 2.0]);
+        assert_passes(&StepOp, &[1.5, 2.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_chain() {
+        assert_passes(&Chain, &[2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_gradcheck_report() {
+        let report = gradcheck(&MulOp, &[2.0, 3.0], 1e-2, 1e-3);
+
+        assert_eq!(report.inputs.len(), 2);
+        assert_eq!(report.inputs[0].forward, 3.0);
+        assert_eq!(report.inputs[0].reverse, 3.0);
+        assert_eq!(report.inputs[1].forward, 2.0);
+        assert_eq!(report.inputs[1].reverse, 2.0);
+        assert!(report.inputs[0].forward_error.abs < 1e-3);
+    }
+
+    #[test]
+    fn test_gradcheck_flags_kink() {
+        // The one-sided AD derivative of relu at 0 disagrees with the
+        // symmetric difference quotient.
+        let report = gradcheck(&ReluOp, &[0.0], 1e-2, 1e-3);
+
+        assert!(!report.passed());
+        assert_eq!(report.inputs[0].numerical, 0.5);
+        assert_eq!(report.inputs[0].forward_error.abs, 0.5);
+    }
+}
@@ -0,0 +1,14 @@
+pub mod anomaly;
+#[cfg(feature = "ndarray")]
+pub mod array;
+pub mod backprop;
+pub mod forward;
+pub mod gradcheck;
+pub mod linalg;
+pub mod nn;
+pub mod optim;
+mod rng;
+pub mod scalar;
+pub mod softmax;
+pub mod sparse;
+pub mod subgradient;
@@ -0,0 +1,575 @@
+/*
+ * Dense linear algebra on plain `f32` matrices, stored row-major as flat
+ * slices. These are the primal kernels behind the differentiable
+ * decompositions of `backprop::tensor::linalg` and `forward::linalg`,
+ * which apply adjoint or tangent rules on top rather than differentiating
+ * through every scalar operation.
+ */
+
+// a[m x k] b[k x n].
+pub fn matmul(a: &[f32], b: &[f32], m: usize, k: usize, n: usize) -> Vec<f32> {
+    let mut c = vec![0.0; m * n];
+    for i in 0..m {
+        for p in 0..k {
+            for j in 0..n {
+                c[i * n + j] += a[i * k + p] * b[p * n + j];
+            }
+        }
+    }
+    c
+}
+
+pub fn transpose(a: &[f32], rows: usize, cols: usize) -> Vec<f32> {
+    let mut t = vec![0.0; rows * cols];
+    for i in 0..rows {
+        for j in 0..cols {
+            t[j * rows + i] = a[i * cols + j];
+        }
+    }
+    t
+}
+
+pub fn identity(n: usize) -> Vec<f32> {
+    let mut eye = vec![0.0; n * n];
+    for i in 0..n {
+        eye[i * n + i] = 1.0;
+    }
+    eye
+}
+
+/*
+ * LU factorization with partial pivoting, P A = L U. L has a unit
+ * diagonal and shares storage with U.
+ */
+#[derive(Debug, Clone)]
+pub struct Lu {
+    pub n: usize,
+    factors: Vec<f32>,
+    // Row i of P A is row pivots[i] of A.
+    pivots: Vec<usize>,
+    sign: f32,
+}
+
+impl Lu {
+    // None if `a` is singular.
+    pub fn new(a: &[f32], n: usize) -> Option<Lu> {
+        let lu = Lu::factor(a, n);
+        (!lu.is_singular()).then_some(lu)
+    }
+
+    /*
+     * The same for any square `a`: a column without a nonzero pivot is
+     * skipped, leaving a zero on the diagonal of U. Only `det` and
+     * `ln_abs_det` are meaningful for a singular `a`.
+     */
+    pub fn factor(a: &[f32], n: usize) -> Lu {
+        assert_eq!(a.len(), n * n, "LU of a non-square matrix");
+        let mut factors = a.to_vec();
+        let mut pivots: Vec<usize> = (0..n).collect();
+        let mut sign = 1.0;
+
+        for k in 0..n {
+            let p = (k..n)
+                .max_by(|&i, &j| {
+                    factors[i * n + k]
+                        .abs()
+                        .total_cmp(&factors[j * n + k].abs())
+                })
+                .unwrap();
+            if factors[p * n + k] == 0.0 {
+                continue;
+            }
+            if p != k {
+                for j in 0..n {
+                    factors.swap(p * n + j, k * n + j);
+                }
+                pivots.swap(p, k);
+                sign = -sign;
+            }
+            for i in k + 1..n {
+                let l = factors[i * n + k] / factors[k * n + k];
+                factors[i * n + k] = l;
+                for j in k + 1..n {
+                    factors[i * n + j] -= l * factors[k * n + j];
+                }
+            }
+        }
+        Lu {
+            n,
+            factors,
+            pivots,
+            sign,
+        }
+    }
+
+    pub fn is_singular(&self) -> bool {
+        let n = self.n;
+        (0..n).any(|i| self.factors[i * n + i] == 0.0)
+    }
+
+    // X with A X = B, for B of shape [n, m].
+    pub fn solve(&self, b: &[f32], m: usize) -> Vec<f32> {
+        let n = self.n;
+        let mut x = vec![0.0; n * m];
+        for (i, &p) in self.pivots.iter().enumerate() {
+            x[i * m..(i + 1) * m].copy_from_slice(&b[p * m..(p + 1) * m]);
+        }
+        // L y = P b, then U x = y.
+        for i in 0..n {
+            for k in 0..i {
+                let l = self.factors[i * n + k];
+                for j in 0..m {
+                    x[i * m + j] -= l * x[k * m + j];
+                }
+            }
+        }
+        for i in (0..n).rev() {
+            for k in i + 1..n {
+                let u = self.factors[i * n + k];
+                for j in 0..m {
+                    x[i * m + j] -= u * x[k * m + j];
+                }
+            }
+            let u = self.factors[i * n + i];
+            for j in 0..m {
+                x[i * m + j] /= u;
+            }
+        }
+        x
+    }
+
+    // X with A^T X = B, as needed by adjoints.
+    pub fn solve_transposed(&self, b: &[f32], m: usize) -> Vec<f32> {
+        let n = self.n;
+        let mut z = b.to_vec();
+        // A^T = U^T L^T P: U^T y = b, then L^T z = y, then x = P^T z.
+        for i in 0..n {
+            for k in 0..i {
+                let u = self.factors[k * n + i];
+                for j in 0..m {
+                    z[i * m + j] -= u * z[k * m + j];
+                }
+            }
+            let u = self.factors[i * n + i];
+            for j in 0..m {
+                z[i * m + j] /= u;
+            }
+        }
+        for i in (0..n).rev() {
+            for k in i + 1..n {
+                let l = self.factors[k * n + i];
+                for j in 0..m {
+                    z[i * m + j] -= l * z[k * m + j];
+                }
+            }
+        }
+        let mut x = vec![0.0; n * m];
+        for (i, &p) in self.pivots.iter().enumerate() {
+            x[p * m..(p + 1) * m].copy_from_slice(&z[i * m..(i + 1) * m]);
+        }
+        x
+    }
+
+    pub fn inverse(&self) -> Vec<f32> {
+        self.solve(&identity(self.n), self.n)
+    }
+
+    pub fn det(&self) -> f32 {
+        let n = self.n;
+        self.sign * (0..n).map(|i| self.factors[i * n + i]).product::<f32>()
+    }
+
+    // ln |det A|, without the overflow of the product; -inf if singular.
+    pub fn ln_abs_det(&self) -> f32 {
+        let n = self.n;
+        (0..n).map(|i| self.factors[i * n + i].abs().ln()).sum()
+    }
+}
+
+/*
+ * The adjugate adj A, with A adj A = det A I, which unlike A^-1 exists for
+ * singular A too and is the derivative of det A = sum_j A_ij adj(A)_ji.
+ * For invertible A it is det A A^-1. Otherwise, from the SVD A = U S V^T,
+ *   adj A = det U det V V adj S U^T,
+ * where adj S is diagonal with the products of all singular values but
+ * the i-th, so that it vanishes for a rank below n - 1.
+ */
+pub fn adjugate(a: &[f32], n: usize) -> Vec<f32> {
+    let lu = Lu::factor(a, n);
+    if !lu.is_singular() {
+        let det = lu.det();
+        return lu.inverse().iter().map(|x| det * x).collect();
+    }
+
+    let (u, s, mut v) = svd(a, n, n);
+    let sign = Lu::factor(&u, n).det().signum() * Lu::factor(&v, n).det().signum();
+    let cofactors: Vec<f32> = (0..n)
+        .map(|i| (0..n).filter(|&j| j != i).map(|j| s[j]).product())
+        .collect();
+    for row in v.chunks_exact_mut(n) {
+        for (x, c) in row.iter_mut().zip(&cofactors) {
+            *x *= sign * c;
+        }
+    }
+    matmul(&v, &transpose(&u, n, n), n, n, n)
+}
+
+/*
+ * Cholesky factor L of a symmetric positive definite matrix, A = L L^T,
+ * reading only the lower triangle of `a`. None if `a` is not positive
+ * definite.
+ */
+pub fn cholesky(a: &[f32], n: usize) -> Option<Vec<f32>> {
+    assert_eq!(a.len(), n * n, "Cholesky of a non-square matrix");
+    let mut l = vec![0.0f32; n * n];
+    for j in 0..n {
+        let d = a[j * n + j] - (0..j).map(|k| l[j * n + k].powi(2)).sum::<f32>();
+        if d <= 0.0 || d.is_nan() {
+            return None;
+        }
+        l[j * n + j] = d.sqrt();
+        for i in j + 1..n {
+            let s = a[i * n + j] - (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum::<f32>();
+            l[i * n + j] = s / l[j * n + j];
+        }
+    }
+    Some(l)
+}
+
+// X with L X = B, or L^T X = B if `transposed`, for lower triangular L.
+pub fn solve_lower(l: &[f32], b: &[f32], n: usize, m: usize, transposed: bool) -> Vec<f32> {
+    let mut x = b.to_vec();
+    let at = |i: usize, k: usize| {
+        if transposed {
+            l[k * n + i]
+        } else {
+            l[i * n + k]
+        }
+    };
+    let order: Vec<usize> = if transposed {
+        (0..n).rev().collect()
+    } else {
+        (0..n).collect()
+    };
+    for (pos, &i) in order.iter().enumerate() {
+        for &k in &order[..pos] {
+            let c = at(i, k);
+            for j in 0..m {
+                x[i * m + j] -= c * x[k * m + j];
+            }
+        }
+        for j in 0..m {
+            x[i * m + j] /= l[i * n + i];
+        }
+    }
+    x
+}
+
+// Sweeps of rotations after which a Jacobi method gives up converging.
+const MAX_SWEEPS: usize = 64;
+
+// tan of the Jacobi rotation angle for the 2x2 problem with coefficient `zeta`.
+fn rotation(zeta: f32) -> (f32, f32) {
+    let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+    let c = 1.0 / (1.0 + t * t).sqrt();
+    (c, c * t)
+}
+
+// Rotates columns p and q of a matrix with `ncols` columns.
+fn rotate_columns(a: &mut [f32], ncols: usize, p: usize, q: usize, (c, s): (f32, f32)) {
+    for row in a.chunks_exact_mut(ncols) {
+        let (x, y) = (row[p], row[q]);
+        row[p] = c * x - s * y;
+        row[q] = s * x + c * y;
+    }
+}
+
+/*
+ * Eigenvalues, in ascending order, and orthonormal eigenvectors (as the
+ * columns of an [n, n] matrix) of a symmetric matrix, by cyclic Jacobi
+ * rotations. Slower than tridiagonalization but simple and accurate to
+ * working precision.
+ */
+pub fn symmetric_eigen(a: &[f32], n: usize) -> (Vec<f32>, Vec<f32>) {
+    assert_eq!(a.len(), n * n, "eigendecomposition of a non-square matrix");
+    let mut a = a.to_vec();
+    let mut v = identity(n);
+
+    for _ in 0..MAX_SWEEPS {
+        let off: f32 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i * n + j].powi(2))
+            .sum();
+        let total: f32 = a.iter().map(|x| x * x).sum();
+        if off <= f32::EPSILON.powi(2) * total {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p * n + q] == 0.0 {
+                    continue;
+                }
+                // Zeroes a[p][q] with A <- J^T A J.
+                let zeta = (a[q * n + q] - a[p * n + p]) / (2.0 * a[p * n + q]);
+                let (c, s) = rotation(zeta);
+                rotate_columns(&mut a, n, p, q, (c, s));
+                for k in 0..n {
+                    let (x, y) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * x - s * y;
+                    a[q * n + k] = s * x + c * y;
+                }
+                rotate_columns(&mut v, n, p, q, (c, s));
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| a[i * n + i].total_cmp(&a[j * n + j]));
+    let values = order.iter().map(|&i| a[i * n + i]).collect();
+    let vectors = permute_columns(&v, n, &order);
+    (values, vectors)
+}
+
+// The columns `order` of a matrix with `ncols` columns.
+fn permute_columns(a: &[f32], ncols: usize, order: &[usize]) -> Vec<f32> {
+    a.chunks_exact(ncols)
+        .flat_map(|row| order.iter().map(move |&j| row[j]))
+        .collect()
+}
+
+/*
+ * Thin singular value decomposition A = U diag(S) V^T of an [m, n] matrix,
+ * by one-sided Jacobi rotations of its columns. With k = min(m, n), U is
+ * [m, k] and V is [n, k], both with orthonormal columns, and S holds the
+ * k singular values in descending order. Columns of U for zero singular
+ * values are completed to an orthonormal set.
+ */
+pub fn svd(a: &[f32], m: usize, n: usize) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+    assert_eq!(
+        a.len(),
+        m * n,
+        "SVD of {} elements as [{}, {}]",
+        a.len(),
+        m,
+        n
+    );
+    if m < n {
+        let (u, s, v) = svd(&transpose(a, m, n), n, m);
+        return (v, s, u);
+    }
+
+    let mut w = a.to_vec();
+    let mut v = identity(n);
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..n {
+            for q in p + 1..n {
+                let (mut alpha, mut beta, mut gamma) = (0.0, 0.0, 0.0);
+                for row in w.chunks_exact(n) {
+                    alpha += row[p] * row[p];
+                    beta += row[q] * row[q];
+                    gamma += row[p] * row[q];
+                }
+                if gamma.abs() <= f32::EPSILON * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+                let (c, s) = rotation((beta - alpha) / (2.0 * gamma));
+                rotate_columns(&mut w, n, p, q, (c, s));
+                rotate_columns(&mut v, n, p, q, (c, s));
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+
+    let norms: Vec<f32> = (0..n)
+        .map(|j| {
+            w.chunks_exact(n)
+                .map(|row| row[j] * row[j])
+                .sum::<f32>()
+                .sqrt()
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| norms[j].total_cmp(&norms[i]));
+
+    let s: Vec<f32> = order.iter().map(|&j| norms[j]).collect();
+    let mut u = permute_columns(&w, n, &order);
+    let v = permute_columns(&v, n, &order);
+    let tiny = f32::EPSILON * s.first().copied().unwrap_or(0.0) * m as f32;
+    for j in 0..n {
+        if s[j] > tiny {
+            for row in u.chunks_exact_mut(n) {
+                row[j] /= s[j];
+            }
+        } else {
+            complete_column(&mut u, m, n, j);
+        }
+    }
+    (u, s, v)
+}
+
+// Replaces column j of an [m, n] matrix by a unit vector orthogonal to the
+// columns before it, by Gram-Schmidt on the standard basis.
+fn complete_column(u: &mut [f32], m: usize, n: usize, j: usize) {
+    for e in 0..m {
+        let mut x: Vec<f32> = (0..m).map(|i| if i == e { 1.0 } else { 0.0 }).collect();
+        for k in 0..j {
+            let dot: f32 = (0..m).map(|i| u[i * n + k] * x[i]).sum();
+            for (i, xi) in x.iter_mut().enumerate() {
+                *xi -= dot * u[i * n + k];
+            }
+        }
+        let norm = x.iter().map(|xi| xi * xi).sum::<f32>().sqrt();
+        if norm > 0.5 {
+            for (i, xi) in x.iter().enumerate() {
+                u[i * n + j] = xi / norm;
+            }
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    pub(crate) use crate::rng::uniform as random;
+
+    // X X^T + n I, comfortably positive definite.
+    pub(crate) fn random_spd(n: usize, seed: u64) -> Vec<f32> {
+        let x = random(n * n, seed);
+        let mut a = super::matmul(&x, &super::transpose(&x, n, n), n, n, n);
+        for i in 0..n {
+            a[i * n + i] += n as f32;
+        }
+        a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_lu_solve() {
+        let a = [2.0, 1.0, 1.0, 4.0, 3.0, 3.0, 8.0, 7.0, 9.0];
+        let lu = Lu::new(&a, 3).unwrap();
+        let x = lu.solve(&[1.0, 2.0, 3.0], 1);
+
+        assert_close(&matmul(&a, &x, 3, 3, 1), &[1.0, 2.0, 3.0]);
+        assert!((lu.det() - 4.0).abs() < 1e-5);
+        assert!((lu.ln_abs_det() - 4f32.ln()).abs() < 1e-5);
+
+        let y = lu.solve_transposed(&[1.0, 2.0, 3.0], 1);
+        assert_close(&matmul(&transpose(&a, 3, 3), &y, 3, 3, 1), &[1.0, 2.0, 3.0]);
+        assert_close(&matmul(&a, &lu.inverse(), 3, 3, 3), &identity(3));
+    }
+
+    #[test]
+    fn test_lu_pivots() {
+        // A zero leading entry needs a row swap, which flips the sign.
+        let a = [0.0, 1.0, 1.0, 0.0];
+        let lu = Lu::new(&a, 2).unwrap();
+
+        assert_eq!(lu.det(), -1.0);
+        assert_close(&lu.solve(&[2.0, 3.0], 1), &[3.0, 2.0]);
+        assert!(Lu::new(&[1.0, 2.0, 2.0, 4.0], 2).is_none());
+    }
+
+    #[test]
+    fn test_singular_det() {
+        let lu = Lu::factor(&[1.0, 2.0, 2.0, 4.0], 2);
+
+        assert!(lu.is_singular());
+        assert_eq!(lu.det(), 0.0);
+        assert_eq!(lu.ln_abs_det(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_adjugate() {
+        let a = [2.0, 1.0, 1.0, 4.0, 3.0, 3.0, 8.0, 7.0, 9.0];
+        let mut det_eye = identity(3);
+        det_eye.iter_mut().for_each(|x| *x *= 4.0);
+        assert_close(&matmul(&a, &adjugate(&a, 3), 3, 3, 3), &det_eye);
+
+        // Rank 2, where the adjugate has rank 1.
+        let a = [1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 1.0, 0.0, 1.0];
+        assert_close(
+            &adjugate(&a, 3),
+            &[4.0, -2.0, 0.0, 4.0, -2.0, 0.0, -4.0, 2.0, 0.0],
+        );
+
+        // Rank 1, where it vanishes.
+        let a = [1.0, 2.0, 4.0, 2.0, 4.0, 8.0, 4.0, 8.0, 16.0];
+        assert_close(&adjugate(&a, 3), &[0.0; 9]);
+    }
+
+    #[test]
+    fn test_cholesky() {
+        let n = 4;
+        let a = random_spd(n, 1);
+        let l = cholesky(&a, n).unwrap();
+
+        assert_close(&matmul(&l, &transpose(&l, n, n), n, n, n), &a);
+        assert!(cholesky(&[1.0, 2.0, 2.0, 1.0], 2).is_none());
+
+        let b = random(n * 2, 2);
+        let x = solve_lower(&l, &b, n, 2, false);
+        assert_close(&matmul(&l, &x, n, n, 2), &b);
+        let x = solve_lower(&l, &b, n, 2, true);
+        assert_close(&matmul(&transpose(&l, n, n), &x, n, n, 2), &b);
+    }
+
+    #[test]
+    fn test_symmetric_eigen() {
+        let n = 5;
+        let a = random_spd(n, 3);
+        let (values, vectors) = symmetric_eigen(&a, n);
+
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+        let vt = transpose(&vectors, n, n);
+        assert_close(&matmul(&vt, &vectors, n, n, n), &identity(n));
+        let mut lambda = vec![0.0; n * n];
+        for i in 0..n {
+            lambda[i * n + i] = values[i];
+        }
+        let reconstructed = matmul(&matmul(&vectors, &lambda, n, n, n), &vt, n, n, n);
+        assert_close(&reconstructed, &a);
+    }
+
+    fn assert_svd(a: &[f32], m: usize, n: usize) {
+        let k = m.min(n);
+        let (u, s, v) = svd(a, m, n);
+
+        assert_eq!((u.len(), s.len(), v.len()), (m * k, k, n * k));
+        assert!(s.windows(2).all(|w| w[0] >= w[1]));
+        assert_close(&matmul(&transpose(&u, m, k), &u, k, m, k), &identity(k));
+        assert_close(&matmul(&transpose(&v, n, k), &v, k, n, k), &identity(k));
+        let mut us = u.clone();
+        for row in us.chunks_exact_mut(k) {
+            for (x, sigma) in row.iter_mut().zip(&s) {
+                *x *= sigma;
+            }
+        }
+        assert_close(&matmul(&us, &transpose(&v, n, k), m, k, n), a);
+    }
+
+    #[test]
+    fn test_svd() {
+        assert_svd(&random(12, 4), 4, 3);
+        assert_svd(&random(12, 5), 3, 4);
+        assert_svd(&random(16, 6), 4, 4);
+
+        // Rank one: the second column of U is completed.
+        assert_svd(&[1.0, 2.0, 2.0, 4.0, 3.0, 6.0], 3, 2);
+    }
+}
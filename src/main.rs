@@ -1,7 +1,4 @@
-mod backprop;
-mod forward;
-
-use forward::value as fwd;
+use rad::forward::value as fwd;
 
 fn f(x: fwd::Value) -> fwd::Value {
     let a = fwd::Value::passive(3.0); // Passive variable
@@ -28,7 +25,7 @@ fn univariate_example() {
 }
 
 fn g(x1: fwd::Value, x2: fwd::Value) -> fwd::Value {
-    return x1 * x2;
+    x1 * x2
 }
 
 fn multivariate_example() {
@@ -45,8 +42,8 @@ fn multivariate_example() {
      *
      * Note that this could be extended to R^n.
      */
-    let mut x = vec![fwd::Value::new(2.0, 0.0), fwd::Value::new(3.0, 0.0)]; // vector of arguments i.e. x = (x1, x2)
-    let mut grad_g = vec![0.0; 2];
+    let mut x = [fwd::Value::new(2.0, 0.0), fwd::Value::new(3.0, 0.0)]; // vector of arguments i.e. x = (x1, x2)
+    let mut grad_g = [0.0; 2];
     for i in 0..2 {
         x[i].der = 1.0; // seed the desired partial
 
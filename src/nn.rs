@@ -0,0 +1,282 @@
+use crate::backprop::variable::Variable;
+use crate::rng::uniform;
+
+/*
+ * A micrograd-style neural network library on reverse-mode variables.
+ *
+ * Parameters are plain `Variable`s owned by the modules. The tape finds
+ * adjoints by name, so every parameter is named after the path to it
+ * ("mlp.1.0.w2" is weight 2 of neuron 0 of layer 1 of "mlp") and keeps
+ * that name when an optimizer updates its value in place; give every
+ * model a distinct name. Weights start uniform in [-1, 1) from `seed` and
+ * biases at 0.
+ *
+ * Every forward pass records onto the thread's tape, which nothing empties
+ * on its own. A training loop should run each step inside `tape::scoped`,
+ * which drops what the step recorded, or clear the tape after the step;
+ * otherwise the tape grows with every step for as long as the thread runs.
+ */
+
+pub trait Module {
+    fn forward(&self, x: &[Variable]) -> Vec<Variable>;
+
+    fn parameters(&self) -> Vec<Variable>;
+
+    // The same parameters, for updating their values in place.
+    fn parameters_mut(&mut self) -> Vec<&mut Variable>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Linear,
+    Relu,
+    Tanh,
+    Sigmoid,
+}
+
+impl Activation {
+    pub fn apply(self, x: Variable) -> Variable {
+        match self {
+            Activation::Linear => x,
+            Activation::Relu => x.relu(),
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => x.sigmoid(),
+        }
+    }
+}
+
+// Seeds for the parts of a module, so that they start out different.
+fn child_seed(seed: u64, i: usize) -> u64 {
+    let mut z = seed ^ (i as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z ^ (z >> 31)
+}
+
+#[derive(Debug, Clone)]
+pub struct Neuron {
+    pub weights: Vec<Variable>,
+    pub bias: Variable,
+    pub activation: Activation,
+}
+
+impl Neuron {
+    pub fn new(name: &str, nin: usize, activation: Activation, seed: u64) -> Self {
+        let weights = uniform(nin, seed)
+            .into_iter()
+            .enumerate()
+            .map(|(i, w)| Variable::new(w, Some(format!("{}.w{}", name, i))))
+            .collect();
+        let bias = Variable::new(0.0, Some(format!("{}.b", name)));
+
+        Neuron {
+            weights,
+            bias,
+            activation,
+        }
+    }
+
+    // activation(w . x + b).
+    pub fn activate(&self, x: &[Variable]) -> Variable {
+        assert_eq!(
+            x.len(),
+            self.weights.len(),
+            "{} inputs to a neuron of {}",
+            x.len(),
+            self.weights.len()
+        );
+        let z = self
+            .weights
+            .iter()
+            .zip(x)
+            .fold(self.bias.clone(), |acc, (w, x)| acc + w.clone() * x.clone());
+        self.activation.apply(z)
+    }
+}
+
+impl Module for Neuron {
+    fn forward(&self, x: &[Variable]) -> Vec<Variable> {
+        vec![self.activate(x)]
+    }
+
+    fn parameters(&self) -> Vec<Variable> {
+        let mut parameters = self.weights.clone();
+        parameters.push(self.bias.clone());
+        parameters
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Variable> {
+        let mut parameters: Vec<&mut Variable> = self.weights.iter_mut().collect();
+        parameters.push(&mut self.bias);
+        parameters
+    }
+}
+
+// A fully connected layer of `nout` neurons.
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub neurons: Vec<Neuron>,
+}
+
+impl Layer {
+    pub fn new(name: &str, nin: usize, nout: usize, activation: Activation, seed: u64) -> Self {
+        let neurons = (0..nout)
+            .map(|j| {
+                let name = format!("{}.{}", name, j);
+                Neuron::new(&name, nin, activation, child_seed(seed, j))
+            })
+            .collect();
+        Layer { neurons }
+    }
+
+    // A layer without an activation.
+    pub fn linear(name: &str, nin: usize, nout: usize, seed: u64) -> Self {
+        Layer::new(name, nin, nout, Activation::Linear, seed)
+    }
+}
+
+impl Module for Layer {
+    fn forward(&self, x: &[Variable]) -> Vec<Variable> {
+        self.neurons.iter().map(|n| n.activate(x)).collect()
+    }
+
+    fn parameters(&self) -> Vec<Variable> {
+        self.neurons.iter().flat_map(Neuron::parameters).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Variable> {
+        self.neurons
+            .iter_mut()
+            .flat_map(Neuron::parameters_mut)
+            .collect()
+    }
+}
+
+/*
+ * A multilayer perceptron with layers of `nouts` neurons. The hidden
+ * layers use `activation` and the last one is linear, as a regression or
+ * logit head.
+ */
+#[derive(Debug, Clone)]
+pub struct MLP {
+    pub layers: Vec<Layer>,
+}
+
+impl MLP {
+    pub fn new(name: &str, nin: usize, nouts: &[usize], activation: Activation, seed: u64) -> Self {
+        let sizes: Vec<usize> = std::iter::once(nin).chain(nouts.iter().copied()).collect();
+        let layers = (0..nouts.len())
+            .map(|l| {
+                let name = format!("{}.{}", name, l);
+                let activation = if l + 1 == nouts.len() {
+                    Activation::Linear
+                } else {
+                    activation
+                };
+                Layer::new(
+                    &name,
+                    sizes[l],
+                    sizes[l + 1],
+                    activation,
+                    child_seed(seed, l),
+                )
+            })
+            .collect();
+        MLP { layers }
+    }
+}
+
+impl Module for MLP {
+    fn forward(&self, x: &[Variable]) -> Vec<Variable> {
+        self.layers
+            .iter()
+            .fold(x.to_vec(), |x, layer| layer.forward(&x))
+    }
+
+    fn parameters(&self) -> Vec<Variable> {
+        self.layers.iter().flat_map(Layer::parameters).collect()
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut Variable> {
+        self.layers
+            .iter_mut()
+            .flat_map(Layer::parameters_mut)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backprop::grad::grad;
+    use crate::backprop::tape::scoped;
+
+    fn inputs(x: &[f32]) -> Vec<Variable> {
+        x.iter().map(|&x| Variable::new(x, None)).collect()
+    }
+
+    #[test]
+    fn test_neuron() {
+        let mut n = Neuron::new("n", 2, Activation::Tanh, 0);
+        n.weights[0].value = 1.0;
+        n.weights[1].value = -2.0;
+        n.bias.value = 0.5;
+
+        let y = n.activate(&inputs(&[3.0, 1.0]));
+        assert_eq!(y.value, 1.5f32.tanh());
+        assert_eq!(n.parameters().len(), 3);
+        assert_eq!(n.parameters()[2].name, "n.b");
+    }
+
+    #[test]
+    fn test_mlp_shapes() {
+        let mlp = MLP::new("mlp", 3, &[4, 4, 1], Activation::Relu, 1);
+
+        assert_eq!(mlp.parameters().len(), 4 * 4 + 4 * 5 + 5);
+        assert_eq!(mlp.forward(&inputs(&[1.0, -1.0, 0.5])).len(), 1);
+        assert_eq!(mlp.layers[2].neurons[0].activation, Activation::Linear);
+        assert_eq!(mlp.layers[1].neurons[0].weights[2].name, "mlp.1.0.w2");
+
+        // Parameters are distinct, and so are their starting values.
+        let mut names: Vec<String> = mlp.parameters().into_iter().map(|p| p.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), mlp.parameters().len());
+        let (a, b) = (&mlp.layers[0].neurons[0], &mlp.layers[0].neurons[1]);
+        assert_ne!(a.weights[0].value, b.weights[0].value);
+    }
+
+    #[test]
+    fn test_training() {
+        // The micrograd demo: fit four points with a small network.
+        let xs = [
+            [2.0, 3.0, -1.0],
+            [3.0, -1.0, 0.5],
+            [0.5, 1.0, 1.0],
+            [1.0, 1.0, -1.0],
+        ];
+        let ys = [1.0, -1.0, -1.0, 1.0];
+        let mut mlp = MLP::new("mlp", 3, &[4, 4, 1], Activation::Tanh, 7);
+
+        let mut losses = Vec::new();
+        for _ in 0..30 {
+            // One scoped tape per step, so the thread's tape does not grow.
+            let ((loss, gradients), _) = scoped(|| {
+                let loss = xs
+                    .iter()
+                    .zip(ys)
+                    .fold(Variable::new(0.0, None), |acc, (x, y)| {
+                        let prediction = mlp.forward(&inputs(x)).remove(0);
+                        acc + (prediction - Variable::new(y, None)).pow(2.0)
+                    });
+                let gradients = grad(&loss, &mlp.parameters());
+                (loss.value, gradients)
+            });
+            for (p, g) in mlp.parameters_mut().into_iter().zip(gradients) {
+                p.value -= 0.05 * g.map_or(0.0, |g| g.value);
+            }
+            losses.push(loss);
+        }
+
+        assert!(losses[29] < 0.1 * losses[0]);
+    }
+}
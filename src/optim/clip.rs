@@ -0,0 +1,181 @@
+use super::Optimizer;
+use crate::backprop::variable::Variable;
+
+/*
+ * Gradient clipping, either of every gradient to [-limit, limit] or of
+ * all of them together to a global (Euclidean) norm of at most `max`,
+ * which keeps their direction. It applies to the gradients `grad`
+ * returns, or through `Clipped` to those an optimizer collects before it
+ * steps.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clip {
+    Value(f32),
+    Norm(f32),
+}
+
+impl Clip {
+    // Clips `gradients` in place, returning their global norm before.
+    pub fn apply(self, gradients: &mut [f32]) -> f32 {
+        self.check();
+        let norm = gradients.iter().map(|g| g * g).sum::<f32>().sqrt();
+        match self {
+            Clip::Value(limit) => {
+                for g in gradients.iter_mut() {
+                    *g = g.clamp(-limit, limit);
+                }
+            }
+            Clip::Norm(max) => {
+                if norm > max {
+                    let scale = max / norm;
+                    gradients.iter_mut().for_each(|g| *g *= scale);
+                }
+            }
+        }
+        norm
+    }
+
+    // Panics unless the bound is a non-negative number; NaN fails too.
+    fn check(self) {
+        match self {
+            Clip::Value(limit) => assert!(
+                limit >= 0.0,
+                "Clip::Value needs a non-negative limit, got {}",
+                limit
+            ),
+            Clip::Norm(max) => assert!(
+                max >= 0.0,
+                "Clip::Norm needs a non-negative max, got {}",
+                max
+            ),
+        }
+    }
+}
+
+// The same for the result of `grad`, where missing gradients count as 0.
+pub fn clip_gradients(gradients: &mut [Option<Variable>], clip: Clip) -> f32 {
+    let mut values: Vec<f32> = gradients
+        .iter()
+        .map(|g| g.as_ref().map_or(0.0, |g| g.value))
+        .collect();
+    let norm = clip.apply(&mut values);
+    for (g, value) in gradients.iter_mut().zip(values) {
+        if let Some(g) = g {
+            g.value = value;
+        }
+    }
+    norm
+}
+
+// An optimizer that clips the gradients it collected before every step.
+#[derive(Debug, Clone)]
+pub struct Clipped<O: Optimizer> {
+    pub optimizer: O,
+    pub clip: Clip,
+}
+
+impl<O: Optimizer> Clipped<O> {
+    pub fn new(optimizer: O, clip: Clip) -> Self {
+        clip.check();
+        Clipped { optimizer, clip }
+    }
+}
+
+impl<O: Optimizer> Optimizer for Clipped<O> {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        self.optimizer.gradients_mut()
+    }
+
+    fn step(&mut self, parameters: Vec<&mut Variable>) {
+        self.clip.apply(self.optimizer.gradients_mut());
+        self.optimizer.step(parameters);
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.optimizer.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.optimizer.set_learning_rate(lr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::schedule::{Scheduled, StepDecay};
+    use super::super::Sgd;
+    use super::*;
+
+    #[test]
+    fn test_clip_value() {
+        let mut g = [3.0, -0.5, -4.0];
+        let norm = Clip::Value(1.0).apply(&mut g);
+
+        assert!((norm - 25.25f32.sqrt()).abs() < 1e-6);
+        assert_eq!(g, [1.0, -0.5, -1.0]);
+    }
+
+    #[test]
+    fn test_clip_norm() {
+        let mut g = [3.0, 0.0, -4.0];
+        assert_eq!(Clip::Norm(1.0).apply(&mut g), 5.0);
+        assert_eq!(g, [0.6, 0.0, -0.8]);
+
+        // Within the limit nothing changes.
+        assert_eq!(Clip::Norm(2.0).apply(&mut g), 1.0);
+        assert_eq!(g, [0.6, 0.0, -0.8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative limit")]
+    fn test_clip_value_negative_limit() {
+        Clip::Value(-1.0).apply(&mut [1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative limit")]
+    fn test_clip_value_nan_limit() {
+        Clip::Value(f32::NAN).apply(&mut [1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative max")]
+    fn test_clipped_negative_norm() {
+        Clipped::new(Sgd::new(1.0), Clip::Norm(-1.0));
+    }
+
+    #[test]
+    fn test_clip_gradients() {
+        let mut gradients = [
+            Some(Variable::new(6.0, None)),
+            None,
+            Some(Variable::new(8.0, None)),
+        ];
+        let norm = clip_gradients(&mut gradients, Clip::Norm(5.0));
+
+        assert_eq!(norm, 10.0);
+        assert!(gradients[1].is_none());
+        assert_eq!(gradients[0].as_ref().unwrap().value, 3.0);
+        assert_eq!(gradients[2].as_ref().unwrap().value, 4.0);
+    }
+
+    #[test]
+    fn test_clipped_and_scheduled() {
+        let mut x = [Variable::new(0.0, None), Variable::new(0.0, None)];
+        let clipped = Clipped::new(Sgd::new(1.0), Clip::Norm(1.0));
+        let mut sgd = Scheduled::new(clipped, StepDecay::new(1, 0.5));
+        for _ in 0..2 {
+            sgd.zero_grad();
+            sgd.accumulate(&[
+                Some(Variable::new(30.0, None)),
+                Some(Variable::new(40.0, None)),
+            ]);
+            sgd.step(x.iter_mut().collect());
+        }
+
+        // Unit steps along (0.6, 0.8), at rates 1 and 0.5.
+        assert!((x[0].value + 0.9).abs() < 1e-6);
+        assert!((x[1].value + 1.2).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,493 @@
+pub mod clip;
+pub mod schedule;
+
+use crate::backprop::grad::grad;
+use crate::backprop::variable::Variable;
+
+/*
+ * Optimizers that apply the gradients `grad` returns to the leaf
+ * parameters of a model, such as `nn::Module::parameters_mut`.
+ *
+ * Variables do not carry gradients, so an optimizer collects them: each
+ * `backward` (or `accumulate`) adds one set, in parameter order, until
+ * `zero_grad` clears them. `step` then updates the parameters in place,
+ * keeping their names. Per-parameter state such as momentum is kept by
+ * position, so pass the parameters in the same order every time.
+ *
+ * Weight decay is L2 regularization, added to the gradient, except in
+ * `AdamW` where it shrinks the parameters directly. Learning-rate
+ * schedules and gradient clipping wrap an optimizer, in `schedule` and
+ * `clip`.
+ */
+
+pub trait Optimizer {
+    // The gradients collected since the last `zero_grad`.
+    fn gradients_mut(&mut self) -> &mut Vec<f32>;
+
+    // Updates `parameters` from the collected gradients.
+    fn step(&mut self, parameters: Vec<&mut Variable>);
+
+    fn learning_rate(&self) -> f32;
+
+    fn set_learning_rate(&mut self, lr: f32);
+
+    // Adds a set of gradients; those `grad` could not find count as 0.
+    fn accumulate(&mut self, gradients: &[Option<Variable>]) {
+        let collected = self.gradients_mut();
+        collected.resize(gradients.len().max(collected.len()), 0.0);
+        for (c, g) in collected.iter_mut().zip(gradients) {
+            *c += g.as_ref().map_or(0.0, |g| g.value);
+        }
+    }
+
+    // Accumulates the gradients of `loss` with respect to `parameters`.
+    fn backward(&mut self, loss: &Variable, parameters: &[Variable]) {
+        self.accumulate(&grad(loss, parameters));
+    }
+
+    fn zero_grad(&mut self) {
+        self.gradients_mut().iter_mut().for_each(|g| *g = 0.0);
+    }
+}
+
+// A buffer of per-parameter state, zeros until first used.
+fn state(buffer: &mut Vec<f32>, n: usize) -> &mut [f32] {
+    buffer.resize(n, 0.0);
+    buffer
+}
+
+fn check(parameters: &[&mut Variable], gradients: &mut Vec<f32>) {
+    assert!(
+        gradients.len() <= parameters.len(),
+        "{} gradients for {} parameters",
+        gradients.len(),
+        parameters.len()
+    );
+    gradients.resize(parameters.len(), 0.0);
+}
+
+/*
+ * Stochastic gradient descent, optionally with (Nesterov) momentum:
+ *   v = momentum v + g,  p -= lr (g + momentum v) or lr v.
+ */
+#[derive(Debug, Clone)]
+pub struct Sgd {
+    pub lr: f32,
+    pub momentum: f32,
+    pub nesterov: bool,
+    pub weight_decay: f32,
+    velocity: Vec<f32>,
+    gradients: Vec<f32>,
+}
+
+impl Sgd {
+    pub fn new(lr: f32) -> Self {
+        Sgd {
+            lr,
+            momentum: 0.0,
+            nesterov: false,
+            weight_decay: 0.0,
+            velocity: Vec::new(),
+            gradients: Vec::new(),
+        }
+    }
+
+    pub fn momentum(mut self, momentum: f32) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn nesterov(mut self, nesterov: bool) -> Self {
+        self.nesterov = nesterov;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f32) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for Sgd {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        &mut self.gradients
+    }
+
+    fn step(&mut self, mut parameters: Vec<&mut Variable>) {
+        check(&parameters, &mut self.gradients);
+        let velocity = state(&mut self.velocity, parameters.len());
+        for ((p, &g), v) in parameters.iter_mut().zip(&self.gradients).zip(velocity) {
+            let g = g + self.weight_decay * p.value;
+            *v = self.momentum * *v + g;
+            let update = if self.nesterov {
+                g + self.momentum * *v
+            } else {
+                *v
+            };
+            p.value -= self.lr * update;
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.lr = lr;
+    }
+}
+
+// Per-parameter steps shrinking with the sum of all squared gradients so far.
+#[derive(Debug, Clone)]
+pub struct Adagrad {
+    pub lr: f32,
+    pub eps: f32,
+    pub weight_decay: f32,
+    sum_squares: Vec<f32>,
+    gradients: Vec<f32>,
+}
+
+impl Adagrad {
+    pub fn new(lr: f32) -> Self {
+        Adagrad {
+            lr,
+            eps: 1e-10,
+            weight_decay: 0.0,
+            sum_squares: Vec::new(),
+            gradients: Vec::new(),
+        }
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f32) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for Adagrad {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        &mut self.gradients
+    }
+
+    fn step(&mut self, mut parameters: Vec<&mut Variable>) {
+        check(&parameters, &mut self.gradients);
+        let sum_squares = state(&mut self.sum_squares, parameters.len());
+        for ((p, &g), s) in parameters.iter_mut().zip(&self.gradients).zip(sum_squares) {
+            let g = g + self.weight_decay * p.value;
+            *s += g * g;
+            p.value -= self.lr * g / (s.sqrt() + self.eps);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.lr = lr;
+    }
+}
+
+// Per-parameter steps shrinking with a running average of squared gradients.
+#[derive(Debug, Clone)]
+pub struct RmsProp {
+    pub lr: f32,
+    // Decay of the running average.
+    pub alpha: f32,
+    pub eps: f32,
+    pub weight_decay: f32,
+    mean_squares: Vec<f32>,
+    gradients: Vec<f32>,
+}
+
+impl RmsProp {
+    pub fn new(lr: f32) -> Self {
+        RmsProp {
+            lr,
+            alpha: 0.99,
+            eps: 1e-8,
+            weight_decay: 0.0,
+            mean_squares: Vec::new(),
+            gradients: Vec::new(),
+        }
+    }
+
+    pub fn alpha(mut self, alpha: f32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f32) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for RmsProp {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        &mut self.gradients
+    }
+
+    fn step(&mut self, mut parameters: Vec<&mut Variable>) {
+        check(&parameters, &mut self.gradients);
+        let mean_squares = state(&mut self.mean_squares, parameters.len());
+        for ((p, &g), s) in parameters.iter_mut().zip(&self.gradients).zip(mean_squares) {
+            let g = g + self.weight_decay * p.value;
+            *s = self.alpha * *s + (1.0 - self.alpha) * g * g;
+            p.value -= self.lr * g / (s.sqrt() + self.eps);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.lr = lr;
+    }
+}
+
+/*
+ * Adam (Kingma and Ba, 2015): running averages m and v of the gradients
+ * and their squares, corrected for their zero start,
+ *   p -= lr m / (1 - beta1^t) / (sqrt(v / (1 - beta2^t)) + eps).
+ */
+#[derive(Debug, Clone)]
+pub struct Adam {
+    pub lr: f32,
+    pub betas: (f32, f32),
+    pub eps: f32,
+    pub weight_decay: f32,
+    steps: i32,
+    means: Vec<f32>,
+    mean_squares: Vec<f32>,
+    gradients: Vec<f32>,
+}
+
+impl Adam {
+    pub fn new(lr: f32) -> Self {
+        Adam {
+            lr,
+            betas: (0.9, 0.999),
+            eps: 1e-8,
+            weight_decay: 0.0,
+            steps: 0,
+            means: Vec::new(),
+            mean_squares: Vec::new(),
+            gradients: Vec::new(),
+        }
+    }
+
+    pub fn betas(mut self, beta1: f32, beta2: f32) -> Self {
+        self.betas = (beta1, beta2);
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f32) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        &mut self.gradients
+    }
+
+    fn step(&mut self, mut parameters: Vec<&mut Variable>) {
+        check(&parameters, &mut self.gradients);
+        let n = parameters.len();
+        let (beta1, beta2) = self.betas;
+        self.steps += 1;
+        let correction1 = 1.0 - beta1.powi(self.steps);
+        let correction2 = 1.0 - beta2.powi(self.steps);
+
+        let means = state(&mut self.means, n);
+        let mean_squares = state(&mut self.mean_squares, n);
+        for (((p, &g), m), v) in parameters
+            .iter_mut()
+            .zip(&self.gradients)
+            .zip(means)
+            .zip(mean_squares)
+        {
+            let g = g + self.weight_decay * p.value;
+            *m = beta1 * *m + (1.0 - beta1) * g;
+            *v = beta2 * *v + (1.0 - beta2) * g * g;
+            p.value -= self.lr * (*m / correction1) / ((*v / correction2).sqrt() + self.eps);
+        }
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.lr = lr;
+    }
+}
+
+/*
+ * Adam with decoupled weight decay (Loshchilov and Hutter, 2019): the
+ * parameters shrink by lr * weight_decay before the Adam update, instead
+ * of the decay going through the adaptive scaling.
+ */
+#[derive(Debug, Clone)]
+pub struct AdamW {
+    // Private, so that its coupled weight decay stays 0 and only the
+    // decoupled one below applies; lr and betas are set through AdamW.
+    adam: Adam,
+    pub weight_decay: f32,
+}
+
+impl AdamW {
+    pub fn new(lr: f32) -> Self {
+        AdamW {
+            adam: Adam::new(lr),
+            weight_decay: 0.01,
+        }
+    }
+
+    pub fn betas(mut self, beta1: f32, beta2: f32) -> Self {
+        self.adam.betas = (beta1, beta2);
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f32) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+impl Optimizer for AdamW {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        self.adam.gradients_mut()
+    }
+
+    fn step(&mut self, mut parameters: Vec<&mut Variable>) {
+        for p in parameters.iter_mut() {
+            p.value -= self.adam.lr * self.weight_decay * p.value;
+        }
+        self.adam.step(parameters);
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.adam.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.adam.lr = lr;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backprop::tape::scoped;
+
+    // sum_i a_i (x_i - c_i)^2, badly conditioned on purpose.
+    const A: [f32; 3] = [1.0, 10.0, 0.5];
+    const C: [f32; 3] = [1.0, -2.0, 3.0];
+
+    fn quadratic(x: &[Variable]) -> Variable {
+        x.iter()
+            .zip(A.iter().zip(C))
+            .fold(Variable::new(0.0, None), |acc, (x, (&a, c))| {
+                let d = x.clone() - Variable::new(c, None);
+                acc + Variable::new(a, None) * d.clone() * d
+            })
+    }
+
+    fn parameters() -> Vec<Variable> {
+        (0..3)
+            .map(|i| Variable::new(0.0, Some(format!("x{}", i))))
+            .collect()
+    }
+
+    fn minimize(optimizer: &mut impl Optimizer, steps: usize) -> Vec<f32> {
+        let mut x = parameters();
+        for _ in 0..steps {
+            optimizer.zero_grad();
+            scoped(|| optimizer.backward(&quadratic(&x), &x));
+            optimizer.step(x.iter_mut().collect());
+        }
+        x.iter().map(|x| x.value).collect()
+    }
+
+    fn assert_converged(x: &[f32], tolerance: f32) {
+        for (x, c) in x.iter().zip(C) {
+            assert!((x - c).abs() < tolerance, "{:?} is not {:?}", x, C);
+        }
+    }
+
+    #[test]
+    fn test_convergence() {
+        assert_converged(&minimize(&mut Sgd::new(0.04), 300), 1e-3);
+        assert_converged(&minimize(&mut Sgd::new(0.02).momentum(0.9), 300), 1e-3);
+        let mut nesterov = Sgd::new(0.02).momentum(0.9).nesterov(true);
+        assert_converged(&minimize(&mut nesterov, 300), 1e-3);
+        assert_converged(&minimize(&mut Adagrad::new(1.0), 500), 1e-3);
+        assert_converged(&minimize(&mut RmsProp::new(0.01), 1000), 1e-2);
+        assert_converged(&minimize(&mut Adam::new(0.1), 1000), 1e-3);
+        assert_converged(
+            &minimize(&mut AdamW::new(0.1).weight_decay(0.0), 1000),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn test_first_steps() {
+        let mut x = [Variable::new(1.0, None)];
+        let gradient = [Some(Variable::new(2.0, None))];
+
+        // v = 2, then v = 0.5 * 2 + 2 = 3.
+        let mut sgd = Sgd::new(0.1).momentum(0.5);
+        for expected in [0.8, 0.5] {
+            sgd.zero_grad();
+            sgd.accumulate(&gradient);
+            sgd.step(x.iter_mut().collect());
+            assert!((x[0].value - expected).abs() < 1e-6);
+        }
+
+        // The first Adam step moves every parameter by about lr.
+        let mut adam = Adam::new(0.1);
+        adam.accumulate(&gradient);
+        adam.step(x.iter_mut().collect());
+        assert!((x[0].value - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_accumulate_and_zero_grad() {
+        let mut sgd = Sgd::new(1.0);
+        let mut x = [Variable::new(0.0, None), Variable::new(0.0, None)];
+        sgd.accumulate(&[Some(Variable::new(1.0, None)), None]);
+        sgd.accumulate(&[
+            Some(Variable::new(2.0, None)),
+            Some(Variable::new(4.0, None)),
+        ]);
+        sgd.step(x.iter_mut().collect());
+
+        assert_eq!([x[0].value, x[1].value], [-3.0, -4.0]);
+        sgd.zero_grad();
+        sgd.step(x.iter_mut().collect());
+        assert_eq!([x[0].value, x[1].value], [-3.0, -4.0]);
+    }
+
+    #[test]
+    fn test_weight_decay() {
+        // Without a gradient, L2 decay through Adam moves p by lr whatever
+        // its size, while AdamW shrinks it by lr * weight_decay * p.
+        let mut x = [Variable::new(100.0, None)];
+        let mut adam = Adam::new(0.1).weight_decay(0.5);
+        adam.step(x.iter_mut().collect());
+        assert!((x[0].value - 99.9).abs() < 1e-4);
+
+        let mut x = [Variable::new(100.0, None)];
+        let mut adamw = AdamW::new(0.1).weight_decay(0.5);
+        adamw.step(x.iter_mut().collect());
+        assert!((x[0].value - 95.0).abs() < 1e-4);
+
+        // Decayed SGD settles where 2 a (x - c) + weight_decay x vanishes.
+        let x = minimize(&mut Sgd::new(0.04).weight_decay(0.5), 500);
+        assert!((x[0] - 2.0 * A[0] * C[0] / (2.0 * A[0] + 0.5)).abs() < 1e-3);
+    }
+}
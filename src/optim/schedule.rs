@@ -0,0 +1,238 @@
+use super::Optimizer;
+use crate::backprop::variable::Variable;
+use std::f32::consts::PI;
+
+/*
+ * Learning-rate schedules, as factors of the optimizer's initial learning
+ * rate at every step, counting from 0. `Warmup` wraps another schedule
+ * and `Scheduled` wraps an optimizer, so they compose:
+ *   Scheduled::new(Adam::new(1e-3), Warmup::new(100, Cosine::new(1000, 0.0)))
+ */
+
+pub trait Schedule {
+    fn factor(&self, step: usize) -> f32;
+}
+
+// Multiplies the rate by `gamma` every `step_size` steps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepDecay {
+    pub step_size: usize,
+    pub gamma: f32,
+}
+
+impl StepDecay {
+    pub fn new(step_size: usize, gamma: f32) -> Self {
+        assert!(step_size > 0, "a step decay every 0 steps");
+        StepDecay { step_size, gamma }
+    }
+}
+
+impl Schedule for StepDecay {
+    fn factor(&self, step: usize) -> f32 {
+        self.gamma.powi((step / self.step_size) as i32)
+    }
+}
+
+// Multiplies the rate by `gamma` every step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exponential {
+    pub gamma: f32,
+}
+
+impl Exponential {
+    pub fn new(gamma: f32) -> Self {
+        Exponential { gamma }
+    }
+}
+
+impl Schedule for Exponential {
+    fn factor(&self, step: usize) -> f32 {
+        self.gamma.powi(step as i32)
+    }
+}
+
+/*
+ * Cosine annealing (Loshchilov and Hutter, 2017) from 1 down to `min`
+ * over `period` steps, staying at `min` afterwards:
+ *   min + (1 - min) (1 + cos(pi t / period)) / 2.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cosine {
+    pub period: usize,
+    pub min: f32,
+}
+
+impl Cosine {
+    pub fn new(period: usize, min: f32) -> Self {
+        assert!(period > 0, "a cosine annealing over 0 steps");
+        Cosine { period, min }
+    }
+}
+
+impl Schedule for Cosine {
+    fn factor(&self, step: usize) -> f32 {
+        let t = step.min(self.period) as f32 / self.period as f32;
+        self.min + (1.0 - self.min) * (1.0 + (PI * t).cos()) / 2.0
+    }
+}
+
+/*
+ * A linear ramp over the first `steps` steps, up to the first factor of
+ * `then`, which continues from its own step 0 afterwards.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Warmup<S: Schedule> {
+    pub steps: usize,
+    pub then: S,
+}
+
+impl<S: Schedule> Warmup<S> {
+    pub fn new(steps: usize, then: S) -> Self {
+        Warmup { steps, then }
+    }
+}
+
+impl<S: Schedule> Schedule for Warmup<S> {
+    fn factor(&self, step: usize) -> f32 {
+        if step < self.steps {
+            self.then.factor(0) * (step + 1) as f32 / self.steps as f32
+        } else {
+            self.then.factor(step - self.steps)
+        }
+    }
+}
+
+// An optimizer whose learning rate follows `schedule`, one step per `step`.
+#[derive(Debug, Clone)]
+pub struct Scheduled<O: Optimizer, S: Schedule> {
+    pub optimizer: O,
+    pub schedule: S,
+    base_lr: f32,
+    steps: usize,
+}
+
+impl<O: Optimizer, S: Schedule> Scheduled<O, S> {
+    pub fn new(optimizer: O, schedule: S) -> Self {
+        let base_lr = optimizer.learning_rate();
+        Scheduled {
+            optimizer,
+            schedule,
+            base_lr,
+            steps: 0,
+        }
+    }
+
+    // The steps taken so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // The rate the next step takes, the base rate scaled by the schedule.
+    pub fn scheduled_learning_rate(&self) -> f32 {
+        self.base_lr * self.schedule.factor(self.steps)
+    }
+}
+
+impl<O: Optimizer, S: Schedule> Optimizer for Scheduled<O, S> {
+    fn gradients_mut(&mut self) -> &mut Vec<f32> {
+        self.optimizer.gradients_mut()
+    }
+
+    fn step(&mut self, parameters: Vec<&mut Variable>) {
+        let lr = self.scheduled_learning_rate();
+        self.optimizer.set_learning_rate(lr);
+        self.optimizer.step(parameters);
+        self.steps += 1;
+    }
+
+    // The base rate the schedule scales, as for `set_learning_rate`.
+    fn learning_rate(&self) -> f32 {
+        self.base_lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.base_lr = lr;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Sgd;
+    use super::*;
+
+    fn trajectory(schedule: &impl Schedule, steps: usize) -> Vec<f32> {
+        (0..steps).map(|t| schedule.factor(t)).collect()
+    }
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-6, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_trajectories() {
+        assert_close(
+            &trajectory(&StepDecay::new(2, 0.5), 5),
+            &[1.0, 1.0, 0.5, 0.5, 0.25],
+        );
+        assert_close(&trajectory(&Exponential::new(0.9), 3), &[1.0, 0.9, 0.81]);
+        assert_close(
+            &trajectory(&Cosine::new(4, 0.2), 6),
+            &[
+                1.0,
+                0.6 + 0.4 * 0.5f32.sqrt(),
+                0.6,
+                0.6 - 0.4 * 0.5f32.sqrt(),
+                0.2,
+                0.2,
+            ],
+        );
+        assert_close(
+            &trajectory(&Warmup::new(4, Exponential::new(0.5)), 7),
+            &[0.25, 0.5, 0.75, 1.0, 1.0, 0.5, 0.25],
+        );
+    }
+
+    #[test]
+    fn test_scheduled_optimizer() {
+        // With a gradient of 1, every step moves the parameter by the rate.
+        let mut x = [Variable::new(0.0, None)];
+        let mut sgd = Scheduled::new(Sgd::new(0.4), StepDecay::new(1, 0.5));
+        let mut rates = Vec::new();
+        for _ in 0..3 {
+            sgd.zero_grad();
+            sgd.accumulate(&[Some(Variable::new(1.0, None))]);
+            let before = x[0].value;
+            sgd.step(x.iter_mut().collect());
+            rates.push(before - x[0].value);
+        }
+
+        assert_close(&rates, &[0.4, 0.2, 0.1]);
+        assert_eq!(sgd.steps(), 3);
+        assert!((sgd.optimizer.learning_rate() - 0.1).abs() < 1e-6);
+        assert!((sgd.scheduled_learning_rate() - 0.05).abs() < 1e-6);
+        assert_eq!(sgd.learning_rate(), 0.4);
+    }
+
+    #[test]
+    fn test_scheduled_learning_rate_round_trip() {
+        let mut sgd = Scheduled::new(Sgd::new(0.4), StepDecay::new(1, 0.5));
+        let mut x = [Variable::new(0.0, None)];
+        sgd.step(x.iter_mut().collect());
+
+        // Setting the rate it reports leaves the schedule where it was.
+        let lr = sgd.learning_rate();
+        sgd.set_learning_rate(lr);
+        assert_eq!(sgd.learning_rate(), 0.4);
+        assert!((sgd.scheduled_learning_rate() - 0.2).abs() < 1e-6);
+
+        // A new base rate scales the remaining steps.
+        sgd.set_learning_rate(0.8);
+        assert_eq!(sgd.learning_rate(), 0.8);
+        assert!((sgd.scheduled_learning_rate() - 0.4).abs() < 1e-6);
+        sgd.step(x.iter_mut().collect());
+        assert!((sgd.optimizer.learning_rate() - 0.4).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,35 @@
+/*
+ * Deterministic pseudo-random numbers, for initializing weights and for
+ * test fixtures. Both must be reproducible from a seed, so a small linear
+ * congruential generator is enough; nothing here needs statistical quality.
+ */
+
+// Values in [-1, 1), from a linear congruential generator.
+pub(crate) fn uniform(len: usize, seed: u64) -> Vec<f32> {
+    let mut state = seed
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform() {
+        let x = uniform(1000, 1);
+
+        assert_eq!(x, uniform(1000, 1));
+        assert_ne!(x, uniform(1000, 2));
+        assert!(x.iter().all(|x| (-1.0..1.0).contains(x)));
+        assert!(x.iter().sum::<f32>().abs() < 100.0);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::backprop::variable::Variable;
+use crate::forward::hyperdual::HyperDual;
+use crate::forward::taylor::Taylor;
+use crate::forward::value::Value;
+use crate::subgradient;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/*
+ * The operations shared by every number type in the crate, so that a
+ * function can be written once and evaluated in plain `f32`, in tangent
+ * mode with `Value`, to higher order with `Taylor` and `HyperDual` or on
+ * the tape with `Variable`.
+ */
+
+pub trait Scalar:
+    Clone
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    // A constant, i.e. a value that is not differentiated.
+    fn constant(value: f32) -> Self;
+
+    fn primal(&self) -> f32;
+
+    fn pow(self, exp: f32) -> Self;
+
+    fn sqrt(self) -> Self;
+
+    fn exp(self) -> Self;
+
+    fn ln(self) -> Self;
+
+    fn tanh(self) -> Self;
+
+    // 1 / (1 + e^-x), composed unless a type implements it as one operation.
+    fn sigmoid(self) -> Self {
+        Self::constant(1.0) / (Self::constant(1.0) + (-self).exp())
+    }
+
+    fn relu(self) -> Self;
+
+    fn abs(self) -> Self;
+
+    fn max(self, rhs: Self) -> Self;
+
+    fn min(self, rhs: Self) -> Self;
+
+    fn clamp(self, lo: f32, hi: f32) -> Self;
+
+    fn step(self) -> Self;
+}
+
+/*
+ * A function of several inputs that can be evaluated with any `Scalar`.
+ * Closures cannot be generic, hence the trait.
+ */
+pub trait Function {
+    fn eval<T: Scalar>(&self, x: &[T]) -> T;
+}
+
+// The same for functions with several outputs, e.g. residuals.
+pub trait VectorFunction {
+    fn eval<T: Scalar>(&self, x: &[T]) -> Vec<T>;
+}
+
+impl Scalar for f32 {
+    fn constant(value: f32) -> Self {
+        value
+    }
+
+    fn primal(&self) -> f32 {
+        *self
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        self.powf(exp)
+    }
+
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        f32::exp(self)
+    }
+
+    fn ln(self) -> Self {
+        f32::ln(self)
+    }
+
+    fn tanh(self) -> Self {
+        f32::tanh(self)
+    }
+
+    fn relu(self) -> Self {
+        f32::max(self, 0.0)
+    }
+
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        f32::max(self, rhs)
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        f32::min(self, rhs)
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        f32::clamp(self, lo, hi)
+    }
+
+    fn step(self) -> Self {
+        subgradient::slope(self, 0.0, 1.0)
+    }
+}
+
+impl Scalar for Value {
+    fn constant(value: f32) -> Self {
+        Value::passive(value)
+    }
+
+    fn primal(&self) -> f32 {
+        self.value
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        Value::pow(self, exp)
+    }
+
+    fn sqrt(self) -> Self {
+        Value::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        Value::exp(self)
+    }
+
+    fn ln(self) -> Self {
+        Value::ln(self)
+    }
+
+    fn tanh(self) -> Self {
+        Value::tanh(self)
+    }
+
+    fn sigmoid(self) -> Self {
+        Value::sigmoid(self)
+    }
+
+    fn relu(self) -> Self {
+        Value::relu(self)
+    }
+
+    fn abs(self) -> Self {
+        Value::abs(self)
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        Value::max(self, rhs)
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        Value::min(self, rhs)
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        Value::clamp(self, lo, hi)
+    }
+
+    fn step(self) -> Self {
+        Value::step(self)
+    }
+}
+
+impl Scalar for Variable {
+    fn constant(value: f32) -> Self {
+        Variable::new(value, None)
+    }
+
+    fn primal(&self) -> f32 {
+        self.value
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        Variable::pow(self, exp)
+    }
+
+    fn sqrt(self) -> Self {
+        Variable::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        Variable::exp(self)
+    }
+
+    fn ln(self) -> Self {
+        Variable::ln(self)
+    }
+
+    fn tanh(self) -> Self {
+        Variable::tanh(self)
+    }
+
+    fn sigmoid(self) -> Self {
+        Variable::sigmoid(self)
+    }
+
+    fn relu(self) -> Self {
+        Variable::relu(self)
+    }
+
+    fn abs(self) -> Self {
+        Variable::abs(self)
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        Variable::max(self, rhs)
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        Variable::min(self, rhs)
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        Variable::clamp(self, lo, hi)
+    }
+
+    fn step(self) -> Self {
+        Variable::step(self)
+    }
+}
+
+impl<const N: usize> Scalar for Taylor<N> {
+    fn constant(value: f32) -> Self {
+        Taylor::constant(value)
+    }
+
+    fn primal(&self) -> f32 {
+        self.value()
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        Taylor::pow(self, exp)
+    }
+
+    fn sqrt(self) -> Self {
+        Taylor::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        Taylor::exp(self)
+    }
+
+    fn ln(self) -> Self {
+        Taylor::ln(self)
+    }
+
+    fn tanh(self) -> Self {
+        Taylor::tanh(self)
+    }
+
+    fn relu(self) -> Self {
+        Taylor::relu(self)
+    }
+
+    fn abs(self) -> Self {
+        Taylor::abs(self)
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        Taylor::max(self, rhs)
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        Taylor::min(self, rhs)
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        Taylor::clamp(self, lo, hi)
+    }
+
+    fn step(self) -> Self {
+        Taylor::step(self)
+    }
+}
+
+impl Scalar for HyperDual {
+    fn constant(value: f32) -> Self {
+        HyperDual::passive(value)
+    }
+
+    fn primal(&self) -> f32 {
+        self.value
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        HyperDual::pow(self, exp)
+    }
+
+    fn sqrt(self) -> Self {
+        HyperDual::sqrt(self)
+    }
+
+    fn exp(self) -> Self {
+        HyperDual::exp(self)
+    }
+
+    fn ln(self) -> Self {
+        HyperDual::ln(self)
+    }
+
+    fn tanh(self) -> Self {
+        HyperDual::tanh(self)
+    }
+
+    fn relu(self) -> Self {
+        HyperDual::relu(self)
+    }
+
+    fn abs(self) -> Self {
+        HyperDual::abs(self)
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        HyperDual::max(self, rhs)
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        HyperDual::min(self, rhs)
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        HyperDual::clamp(self, lo, hi)
+    }
+
+    fn step(self) -> Self {
+        HyperDual::step(self)
+    }
+}
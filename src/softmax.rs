@@ -0,0 +1,50 @@
+/*
+ * Max-shifted softmax kernels on plain `f32` slices, the primal behind the
+ * softmax of variables, tensors and `Value`s.
+ */
+
+/*
+ * The maximum m of x and ln sum exp(x - m), so that no exponential
+ * overflows; logsumexp(x) is their sum.
+ */
+fn shifted_logsumexp(x: &[f32]) -> (f32, f32) {
+    let m = x.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    if m.is_infinite() {
+        return (m, 0.0);
+    }
+    (m, x.iter().map(|x| (x - m).exp()).sum::<f32>().ln())
+}
+
+// ln sum exp(x); -inf for no elements.
+pub fn logsumexp(x: &[f32]) -> f32 {
+    let (m, lse) = shifted_logsumexp(x);
+    m + lse
+}
+
+// x - logsumexp(x), subtracting the maximum first to keep small differences.
+pub fn log_softmax(x: &[f32]) -> Vec<f32> {
+    let (m, lse) = shifted_logsumexp(x);
+    x.iter().map(|x| (x - m) - lse).collect()
+}
+
+pub fn softmax(x: &[f32]) -> Vec<f32> {
+    log_softmax(x).into_iter().map(f32::exp).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logsumexp_and_softmax() {
+        let x = [1000.0, 1001.0, -f32::INFINITY];
+        let e = 1f32.exp();
+
+        assert!((logsumexp(&x) - (1001.0 + (1.0 + 1.0 / e).ln())).abs() < 1e-3);
+        assert!((log_softmax(&x)[0] + (1.0 + e).ln()).abs() < 1e-6);
+        let s = softmax(&x);
+        assert!((s[0] - 1.0 / (1.0 + e)).abs() < 1e-6 && (s[1] - e / (1.0 + e)).abs() < 1e-6);
+        assert_eq!(s[2], 0.0);
+        assert_eq!(logsumexp(&[]), f32::NEG_INFINITY);
+    }
+}
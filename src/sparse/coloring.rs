@@ -0,0 +1,233 @@
+use super::matrix::Pattern;
+
+/*
+ * Graph colorings that group the columns (or rows) of a sparse matrix so
+ * that all members of a group can be probed by one directional derivative.
+ *
+ * Two columns may share a color when no row has a nonzero in both; the
+ * sum of their columns then still isolates every entry. Colors are
+ * assigned greedily, visiting the densest columns first, which is cheap
+ * and usually close to the optimum for banded matrices.
+ */
+
+fn largest_first(pattern: &Pattern) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..pattern.nrows).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(pattern.rows[i].len()));
+    order
+}
+
+// forbidden[c] == v + 1 marks color c as taken for vertex v.
+fn forbid(forbidden: &mut Vec<usize>, color: usize, v: usize) {
+    if forbidden.len() <= color {
+        forbidden.resize(color + 1, 0);
+    }
+    forbidden[color] = v + 1;
+}
+
+fn smallest_free(forbidden: &[usize], marker: usize) -> usize {
+    (0..).find(|&c| forbidden.get(c) != Some(&marker)).unwrap()
+}
+
+// One color per column such that columns of the same color never share
+// a row, for compressed forward mode.
+pub fn color_columns(pattern: &Pattern) -> Vec<usize> {
+    let columns = pattern.transpose();
+    let mut colors = vec![usize::MAX; pattern.ncols];
+    let mut forbidden = Vec::new();
+
+    for j in largest_first(&columns) {
+        for &i in &columns.rows[j] {
+            for &k in &pattern.rows[i] {
+                if colors[k] != usize::MAX {
+                    forbid(&mut forbidden, colors[k], j);
+                }
+            }
+        }
+        colors[j] = smallest_free(&forbidden, j + 1);
+    }
+    colors
+}
+
+// One color per row such that rows of the same color never share a
+// column, for compressed reverse mode.
+pub fn color_rows(pattern: &Pattern) -> Vec<usize> {
+    color_columns(&pattern.transpose())
+}
+
+/*
+ * Star coloring of the adjacency graph of a symmetric pattern: a proper
+ * coloring in which every path on four vertices uses at least three
+ * colors. Then every off-diagonal entry (i, j) is the only entry of its
+ * color in row i or in row j, so a compressed Hessian H S can be read off
+ * directly. Greedy algorithm of Gebremedhin, Manne and Pothen (2005).
+ */
+pub fn star_color(pattern: &Pattern) -> Vec<usize> {
+    let n = pattern.nrows;
+    let neighbors = |v: usize| pattern.rows[v].iter().copied().filter(move |&w| w != v);
+    let mut colors: Vec<Option<usize>> = vec![None; n];
+    let mut forbidden = Vec::new();
+
+    for v in 0..n {
+        for w in neighbors(v) {
+            if let Some(color_w) = colors[w] {
+                forbid(&mut forbidden, color_w, v);
+            }
+            for x in neighbors(w).filter(|&x| x != v) {
+                let Some(color_x) = colors[x] else { continue };
+                match colors[w] {
+                    // v - w - x would become a two-colored path.
+                    None => forbid(&mut forbidden, color_x, v),
+                    // x - w already has a partner y of the color of w,
+                    // so v - w - x - y would be a two-colored path.
+                    Some(color_w) => {
+                        if neighbors(x).any(|y| y != w && colors[y] == Some(color_w)) {
+                            forbid(&mut forbidden, color_x, v);
+                        }
+                    }
+                }
+            }
+        }
+        colors[v] = Some(smallest_free(&forbidden, v + 1));
+    }
+    colors.into_iter().map(Option::unwrap).collect()
+}
+
+pub fn num_colors(colors: &[usize]) -> usize {
+    colors.iter().map(|&c| c + 1).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // No row holds two columns of the same color.
+    fn assert_valid(pattern: &Pattern, colors: &[usize]) {
+        for row in &pattern.rows {
+            for (a, &j) in row.iter().enumerate() {
+                for &k in &row[a + 1..] {
+                    assert_ne!(colors[j], colors[k], "columns {} and {}", j, k);
+                }
+            }
+        }
+    }
+
+    fn tridiagonal(n: usize) -> Pattern {
+        let rows = (0..n)
+            .map(|i| (i.saturating_sub(1)..(i + 2).min(n)).collect())
+            .collect();
+        Pattern::new(n, rows)
+    }
+
+    #[test]
+    fn test_tridiagonal_needs_three_colors() {
+        let pattern = tridiagonal(10);
+        let colors = color_columns(&pattern);
+
+        assert_valid(&pattern, &colors);
+        assert_eq!(num_colors(&colors), 3);
+    }
+
+    #[test]
+    fn test_diagonal_needs_one_color() {
+        let pattern = Pattern::new(4, (0..4).map(|i| vec![i]).collect());
+
+        assert_eq!(color_columns(&pattern), [0; 4]);
+    }
+
+    #[test]
+    fn test_dense_row_needs_all_colors() {
+        let pattern = Pattern::new(4, vec![vec![0, 1, 2, 3], vec![0]]);
+        let colors = color_columns(&pattern);
+
+        assert_valid(&pattern, &colors);
+        assert_eq!(num_colors(&colors), 4);
+
+        // The transpose has a single dense column: every row conflicts
+        // with the first one only.
+        let colors = color_rows(&pattern);
+        assert_valid(&pattern.transpose(), &colors);
+        assert_eq!(num_colors(&colors), 2);
+    }
+
+    // Proper, and no path on four vertices uses only two colors.
+    fn assert_star(pattern: &Pattern, colors: &[usize]) {
+        let neighbors = |v: usize| pattern.rows[v].iter().copied().filter(move |&w| w != v);
+        for a in 0..pattern.nrows {
+            for b in neighbors(a) {
+                assert_ne!(colors[a], colors[b]);
+                for c in neighbors(b).filter(|&c| c != a) {
+                    for d in neighbors(c).filter(|&d| d != b && d != a) {
+                        assert!(
+                            !(colors[a] == colors[c] && colors[b] == colors[d]),
+                            "two-colored path {} {} {} {}",
+                            a,
+                            b,
+                            c,
+                            d
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_star_color_path() {
+        let pattern = tridiagonal(10);
+        let colors = star_color(&pattern);
+
+        assert_star(&pattern, &colors);
+        assert_eq!(num_colors(&colors), 3);
+    }
+
+    #[test]
+    fn test_star_color_arrow() {
+        // Dense first row and column plus the diagonal: the hub gets its own
+        // color and all leaves can share one.
+        let rows = (0..6)
+            .map(|i| if i == 0 { (0..6).collect() } else { vec![0, i] })
+            .collect();
+        let pattern = Pattern::new(6, rows);
+        let colors = star_color(&pattern);
+
+        assert_star(&pattern, &colors);
+        assert_eq!(num_colors(&colors), 2);
+    }
+
+    #[test]
+    fn test_star_color_grid() {
+        // 5-point stencil on a 4x4 grid.
+        let index = |r: usize, c: usize| 4 * r + c;
+        let rows = (0..16)
+            .map(|v| {
+                let (r, c) = (v / 4, v % 4);
+                let mut row = vec![v];
+                if r > 0 {
+                    row.push(index(r - 1, c));
+                }
+                if r < 3 {
+                    row.push(index(r + 1, c));
+                }
+                if c > 0 {
+                    row.push(index(r, c - 1));
+                }
+                if c < 3 {
+                    row.push(index(r, c + 1));
+                }
+                row
+            })
+            .collect();
+        let pattern = Pattern::new(16, rows);
+        let colors = star_color(&pattern);
+
+        assert_star(&pattern, &colors);
+        assert!(num_colors(&colors) < 16);
+    }
+
+    #[test]
+    fn test_empty_columns() {
+        let pattern = Pattern::new(3, vec![vec![1]]);
+
+        assert_eq!(color_columns(&pattern), [0, 0, 0]);
+    }
+}
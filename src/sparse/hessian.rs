@@ -0,0 +1,506 @@
+use super::coloring::{num_colors, star_color};
+use super::matrix::{CsrMatrix, Pattern};
+use crate::backprop::grad::backpropagate;
+use crate::backprop::tape::scoped;
+use crate::backprop::variable::Variable;
+use crate::scalar::{Function, Scalar};
+use crate::subgradient;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/*
+ * Sparse Hessians of a scalar `Function`.
+ *
+ * The pattern is detected on a recorded tape: every variable carries the
+ * set of inputs it depends on, and every operation that is nonlinear in
+ * its inputs couples them. Operations are classified by their `op`:
+ *
+ *  linear      add, sub, split, modf, div_rem, and the piecewise-linear
+ *              relu, abs, max, min, clamp, step, max_with_index
+ *  bilinear    mul couples the inputs of one factor with the other's
+ *  div         a / b is linear in a: couples a with b and b with itself
+ *  otherwise   couples all of its inputs with each other
+ *
+ * Only the operations the result depends on are taken into account.
+ * The Hessian is then recovered from one Hessian-vector product per color
+ * of a star coloring of the pattern. Each product is computed in
+ * reverse-over-forward mode: `f` is evaluated on tangents whose parts are
+ * taped variables, and the directional derivative is differentiated.
+ */
+
+fn is_linear(op: &str) -> bool {
+    matches!(
+        op,
+        "add"
+            | "sub"
+            | "split"
+            | "modf"
+            | "div_rem"
+            | "relu"
+            | "abs"
+            | "max"
+            | "min"
+            | "clamp"
+            | "step"
+            | "max_with_index"
+    )
+}
+
+fn couple(pairs: &mut BTreeSet<(usize, usize)>, a: &BTreeSet<usize>, b: &BTreeSet<usize>) {
+    for &i in a {
+        for &j in b {
+            pairs.insert((i, j));
+            pairs.insert((j, i));
+        }
+    }
+}
+
+// Structural nonzeros of the Hessian of `f` at `x`.
+pub fn hessian_pattern<F: Function>(f: &F, x: &[f32]) -> Pattern {
+    let ((inputs, output), tape) = scoped(|| {
+        let inputs: Vec<Variable> = x.iter().map(|&x| Variable::new(x, None)).collect();
+        let output = f.eval(&inputs);
+        (inputs, output)
+    });
+
+    // Entries the output depends on.
+    let mut needed = HashSet::from([output.name.clone()]);
+    let mut live = vec![false; tape.entries.len()];
+    for (k, entry) in tape.entries.iter().enumerate().rev() {
+        if entry.outputs.iter().any(|v| needed.contains(&v.name)) {
+            live[k] = true;
+            needed.extend(entry.inputs.iter().map(|v| v.name.clone()));
+        }
+    }
+
+    let mut depends_on: HashMap<String, BTreeSet<usize>> = inputs
+        .iter()
+        .enumerate()
+        .map(|(j, input)| (input.name.clone(), BTreeSet::from([j])))
+        .collect();
+    let mut pairs = BTreeSet::new();
+    for (entry, _) in tape.entries.iter().zip(live).filter(|(_, live)| *live) {
+        let sets: Vec<BTreeSet<usize>> = entry
+            .inputs
+            .iter()
+            .map(|v| depends_on.get(&v.name).cloned().unwrap_or_default())
+            .collect();
+        let union: BTreeSet<usize> = sets.iter().flatten().copied().collect();
+
+        match entry.op {
+            op if is_linear(op) => {}
+            "mul" => couple(&mut pairs, &sets[0], &sets[1]),
+            "div" => {
+                couple(&mut pairs, &sets[0], &sets[1]);
+                couple(&mut pairs, &sets[1], &sets[1]);
+            }
+            _ => couple(&mut pairs, &union, &union),
+        }
+        for output in &entry.outputs {
+            depends_on.insert(output.name.clone(), union.clone());
+        }
+    }
+
+    let mut rows = vec![Vec::new(); x.len()];
+    for (i, j) in pairs {
+        rows[i].push(j);
+    }
+    Pattern::new(x.len(), rows)
+}
+
+// A forward-mode tangent whose parts are taped variables, so that the
+// directional derivative can itself be differentiated in reverse mode.
+#[derive(Clone)]
+struct Tangent {
+    value: Variable,
+    der: Variable,
+}
+
+impl Tangent {
+    // Applies a function given its value and derivative at `self.value`.
+    fn chain(self, value: Variable, dvalue_dself: Variable) -> Self {
+        Tangent {
+            value,
+            der: dvalue_dself * self.der,
+        }
+    }
+
+    // Same for piecewise-linear functions, whose slope is locally constant.
+    fn linear(self, value: Variable, slope: f32) -> Self {
+        Tangent {
+            value,
+            der: Variable::constant(slope) * self.der,
+        }
+    }
+}
+
+impl Add for Tangent {
+    type Output = Tangent;
+
+    fn add(self, rhs: Tangent) -> Self::Output {
+        Tangent {
+            value: self.value + rhs.value,
+            der: self.der + rhs.der,
+        }
+    }
+}
+
+impl Sub for Tangent {
+    type Output = Tangent;
+
+    fn sub(self, rhs: Tangent) -> Self::Output {
+        Tangent {
+            value: self.value - rhs.value,
+            der: self.der - rhs.der,
+        }
+    }
+}
+
+impl Mul for Tangent {
+    type Output = Tangent;
+
+    fn mul(self, rhs: Tangent) -> Self::Output {
+        Tangent {
+            value: self.value.clone() * rhs.value.clone(),
+            der: self.der * rhs.value + self.value * rhs.der,
+        }
+    }
+}
+
+impl Div for Tangent {
+    type Output = Tangent;
+
+    fn div(self, rhs: Tangent) -> Self::Output {
+        let value = self.value / rhs.value.clone();
+        Tangent {
+            der: (self.der - value.clone() * rhs.der) / rhs.value,
+            value,
+        }
+    }
+}
+
+impl Neg for Tangent {
+    type Output = Tangent;
+
+    fn neg(self) -> Self::Output {
+        Tangent {
+            value: -self.value,
+            der: -self.der,
+        }
+    }
+}
+
+impl Scalar for Tangent {
+    fn constant(value: f32) -> Self {
+        Tangent {
+            value: Variable::constant(value),
+            der: Variable::constant(0.0),
+        }
+    }
+
+    fn primal(&self) -> f32 {
+        self.value.value
+    }
+
+    fn pow(self, exp: f32) -> Self {
+        let x = self.value.clone();
+        let dvalue_dself = Variable::constant(exp) * x.clone().pow(exp - 1.0);
+        self.chain(x.pow(exp), dvalue_dself)
+    }
+
+    fn sqrt(self) -> Self {
+        let sqrt = self.value.clone().sqrt();
+        let dvalue_dself = Variable::constant(0.5) / sqrt.clone();
+        self.chain(sqrt, dvalue_dself)
+    }
+
+    fn exp(self) -> Self {
+        let exp = self.value.clone().exp();
+        self.chain(exp.clone(), exp)
+    }
+
+    fn ln(self) -> Self {
+        let x = self.value.clone();
+        let dvalue_dself = Variable::constant(1.0) / x.clone();
+        self.chain(x.ln(), dvalue_dself)
+    }
+
+    fn tanh(self) -> Self {
+        let tanh = self.value.clone().tanh();
+        let dvalue_dself = Variable::constant(1.0) - tanh.clone() * tanh.clone();
+        self.chain(tanh, dvalue_dself)
+    }
+
+    fn relu(self) -> Self {
+        let slope = subgradient::slope(self.primal(), 0.0, 1.0);
+        let value = self.value.clone().relu();
+        self.linear(value, slope)
+    }
+
+    fn abs(self) -> Self {
+        let slope = subgradient::slope(self.primal(), -1.0, 1.0);
+        let value = self.value.clone().abs();
+        self.linear(value, slope)
+    }
+
+    fn max(self, rhs: Self) -> Self {
+        let dresult_dself = subgradient::slope(self.primal() - rhs.primal(), 0.0, 1.0);
+        Tangent {
+            value: self.value.max(rhs.value),
+            der: Variable::constant(dresult_dself) * self.der
+                + Variable::constant(1.0 - dresult_dself) * rhs.der,
+        }
+    }
+
+    fn min(self, rhs: Self) -> Self {
+        let dresult_dself = subgradient::slope(self.primal() - rhs.primal(), 1.0, 0.0);
+        Tangent {
+            value: self.value.min(rhs.value),
+            der: Variable::constant(dresult_dself) * self.der
+                + Variable::constant(1.0 - dresult_dself) * rhs.der,
+        }
+    }
+
+    fn clamp(self, lo: f32, hi: f32) -> Self {
+        let slope = subgradient::clamp_slope(self.primal(), lo, hi);
+        let value = self.value.clone().clamp(lo, hi);
+        self.linear(value, slope)
+    }
+
+    fn step(self) -> Self {
+        let value = self.value.clone().step();
+        self.linear(value, 0.0)
+    }
+}
+
+// H v, the product of the Hessian of `f` at `x` with `v`.
+pub fn hessian_vector_product<F: Function>(f: &F, x: &[f32], v: &[f32]) -> Vec<f32> {
+    let ((inputs, output), tape) = scoped(|| {
+        let inputs: Vec<Variable> = x.iter().map(|&x| Variable::new(x, None)).collect();
+        let tangents: Vec<Tangent> = inputs
+            .iter()
+            .zip(v)
+            .map(|(x, &v)| Tangent {
+                value: x.clone(),
+                der: Variable::constant(v),
+            })
+            .collect();
+        (inputs, f.eval(&tangents))
+    });
+
+    let seeds = HashMap::from([(output.der.name.clone(), Variable::constant(1.0))]);
+    let adjoints = backpropagate(&tape.entries, seeds);
+    inputs
+        .iter()
+        .map(|input| adjoints.get(&input.name).map_or(0.0, |d| d.value))
+        .collect()
+}
+
+// Hessian of `f` at `x` restricted to `pattern`, from one Hessian-vector
+// product per star color.
+pub fn sparse_hessian<F: Function>(f: &F, x: &[f32], pattern: &Pattern) -> CsrMatrix {
+    let colors = star_color(pattern);
+    let n = x.len();
+
+    // compressed[c][i] = (H s_c)_i, with s_c the indicator of color c.
+    let compressed: Vec<Vec<f32>> = (0..num_colors(&colors))
+        .map(|color| {
+            let seed: Vec<f32> = colors
+                .iter()
+                .map(|&c| if c == color { 1.0 } else { 0.0 })
+                .collect();
+            hessian_vector_product(f, x, &seed)
+        })
+        .collect();
+
+    // Entry (i, j) is read from row i if j is the only neighbor of i with
+    // its color, and from row j otherwise; a star coloring ensures one of
+    // the two holds.
+    let alone_in_row = |i: usize, j: usize| {
+        pattern.rows[i]
+            .iter()
+            .all(|&k| k == j || colors[k] != colors[j])
+    };
+    let mut hessian = CsrMatrix::zeros(pattern);
+    for i in 0..n {
+        for &j in &pattern.rows[i] {
+            let value = if alone_in_row(i, j) {
+                compressed[colors[j]][i]
+            } else {
+                debug_assert!(alone_in_row(j, i));
+                compressed[colors[i]][j]
+            };
+            hessian.set(i, j, value);
+        }
+    }
+    hessian
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Extended Rosenbrock chain, sum (1 - x_i)^2 + 100 (x_{i+1} - x_i^2)^2,
+    // whose Hessian is tridiagonal.
+    struct Chain;
+
+    impl Function for Chain {
+        fn eval<T: Scalar>(&self, x: &[T]) -> T {
+            let mut total = T::constant(0.0);
+            for i in 0..x.len() - 1 {
+                let a = T::constant(1.0) - x[i].clone();
+                let b = x[i + 1].clone() - x[i].clone().pow(2.0);
+                total = total + a.pow(2.0) + T::constant(100.0) * b.pow(2.0);
+            }
+            total
+        }
+    }
+
+    fn chain_hessian(x: &[f32]) -> Vec<Vec<f32>> {
+        let n = x.len();
+        let mut dense = vec![vec![0.0; n]; n];
+        for i in 0..n - 1 {
+            dense[i][i] += 2.0 + 1200.0 * x[i] * x[i] - 400.0 * x[i + 1];
+            dense[i][i + 1] -= 400.0 * x[i];
+            dense[i + 1][i] -= 400.0 * x[i];
+            dense[i + 1][i + 1] += 200.0;
+        }
+        dense
+    }
+
+    fn assert_close(actual: &[Vec<f32>], expected: &[Vec<f32>]) {
+        for (row, expected_row) in actual.iter().zip(expected) {
+            for (a, e) in row.iter().zip(expected_row) {
+                assert!(
+                    (a - e).abs() <= 1e-4 * (1.0 + e.abs()),
+                    "{:?} != {:?}",
+                    row,
+                    expected_row
+                );
+            }
+        }
+    }
+
+    fn point(n: usize) -> Vec<f32> {
+        (0..n).map(|i| 0.5 + 0.1 * i as f32).collect()
+    }
+
+    #[test]
+    fn test_pattern_tridiagonal() {
+        let pattern = hessian_pattern(&Chain, &point(5));
+
+        assert_eq!(
+            pattern.rows,
+            [
+                vec![0, 1],
+                vec![0, 1, 2],
+                vec![1, 2, 3],
+                vec![2, 3, 4],
+                vec![3, 4]
+            ]
+        );
+    }
+
+    struct Mixed;
+
+    impl Function for Mixed {
+        fn eval<T: Scalar>(&self, x: &[T]) -> T {
+            // Linear in x0, bilinear in x1 x2, x3 / x4 and relu(x5) in a
+            // dead branch.
+            let _unused = x[5].clone().relu() * x[5].clone();
+            x[0].clone() + x[1].clone() * x[2].clone() + x[3].clone() / x[4].clone()
+        }
+    }
+
+    #[test]
+    fn test_pattern_classifies_operations() {
+        let pattern = hessian_pattern(&Mixed, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        assert_eq!(
+            pattern.rows,
+            [vec![], vec![2], vec![1], vec![4], vec![3, 4], vec![]]
+        );
+    }
+
+    #[test]
+    fn test_hessian_vector_product() {
+        let x = point(4);
+        let v = [1.0, -2.0, 0.5, 3.0];
+        let hv = hessian_vector_product(&Chain, &x, &v);
+
+        let dense = chain_hessian(&x);
+        let expected: Vec<f32> = dense
+            .iter()
+            .map(|row| row.iter().zip(v).map(|(h, v)| h * v).sum())
+            .collect();
+        assert_close(&[hv], &[expected]);
+    }
+
+    #[test]
+    fn test_hessian_vector_product_smooth() {
+        // The transcendental rules of the tangent against hyper-dual numbers.
+        struct Smooth;
+
+        impl Function for Smooth {
+            fn eval<T: Scalar>(&self, x: &[T]) -> T {
+                let a = x[0].clone().exp() * x[1].clone().ln();
+                a + (x[0].clone() * x[1].clone()).tanh() + x[1].clone().sigmoid()
+            }
+        }
+
+        let x = [0.3, 1.7];
+        let v = [1.0, -0.5];
+        let hv = hessian_vector_product(&Smooth, &x, &v);
+
+        let dense = crate::forward::hyperdual::hessian(&Smooth, &x);
+        let expected: Vec<f32> = dense
+            .iter()
+            .map(|row| row.iter().zip(v).map(|(h, v)| h * v).sum())
+            .collect();
+        assert_close(&[hv], &[expected]);
+    }
+
+    #[test]
+    fn test_sparse_hessian_tridiagonal() {
+        let x = point(12);
+        let pattern = hessian_pattern(&Chain, &x);
+        let hessian = sparse_hessian(&Chain, &x, &pattern);
+
+        assert_eq!(num_colors(&star_color(&pattern)), 3);
+        assert_eq!(hessian.nnz(), 3 * 12 - 2);
+        assert_close(&hessian.to_dense(), &chain_hessian(&x));
+    }
+
+    #[test]
+    fn test_sparse_hessian_arrow() {
+        // f = x0 * sum(x_i^2): dense first row and column plus the diagonal,
+        // recovered from two products.
+        struct Arrow;
+
+        impl Function for Arrow {
+            fn eval<T: Scalar>(&self, x: &[T]) -> T {
+                let squares = x[1..]
+                    .iter()
+                    .map(|xi| xi.clone() * xi.clone())
+                    .reduce(|a, b| a + b)
+                    .unwrap();
+                x[0].clone() * squares
+            }
+        }
+
+        let x = [2.0, 1.0, -1.0, 3.0];
+        let pattern = hessian_pattern(&Arrow, &x);
+        let hessian = sparse_hessian(&Arrow, &x, &pattern);
+
+        assert_eq!(num_colors(&star_color(&pattern)), 2);
+        assert_close(
+            &hessian.to_dense(),
+            &[
+                vec![0.0, 2.0, -2.0, 6.0],
+                vec![2.0, 4.0, 0.0, 0.0],
+                vec![-2.0, 0.0, 4.0, 0.0],
+                vec![6.0, 0.0, 0.0, 4.0],
+            ],
+        );
+    }
+}
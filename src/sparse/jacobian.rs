@@ -0,0 +1,272 @@
+use super::coloring::{color_columns, color_rows, num_colors};
+use super::matrix::{CsrMatrix, Pattern};
+use crate::backprop::grad::backpropagate;
+use crate::backprop::tape::{scoped, GradientTape};
+use crate::backprop::variable::Variable;
+use crate::forward::value::Value;
+use crate::scalar::VectorFunction;
+use std::collections::{BTreeSet, HashMap};
+
+/*
+ * Sparse Jacobians of a `VectorFunction`.
+ *
+ * The sparsity pattern is found by recording one evaluation on a scratch
+ * tape and propagating, for every variable, the set of inputs it depends
+ * on. The pattern reflects the branches taken at that point (e.g. by
+ * `max`), so it should be detected where the structure is representative
+ * and can then be reused for every evaluation.
+ *
+ * With the columns colored, forward mode needs one pass per column color;
+ * with the rows colored, reverse mode needs one sweep of the recorded tape
+ * per row color.
+ */
+
+// Evaluates `f` on a scratch tape; returns the inputs, outputs and tape.
+fn record<F: VectorFunction>(f: &F, x: &[f32]) -> (Vec<Variable>, Vec<Variable>, GradientTape) {
+    let ((inputs, outputs), tape) = scoped(|| {
+        let inputs: Vec<Variable> = x.iter().map(|&x| Variable::new(x, None)).collect();
+        let outputs = f.eval(&inputs);
+        (inputs, outputs)
+    });
+    (inputs, outputs, tape)
+}
+
+// Structural nonzeros of the Jacobian of `f` at `x`, one row per output.
+pub fn jacobian_pattern<F: VectorFunction>(f: &F, x: &[f32]) -> Pattern {
+    let (inputs, outputs, tape) = record(f, x);
+
+    let mut depends_on: HashMap<String, BTreeSet<usize>> = inputs
+        .iter()
+        .enumerate()
+        .map(|(j, input)| (input.name.clone(), BTreeSet::from([j])))
+        .collect();
+    for entry in &tape.entries {
+        let mut union = BTreeSet::new();
+        for input in &entry.inputs {
+            if let Some(set) = depends_on.get(&input.name) {
+                union.extend(set);
+            }
+        }
+        for output in &entry.outputs {
+            depends_on.insert(output.name.clone(), union.clone());
+        }
+    }
+
+    let rows = outputs
+        .iter()
+        .map(|output| {
+            depends_on
+                .get(&output.name)
+                .map_or(Vec::new(), |set| set.iter().copied().collect())
+        })
+        .collect();
+    Pattern::new(x.len(), rows)
+}
+
+// Jacobian of `f` at `x` by forward mode, one pass per column color of
+// `pattern`.
+pub fn forward_jacobian<F: VectorFunction>(f: &F, x: &[f32], pattern: &Pattern) -> CsrMatrix {
+    let colors = color_columns(pattern);
+    let mut jacobian = CsrMatrix::zeros(pattern);
+
+    for color in 0..num_colors(&colors) {
+        let seeded: Vec<Value> = x
+            .iter()
+            .zip(&colors)
+            .map(|(&x, &c)| Value::new(x, if c == color { 1.0 } else { 0.0 }))
+            .collect();
+        let outputs = f.eval(&seeded);
+
+        for (i, row) in pattern.rows.iter().enumerate() {
+            // At most one column of the row has this color.
+            if let Some(&j) = row.iter().find(|&&j| colors[j] == color) {
+                jacobian.set(i, j, outputs[i].der);
+            }
+        }
+    }
+    jacobian
+}
+
+// Jacobian of `f` at `x` by reverse mode: `f` is recorded once and its
+// tape swept once per row color of `pattern`.
+pub fn reverse_jacobian<F: VectorFunction>(f: &F, x: &[f32], pattern: &Pattern) -> CsrMatrix {
+    let colors = color_rows(pattern);
+    let mut jacobian = CsrMatrix::zeros(pattern);
+    let (inputs, outputs, tape) = record(f, x);
+
+    for color in 0..num_colors(&colors) {
+        let seeds = outputs
+            .iter()
+            .zip(&colors)
+            .filter(|(_, &c)| c == color)
+            .map(|(output, _)| (output.name.clone(), Variable::new(1.0, None)))
+            .collect();
+        let adjoints = backpropagate(&tape.entries, seeds);
+
+        for (i, row) in pattern.rows.iter().enumerate() {
+            if colors[i] != color {
+                continue;
+            }
+            // No other row of this color touches these columns.
+            for &j in row {
+                if let Some(adjoint) = adjoints.get(&inputs[j].name) {
+                    jacobian.set(i, j, adjoint.value);
+                }
+            }
+        }
+    }
+    jacobian
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+
+    // Residual of the discretized 1D problem -u'' + u^3 = 0 with zero
+    // boundary values; its Jacobian is tridiagonal.
+    struct Residual;
+
+    impl VectorFunction for Residual {
+        fn eval<T: Scalar>(&self, u: &[T]) -> Vec<T> {
+            let n = u.len();
+            let zero = T::constant(0.0);
+            (0..n)
+                .map(|i| {
+                    let left = if i > 0 {
+                        u[i - 1].clone()
+                    } else {
+                        zero.clone()
+                    };
+                    let right = if i + 1 < n {
+                        u[i + 1].clone()
+                    } else {
+                        zero.clone()
+                    };
+                    T::constant(2.0) * u[i].clone() - left - right + u[i].clone().pow(3.0)
+                })
+                .collect()
+        }
+    }
+
+    fn residual_jacobian(u: &[f32]) -> Vec<Vec<f32>> {
+        let n = u.len();
+        let mut dense = vec![vec![0.0; n]; n];
+        for i in 0..n {
+            dense[i][i] = 2.0 + 3.0 * u[i] * u[i];
+            if i > 0 {
+                dense[i][i - 1] = -1.0;
+            }
+            if i + 1 < n {
+                dense[i][i + 1] = -1.0;
+            }
+        }
+        dense
+    }
+
+    fn assert_close(actual: &CsrMatrix, expected: &[Vec<f32>]) {
+        for (row, expected_row) in actual.to_dense().iter().zip(expected) {
+            for (a, e) in row.iter().zip(expected_row) {
+                assert!((a - e).abs() < 1e-5, "{:?} != {:?}", row, expected_row);
+            }
+        }
+    }
+
+    // Mixes a dense row, a dense column, a constant and an identity output.
+    struct Arrow;
+
+    impl VectorFunction for Arrow {
+        fn eval<T: Scalar>(&self, x: &[T]) -> Vec<T> {
+            let total = x.iter().cloned().reduce(|a, b| a + b).unwrap();
+            let mut outputs = vec![total];
+            for xi in &x[1..] {
+                outputs.push(x[0].clone() * xi.clone());
+            }
+            outputs.push(T::constant(3.0));
+            outputs.push(x[2].clone());
+            outputs
+        }
+    }
+
+    #[test]
+    fn test_pattern_tridiagonal() {
+        let pattern = jacobian_pattern(&Residual, &[0.1; 5]);
+
+        assert_eq!(
+            pattern.rows,
+            [
+                vec![0, 1],
+                vec![0, 1, 2],
+                vec![1, 2, 3],
+                vec![2, 3, 4],
+                vec![3, 4]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pattern_arrow() {
+        let pattern = jacobian_pattern(&Arrow, &[1.0, 2.0, 3.0, 4.0]);
+
+        assert_eq!(
+            pattern.rows,
+            [
+                vec![0, 1, 2, 3],
+                vec![0, 1],
+                vec![0, 2],
+                vec![0, 3],
+                vec![],
+                vec![2]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forward_jacobian_tridiagonal() {
+        let u: Vec<f32> = (0..8).map(|i| 0.1 * i as f32).collect();
+        let pattern = jacobian_pattern(&Residual, &u);
+        let jacobian = forward_jacobian(&Residual, &u, &pattern);
+
+        assert_eq!(num_colors(&color_columns(&pattern)), 3);
+        assert_eq!(jacobian.nnz(), 3 * 8 - 2);
+        assert_close(&jacobian, &residual_jacobian(&u));
+    }
+
+    #[test]
+    fn test_reverse_jacobian_tridiagonal() {
+        let u: Vec<f32> = (0..8).map(|i| 0.1 * i as f32).collect();
+        let pattern = jacobian_pattern(&Residual, &u);
+        let jacobian = reverse_jacobian(&Residual, &u, &pattern);
+
+        assert_eq!(num_colors(&color_rows(&pattern)), 3);
+        assert_close(&jacobian, &residual_jacobian(&u));
+    }
+
+    #[test]
+    fn test_forward_and_reverse_agree() {
+        let x = [1.0, 2.0, 3.0, 4.0];
+        let pattern = jacobian_pattern(&Arrow, &x);
+        let expected = [
+            [1.0, 1.0, 1.0, 1.0],
+            [2.0, 1.0, 0.0, 0.0],
+            [3.0, 0.0, 1.0, 0.0],
+            [4.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+        ];
+
+        assert_eq!(forward_jacobian(&Arrow, &x, &pattern).to_dense(), expected);
+        assert_eq!(reverse_jacobian(&Arrow, &x, &pattern).to_dense(), expected);
+    }
+
+    #[test]
+    fn test_pattern_reused_at_other_points() {
+        let pattern = jacobian_pattern(&Residual, &[0.0; 6]);
+        let u = [0.5, -0.25, 1.0, 0.0, 2.0, -1.0];
+
+        assert_close(
+            &forward_jacobian(&Residual, &u, &pattern),
+            &residual_jacobian(&u),
+        );
+    }
+}
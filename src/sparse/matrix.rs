@@ -0,0 +1,159 @@
+/*
+ * Sparsity patterns and compressed sparse row matrices.
+ *
+ * A pattern stores the column indices of the structural nonzeros of each
+ * row, sorted. `CsrMatrix` pairs a pattern with one value per nonzero.
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub rows: Vec<Vec<usize>>,
+}
+
+impl Pattern {
+    pub fn new(ncols: usize, mut rows: Vec<Vec<usize>>) -> Self {
+        for row in rows.iter_mut() {
+            row.sort_unstable();
+            row.dedup();
+        }
+        Pattern {
+            nrows: rows.len(),
+            ncols,
+            rows,
+        }
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).sum()
+    }
+
+    pub fn contains(&self, i: usize, j: usize) -> bool {
+        self.rows[i].binary_search(&j).is_ok()
+    }
+
+    pub fn transpose(&self) -> Pattern {
+        let mut rows = vec![Vec::new(); self.ncols];
+        for (i, row) in self.rows.iter().enumerate() {
+            for &j in row {
+                rows[j].push(i);
+            }
+        }
+        Pattern {
+            nrows: self.ncols,
+            ncols: self.nrows,
+            rows,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrMatrix {
+    pub nrows: usize,
+    pub ncols: usize,
+    // Row i owns indices[indptr[i]..indptr[i + 1]] and the same range
+    // of values.
+    pub indptr: Vec<usize>,
+    pub indices: Vec<usize>,
+    pub values: Vec<f32>,
+}
+
+impl CsrMatrix {
+    // A matrix with the given pattern and all its nonzeros set to zero.
+    pub fn zeros(pattern: &Pattern) -> Self {
+        let mut indptr = vec![0];
+        let mut indices = Vec::with_capacity(pattern.nnz());
+        for row in &pattern.rows {
+            indices.extend_from_slice(row);
+            indptr.push(indices.len());
+        }
+        CsrMatrix {
+            nrows: pattern.nrows,
+            ncols: pattern.ncols,
+            indptr,
+            values: vec![0.0; indices.len()],
+            indices,
+        }
+    }
+
+    fn position(&self, i: usize, j: usize) -> Option<usize> {
+        let row = &self.indices[self.indptr[i]..self.indptr[i + 1]];
+        row.binary_search(&j).ok().map(|k| self.indptr[i] + k)
+    }
+
+    // Entry (i, j), zero outside the pattern.
+    pub fn get(&self, i: usize, j: usize) -> f32 {
+        self.position(i, j).map_or(0.0, |k| self.values[k])
+    }
+
+    // Sets a structural nonzero; panics outside the pattern.
+    pub fn set(&mut self, i: usize, j: usize, value: f32) {
+        let k = self
+            .position(i, j)
+            .unwrap_or_else(|| panic!("({}, {}) is not in the sparsity pattern", i, j));
+        self.values[k] = value;
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    // (row, column, value) triplets, i.e. the COO form, in row order.
+    pub fn triplets(&self) -> Vec<(usize, usize, f32)> {
+        (0..self.nrows)
+            .flat_map(|i| {
+                (self.indptr[i]..self.indptr[i + 1])
+                    .map(move |k| (i, self.indices[k], self.values[k]))
+            })
+            .collect()
+    }
+
+    pub fn to_dense(&self) -> Vec<Vec<f32>> {
+        let mut dense = vec![vec![0.0; self.ncols]; self.nrows];
+        for (i, j, value) in self.triplets() {
+            dense[i][j] = value;
+        }
+        dense
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern() {
+        let pattern = Pattern::new(3, vec![vec![2, 0, 2], vec![], vec![1]]);
+
+        assert_eq!(pattern.rows, [vec![0, 2], vec![], vec![1]]);
+        assert_eq!(pattern.nnz(), 3);
+        assert!(pattern.contains(0, 2));
+        assert!(!pattern.contains(1, 2));
+        assert_eq!(pattern.transpose().rows, [vec![0], vec![2], vec![0]]);
+    }
+
+    #[test]
+    fn test_csr_matrix() {
+        let pattern = Pattern::new(3, vec![vec![0, 2], vec![], vec![1]]);
+        let mut matrix = CsrMatrix::zeros(&pattern);
+        matrix.set(0, 2, 1.5);
+        matrix.set(2, 1, -2.0);
+
+        assert_eq!(matrix.indptr, [0, 2, 2, 3]);
+        assert_eq!(matrix.get(0, 2), 1.5);
+        assert_eq!(matrix.get(1, 1), 0.0);
+        assert_eq!(matrix.triplets(), [(0, 0, 0.0), (0, 2, 1.5), (2, 1, -2.0)]);
+        assert_eq!(
+            matrix.to_dense(),
+            [[0.0, 0.0, 1.5], [0.0, 0.0, 0.0], [0.0, -2.0, 0.0]]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not in the sparsity pattern")]
+    fn test_set_outside_pattern() {
+        let pattern = Pattern::new(2, vec![vec![0]]);
+        CsrMatrix::zeros(&pattern).set(0, 1, 1.0);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod coloring;
+pub mod hessian;
+pub mod jacobian;
+pub mod matrix;
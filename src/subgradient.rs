@@ -0,0 +1,152 @@
+use std::cell::Cell;
+
+/*
+ * Subgradient policy at non-differentiable points.
+ *
+ * relu, abs, max, min, clamp and step all have kinks where the derivative
+ * from the left differs from the derivative from the right. The policy
+ * decides which value is used exactly at the kink, the same way in forward
+ * and reverse mode:
+ *
+ *            relu   abs   max(a, b)      min(a, b)      clamp   step
+ *  Left      0      -1    (0, 1)         (1, 0)         lo: 0   value 0
+ *                                                       hi: 1
+ *  Right     1       1    (1, 0)         (0, 1)         lo: 1   value 1
+ *                                                       hi: 0
+ *  Midpoint  0.5     0    (0.5, 0.5)     (0.5, 0.5)     0.5     value 0.5
+ *
+ * "Left" and "right" refer to the argument of the kink, e.g. `a - b` for
+ * max(a, b). The step function has a zero derivative everywhere, so the
+ * policy only fixes its value at 0.
+ *
+ * The default is `Left`, which keeps relu(0) flat. The policy is a
+ * per-thread setting and is read when an operation is evaluated, so a
+ * recorded tape keeps the choice that was active during the forward pass.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Subgradient {
+    #[default]
+    Left,
+    Right,
+    // Average of the one-sided derivatives, zero for symmetric kinks.
+    Midpoint,
+}
+
+thread_local! {
+    static SUBGRADIENT: Cell<Subgradient> = const { Cell::new(Subgradient::Left) };
+}
+
+pub fn set_subgradient(policy: Subgradient) {
+    SUBGRADIENT.set(policy);
+}
+
+pub fn subgradient() -> Subgradient {
+    SUBGRADIENT.get()
+}
+
+// Runs `f` under `policy`, restoring the previous policy afterwards.
+pub fn with_subgradient<R>(policy: Subgradient, f: impl FnOnce() -> R) -> R {
+    // Puts the previous policy back even if `f` unwinds.
+    struct Restore(Subgradient);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SUBGRADIENT.set(self.0);
+        }
+    }
+
+    let _previous = Restore(SUBGRADIENT.replace(policy));
+    f()
+}
+
+// Slope of a piecewise-linear function with a kink at `x = 0`, going
+// from `left` to `right`.
+pub(crate) fn slope(x: f32, left: f32, right: f32) -> f32 {
+    if x < 0.0 {
+        left
+    } else if x > 0.0 {
+        right
+    } else {
+        match subgradient() {
+            Subgradient::Left => left,
+            Subgradient::Right => right,
+            Subgradient::Midpoint => 0.5 * (left + right),
+        }
+    }
+}
+
+// d clamp(x, lo, hi) / dx.
+pub(crate) fn clamp_slope(x: f32, lo: f32, hi: f32) -> f32 {
+    if lo == hi {
+        return 0.0;
+    }
+    slope(x - lo, 0.0, 1.0) * slope(x - hi, 1.0, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy() {
+        assert_eq!(subgradient(), Subgradient::Left);
+        assert_eq!(slope(0.0, 0.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_slope_away_from_kink() {
+        for policy in [Subgradient::Left, Subgradient::Right, Subgradient::Midpoint] {
+            with_subgradient(policy, || {
+                assert_eq!(slope(-1.0, -1.0, 1.0), -1.0);
+                assert_eq!(slope(1.0, -1.0, 1.0), 1.0);
+            });
+        }
+    }
+
+    #[test]
+    fn test_slope_at_kink() {
+        assert_eq!(
+            with_subgradient(Subgradient::Left, || slope(0.0, -1.0, 1.0)),
+            -1.0
+        );
+        assert_eq!(
+            with_subgradient(Subgradient::Right, || slope(0.0, -1.0, 1.0)),
+            1.0
+        );
+        assert_eq!(
+            with_subgradient(Subgradient::Midpoint, || slope(0.0, -1.0, 1.0)),
+            0.0
+        );
+        assert_eq!(subgradient(), Subgradient::Left);
+    }
+
+    #[test]
+    fn test_policy_restored_after_panic() {
+        let result = std::panic::catch_unwind(|| {
+            with_subgradient(Subgradient::Right, || panic!("inside"));
+        });
+
+        assert!(result.is_err());
+        assert_eq!(subgradient(), Subgradient::Left);
+    }
+
+    #[test]
+    fn test_clamp_slope() {
+        assert_eq!(clamp_slope(-1.0, 0.0, 1.0), 0.0);
+        assert_eq!(clamp_slope(0.5, 0.0, 1.0), 1.0);
+        assert_eq!(clamp_slope(2.0, 0.0, 1.0), 0.0);
+        assert_eq!(clamp_slope(0.0, 0.0, 1.0), 0.0);
+        assert_eq!(clamp_slope(1.0, 0.0, 1.0), 1.0);
+        assert_eq!(clamp_slope(0.5, 0.5, 0.5), 0.0);
+
+        with_subgradient(Subgradient::Right, || {
+            assert_eq!(clamp_slope(0.0, 0.0, 1.0), 1.0);
+            assert_eq!(clamp_slope(1.0, 0.0, 1.0), 0.0);
+        });
+        with_subgradient(Subgradient::Midpoint, || {
+            assert_eq!(clamp_slope(0.0, 0.0, 1.0), 0.5);
+            assert_eq!(clamp_slope(1.0, 0.0, 1.0), 0.5);
+        });
+    }
+}